@@ -0,0 +1,416 @@
+//! The bench mode: announce routes for simulated peers as fast as the
+//! monitoring station takes them, or at a given rate, and report the
+//! throughput.
+
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr},
+    str::FromStr,
+    sync::Arc,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+use bytes::Bytes;
+use routecore::{addr::Prefix, asn::Asn};
+
+use routes::bmp::builder::{Initiation, PeerUp, RouteMonitoring};
+use routes::bmp::encode::{mk_termination_msg, Announcements, PerPeerHeader};
+use routes::bmp::table::{PathAttributes, Table, TableConfig};
+
+use crate::{check_warnings, Output, SharedOutput};
+
+/// The ASN of the first simulated peer, from the private use range.
+const FIRST_PEER_ASN: u32 = 64512;
+
+/// The ASN that the AS path of the first set of path attributes ends in,
+/// each further set ending in the next ASN.
+const FIRST_SET_ASN: u32 = 4_200_000_000;
+
+/// The first prefix announced, from which the /24s of the table follow.
+const FIRST_PREFIX: Ipv4Addr = Ipv4Addr::new(1, 0, 0, 0);
+
+/// The number of /24s from the first prefix to the end of the IPv4 address
+/// space, and thus the most prefixes a peer can announce.
+const MAX_PREFIXES: u32 =
+    (1 << 24) - (u32::from_be_bytes(FIRST_PREFIX.octets()) >> 8);
+
+/// An empty MP_UNREACH_NLRI attribute for IPv6 unicast, which turns an
+/// otherwise empty UPDATE into the IPv6 unicast End-of-RIB marker.
+pub const IPV6_END_OF_RIB: &[u8] = &[0x80, 15, 3, 0, 2, 1];
+
+/// Which routes the simulated peers announce.
+#[derive(Clone, Copy, Debug)]
+pub enum TableKind {
+    /// Consecutive IPv4 /24s, divided over a number of sets of path
+    /// attributes.
+    Sequential,
+
+    /// A table that looks like the Internet table, see
+    /// [`routes::bmp::table`].
+    Synthetic,
+}
+
+impl FromStr for TableKind {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "sequential" => Ok(TableKind::Sequential),
+            "synthetic" => Ok(TableKind::Synthetic),
+            _ => Err(anyhow!("Expected sequential or synthetic")),
+        }
+    }
+}
+
+/// How fast to send Route Monitoring messages.
+#[derive(Clone, Copy, Debug)]
+pub enum Rate {
+    /// As fast as the monitoring stations accept them.
+    Max,
+
+    /// At most this many messages per second.
+    PerSecond(f64),
+}
+
+impl FromStr for Rate {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "max" {
+            return Ok(Rate::Max);
+        }
+        match s.parse::<f64>() {
+            Ok(rate) if rate.is_finite() && rate > 0.0 => {
+                Ok(Rate::PerSecond(rate))
+            }
+            _ => Err(anyhow!("Expected a positive number or max")),
+        }
+    }
+}
+
+/// What to simulate.
+pub struct Options {
+    pub peers: u32,
+    pub table: TableKind,
+
+    /// The number of IPv4 prefixes each peer announces.
+    pub prefixes: u32,
+
+    /// The number of IPv6 prefixes each peer announces, for the synthetic
+    /// table only.
+    pub ipv6_prefixes: u32,
+
+    /// The number of distinct sets of path attributes per peer, for the
+    /// sequential table only.
+    pub attribute_sets: u32,
+
+    /// The seed of the synthetic table.
+    pub seed: u64,
+
+    /// The number of prefixes per Route Monitoring message.
+    pub batch: u32,
+
+    pub rate: Rate,
+    pub strict: bool,
+}
+
+/// Counts what was sent.
+#[derive(Default)]
+struct Totals {
+    messages: u64,
+    bytes: u64,
+    routes: u64,
+}
+
+/// Sends an Initiation message, a Peer Up Notification for each peer, their
+/// routes interleaved and an End-of-RIB marker for each, then a
+/// Termination message, and reports the throughput of the routes and
+/// End-of-RIB markers.
+///
+/// Each peer announces the same table: either consecutive /24s, split into
+/// contiguous ranges with their own AS path and community, or a synthetic
+/// Internet table.
+pub fn run(
+    output: SharedOutput,
+    options: Options,
+) -> Result<(), anyhow::Error> {
+    if options.prefixes > MAX_PREFIXES {
+        return Err(anyhow!("At most {MAX_PREFIXES} prefixes per peer"));
+    }
+    if options.peers > 1 << 24 {
+        return Err(anyhow!("At most 16777216 peers"));
+    }
+    // Generated up front so that it isn't measured.
+    let chunks = match options.table {
+        TableKind::Sequential => None,
+        TableKind::Synthetic => {
+            let table = Table::generate(&TableConfig {
+                ipv4_prefixes: options.prefixes as usize,
+                ipv6_prefixes: options.ipv6_prefixes as usize,
+                seed: options.seed,
+            });
+            Some(synthetic_chunks(&table, options.batch))
+        }
+    };
+
+    let mut output = output.lock().unwrap();
+    output.send(
+        Initiation::new()
+            .sys_name("bmp-speaker")
+            .sys_descr("bmp-speaker bench")
+            .build()?,
+    )?;
+
+    let peers: Vec<Peer> = (0..options.peers)
+        .map(|idx| Peer::new(idx, &options))
+        .collect();
+    for peer in &peers {
+        let res = PeerUp::new(peer.per_peer_header.clone()).build();
+        output.send(check_warnings(res, options.strict)?)?;
+    }
+
+    // Only the routes and End-of-RIB markers are measured.
+    let mut totals = Totals::default();
+    let start = Instant::now();
+    let write_time_before = write_time(&output);
+    let mut pacer = Pacer::new(options.rate);
+    let batch = options.batch.max(1);
+    match &chunks {
+        None => {
+            let mut offset = 0;
+            while offset < options.prefixes {
+                let len = batch.min(options.prefixes - offset);
+                for peer in &peers {
+                    for announcements in peer.announcements(offset, len)? {
+                        announce(
+                            &mut output,
+                            &mut totals,
+                            &mut pacer,
+                            peer,
+                            announcements,
+                            options.strict,
+                        )?;
+                    }
+                }
+                totals.routes += u64::from(len) * u64::from(options.peers);
+                offset += len;
+            }
+        }
+        Some(chunks) => {
+            for (attributes, prefixes) in chunks {
+                for peer in &peers {
+                    let next_hop = if prefixes[0].is_v4() {
+                        peer.per_peer_header.peer_address
+                    } else {
+                        peer.ipv6_next_hop
+                    };
+                    announce(
+                        &mut output,
+                        &mut totals,
+                        &mut pacer,
+                        peer,
+                        attributes.announcements(
+                            peer.per_peer_header.peer_as,
+                            next_hop,
+                            prefixes.clone(),
+                        ),
+                        options.strict,
+                    )?;
+                }
+                totals.routes +=
+                    prefixes.len() as u64 * u64::from(options.peers);
+            }
+        }
+    }
+    for peer in &peers {
+        let res = RouteMonitoring::new(peer.per_peer_header.clone()).build();
+        send(
+            &mut output,
+            &mut totals,
+            check_warnings(res, options.strict)?,
+        )?;
+        if options.ipv6_prefixes > 0 && chunks.is_some() {
+            let res = RouteMonitoring::new(peer.per_peer_header.clone())
+                .extra_path_attributes(IPV6_END_OF_RIB)
+                .build();
+            send(
+                &mut output,
+                &mut totals,
+                check_warnings(res, options.strict)?,
+            )?;
+        }
+    }
+    let elapsed = start.elapsed();
+    let blocked = write_time(&output) - write_time_before;
+    output.send(mk_termination_msg()?)?;
+
+    let secs = elapsed.as_secs_f64().max(f64::EPSILON);
+    eprintln!(
+        "Sent {} messages ({} bytes) with {} routes in {:.3}s: {:.0} \
+         messages/s, {:.0} bytes/s, {:.0} routes/s",
+        totals.messages,
+        totals.bytes,
+        totals.routes,
+        secs,
+        totals.messages as f64 / secs,
+        totals.bytes as f64 / secs,
+        totals.routes as f64 / secs,
+    );
+    eprintln!(
+        "Blocked on writes for {:.3}s ({:.1}% of the time)",
+        blocked.as_secs_f64(),
+        100.0 * blocked.as_secs_f64() / secs
+    );
+    Ok(())
+}
+
+/// Spaces out messages to send them at a given rate.
+struct Pacer {
+    rate: Rate,
+    start: Instant,
+    sent: u64,
+}
+
+impl Pacer {
+    fn new(rate: Rate) -> Self {
+        Pacer {
+            rate,
+            start: Instant::now(),
+            sent: 0,
+        }
+    }
+
+    /// Sleeps until it is time to send the next message.
+    fn wait(&mut self) {
+        if let Rate::PerSecond(rate) = self.rate {
+            let due =
+                self.start + Duration::from_secs_f64(self.sent as f64 / rate);
+            let now = Instant::now();
+            if due > now {
+                thread::sleep(due - now);
+            }
+        }
+        self.sent += 1;
+    }
+}
+
+/// Returns the routes of a synthetic table in chunks of at most a batch of
+/// prefixes with the same path attributes.
+pub fn synthetic_chunks(
+    table: &Table,
+    batch: u32,
+) -> Vec<(Arc<PathAttributes>, Vec<Prefix>)> {
+    let mut out = vec![];
+    for (attributes, prefixes) in table.by_attributes() {
+        for chunk in prefixes.chunks(batch.max(1) as usize) {
+            out.push((attributes.clone(), chunk.to_vec()));
+        }
+    }
+    out
+}
+
+/// Sends a Route Monitoring message with announcements.
+fn announce(
+    output: &mut Output,
+    totals: &mut Totals,
+    pacer: &mut Pacer,
+    peer: &Peer,
+    announcements: Announcements,
+    strict: bool,
+) -> Result<(), anyhow::Error> {
+    let res = RouteMonitoring::new(peer.per_peer_header.clone())
+        .announce(announcements)
+        .build();
+    let msg = check_warnings(res, strict)?;
+    pacer.wait();
+    send(output, totals, msg)
+}
+
+fn send(
+    output: &mut Output,
+    totals: &mut Totals,
+    msg: Bytes,
+) -> Result<(), anyhow::Error> {
+    totals.messages += 1;
+    totals.bytes += msg.len() as u64;
+    output.send(msg)
+}
+
+/// Returns the time spent writing to all stations.
+fn write_time(output: &Output) -> Duration {
+    output
+        .stations
+        .iter()
+        .map(|station| station.write_time())
+        .sum()
+}
+
+/// Returns the Per-Peer Header of a simulated peer, with an IPv4 address
+/// from 10.0.0.1 on, and the next hop of its IPv6 routes.
+pub fn simulated_peer(idx: u32) -> (PerPeerHeader, IpAddr) {
+    let address = IpAddr::from(Ipv4Addr::from(
+        u32::from(Ipv4Addr::new(10, 0, 0, 1)) + idx,
+    ));
+    let asn = Asn::from_u32(FIRST_PEER_ASN + idx);
+    let ipv6_next_hop = Ipv6Addr::from(0xfd00 << 112 | u128::from(idx + 1));
+    (PerPeerHeader::new(address, asn), ipv6_next_hop.into())
+}
+
+/// A simulated peer.
+struct Peer {
+    per_peer_header: PerPeerHeader,
+
+    /// The next hop of IPv6 routes, as the peer address is IPv4.
+    ipv6_next_hop: IpAddr,
+
+    /// The number of prefixes per set of path attributes.
+    per_set: u32,
+}
+
+impl Peer {
+    fn new(idx: u32, options: &Options) -> Self {
+        let (per_peer_header, ipv6_next_hop) = simulated_peer(idx);
+        let sets = options.attribute_sets.clamp(1, options.prefixes.max(1));
+        Peer {
+            per_peer_header,
+            ipv6_next_hop,
+            per_set: options.prefixes.div_ceil(sets),
+        }
+    }
+
+    /// Returns the announcements for `len` prefixes of the table starting
+    /// at `offset`, one for each set of path attributes they have.
+    fn announcements(
+        &self,
+        offset: u32,
+        len: u32,
+    ) -> Result<Vec<Announcements>, anyhow::Error> {
+        let mut out = vec![];
+        let mut idx = offset;
+        while idx < offset + len {
+            let set = idx / self.per_set;
+            let end = ((set + 1) * self.per_set).min(offset + len);
+            let prefixes: Vec<String> = (idx..end)
+                .map(|i| {
+                    let addr = u32::from(FIRST_PREFIX) + (i << 8);
+                    format!("{}/24", Ipv4Addr::from(addr))
+                })
+                .collect();
+            out.push(
+                format!(
+                    "i [{},{}] {} {}:{} {}",
+                    self.per_peer_header.peer_as.into_u32(),
+                    FIRST_SET_ASN + set,
+                    self.per_peer_header.peer_address,
+                    FIRST_PEER_ASN,
+                    set % 65536,
+                    prefixes.join(",")
+                )
+                .parse()?,
+            );
+            idx = end;
+        }
+        Ok(out)
+    }
+}
@@ -0,0 +1,174 @@
+//! The churn mode: announce a synthetic table for simulated peers, then
+//! keep changing their routes for a while.
+
+use std::{
+    collections::BTreeMap,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::anyhow;
+
+use routes::bmp::builder::{Initiation, PeerUp, RouteMonitoring};
+use routes::bmp::churn::{Churn, ChurnConfig, EventKind};
+use routes::bmp::encode::mk_termination_msg;
+use routes::bmp::table::{Table, TableConfig};
+
+use crate::bench::{simulated_peer, synthetic_chunks, IPV6_END_OF_RIB};
+use crate::replay::Speed;
+use crate::script::parse_duration;
+use crate::{check_warnings, SharedOutput};
+
+/// The number of prefixes per Route Monitoring message of the initial
+/// table.
+const TABLE_BATCH: u32 = 100;
+
+/// What to simulate.
+pub struct Options {
+    pub peers: u32,
+    pub table: TableConfig,
+    pub churn: ChurnConfig,
+
+    /// How long to produce churn for, in simulated time.
+    pub duration: Duration,
+
+    pub speed: Speed,
+    pub strict: bool,
+}
+
+/// Sends an Initiation message, a Peer Up Notification for each peer, the
+/// table of each peer with End-of-RIB markers, the churn up to the
+/// duration and a Termination message, then reports how many events of
+/// each kind were sent.
+pub fn run(
+    output: SharedOutput,
+    options: Options,
+) -> Result<(), anyhow::Error> {
+    if options.peers > 1 << 24 {
+        return Err(anyhow!("At most 16777216 peers"));
+    }
+    let table = Table::generate(&options.table);
+    let peers: Vec<_> = (0..options.peers).map(simulated_peer).collect();
+
+    let mut output = output.lock().unwrap();
+    output.send(
+        Initiation::new()
+            .sys_name("bmp-speaker")
+            .sys_descr("bmp-speaker churn")
+            .build()?,
+    )?;
+    for (per_peer_header, _) in &peers {
+        let res = PeerUp::new(per_peer_header.clone()).build();
+        output.send(check_warnings(res, options.strict)?)?;
+    }
+    let chunks = synthetic_chunks(&table, TABLE_BATCH);
+    for (per_peer_header, ipv6_next_hop) in &peers {
+        for (attributes, prefixes) in &chunks {
+            let next_hop = if prefixes[0].is_v4() {
+                per_peer_header.peer_address
+            } else {
+                *ipv6_next_hop
+            };
+            let res = RouteMonitoring::new(per_peer_header.clone())
+                .announce(attributes.announcements(
+                    per_peer_header.peer_as,
+                    next_hop,
+                    prefixes.clone(),
+                ))
+                .build();
+            output.send(check_warnings(res, options.strict)?)?;
+        }
+        let res = RouteMonitoring::new(per_peer_header.clone()).build();
+        output.send(check_warnings(res, options.strict)?)?;
+        if options.table.ipv6_prefixes > 0 {
+            let res = RouteMonitoring::new(per_peer_header.clone())
+                .extra_path_attributes(IPV6_END_OF_RIB)
+                .build();
+            output.send(check_warnings(res, options.strict)?)?;
+        }
+    }
+
+    let mut counts: BTreeMap<EventKind, u64> = BTreeMap::new();
+    let mut messages = 0;
+    let start = Instant::now();
+    let churn = Churn::new(&table, peers.len(), options.churn);
+    for event in churn.take_while(|event| event.at < options.duration) {
+        if let Speed::Factor(factor) = options.speed {
+            let due = start + event.at.div_f64(factor);
+            let now = Instant::now();
+            if due > now {
+                thread::sleep(due - now);
+            }
+        }
+        let (per_peer_header, ipv6_next_hop) = &peers[event.peer];
+        let res = event.messages(
+            per_peer_header,
+            per_peer_header.peer_address,
+            *ipv6_next_hop,
+        );
+        for msg in check_warnings(res, options.strict)? {
+            output.send(msg)?;
+            messages += 1;
+        }
+        *counts.entry(event.kind).or_default() += 1;
+    }
+    output.send(mk_termination_msg()?)?;
+
+    let kinds: Vec<String> = counts
+        .iter()
+        .map(|(kind, count)| format!("{count} {kind}"))
+        .collect();
+    eprintln!(
+        "Sent {} churn events in {} messages over {:.3}s: {}",
+        counts.values().sum::<u64>(),
+        messages,
+        start.elapsed().as_secs_f64(),
+        if kinds.is_empty() {
+            "none".to_string()
+        } else {
+            kinds.join(", ")
+        }
+    );
+    Ok(())
+}
+
+/// Parses a number of events per second, which is either zero or large
+/// enough for the mean time between events to fit a `Duration`.
+pub fn parse_rate(s: &str) -> Result<f64, anyhow::Error> {
+    match s.parse::<f64>() {
+        Ok(rate) if rate.is_finite() && rate >= 0.0 => {
+            if rate > 0.0 && Duration::try_from_secs_f64(1.0 / rate).is_err()
+            {
+                Err(anyhow!("Rate {s} is too small"))
+            } else {
+                Ok(rate)
+            }
+        }
+        _ => Err(anyhow!("Expected a number of events per second")),
+    }
+}
+
+/// Parses a duration above zero or `none`.
+pub fn parse_burst_interval(
+    s: &str,
+) -> Result<Option<Duration>, anyhow::Error> {
+    if s == "none" {
+        return Ok(None);
+    }
+    match parse_duration(s)? {
+        interval if interval.is_zero() => {
+            Err(anyhow!("Expected a burst interval above zero or none"))
+        }
+        interval => Ok(Some(interval)),
+    }
+}
+
+/// Parses a percentage into a share from 0 to 1.
+pub fn parse_percentage(s: &str) -> Result<f64, anyhow::Error> {
+    match s.trim_end_matches('%').parse::<f64>() {
+        Ok(percent) if (0.0..=100.0).contains(&percent) => {
+            Ok(percent / 100.0)
+        }
+        _ => Err(anyhow!("Expected a percentage from 0 to 100")),
+    }
+}
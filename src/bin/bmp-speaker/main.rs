@@ -0,0 +1,656 @@
+use std::{
+    fs::File,
+    io,
+    net::{IpAddr, SocketAddr},
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bytes::Bytes;
+use const_format::formatcp;
+
+use routes::bmp::churn::{ChurnConfig, EventMix};
+use routes::bmp::clock::{Clock, SystemClock};
+use routes::bmp::describe::{hexdump, tree};
+use routes::bmp::encode::{strict, EncodeError, Warning};
+use routes::bmp::mutate::{mutate, Mutation};
+use routes::bmp::record::{RecordFormat, Recorder};
+use routes::bmp::session::Session;
+use routes::bmp::table::TableConfig;
+
+mod bench;
+mod churn;
+mod repl;
+mod replay;
+mod script;
+mod sink;
+mod station;
+
+use sink::{ListenSink, Sink, TcpSink};
+use station::Station;
+
+const DEF_BMP_PORT: u16 = 11019;
+
+type SharedOutput = Arc<Mutex<Output>>;
+
+/// Where messages are sent: one or more monitoring stations, or a file or
+/// standard output in offline mode, or nowhere in a dry run.
+struct Output {
+    stations: Vec<Station>,
+
+    /// Mutations to apply to the next message only.
+    mutations: Vec<Mutation>,
+
+    /// The capture file that sent messages are also written to.
+    recorder: Option<Recorder<File>>,
+
+    /// What the stations know from the messages sent so far.
+    session: Session,
+
+    /// Whether to warn about messages sent out of order, e.g. Route
+    /// Monitoring for a peer that is not up.
+    check_sequence: bool,
+
+    /// Whether to print each message sent as decoded by routecore.
+    verbose: bool,
+
+    /// Whether to print each message sent as an annotated hex dump.
+    hexdump: bool,
+
+    /// Whether to only print the messages, to standard output, instead of
+    /// sending them.
+    dry_run: bool,
+}
+
+impl Output {
+    fn new(stations: Vec<Station>, recorder: Option<Recorder<File>>) -> Self {
+        Output {
+            stations,
+            mutations: vec![],
+            recorder,
+            session: Session::new(),
+            check_sequence: true,
+            verbose: false,
+            hexdump: false,
+            dry_run: false,
+        }
+    }
+
+    /// Sends a message to all stations, applying and then forgetting any
+    /// pending mutations.
+    ///
+    /// A single station that was lost is waited for, while with several
+    /// stations the message is sent to those that can be reached. Either
+    /// way, a station that is reconnected to is brought up to date with
+    /// the session state first. It is an error if the message reaches no
+    /// station at all.
+    ///
+    /// Messages that are out of order for the session are warned about but
+    /// sent anyway, as testing a station with them may be the point.
+    fn send(&mut self, bytes: Bytes) -> Result<(), anyhow::Error> {
+        if self.check_sequence {
+            for warning in self.session.check(&bytes) {
+                eprintln!("Warning: {}", warning);
+            }
+        }
+        let mutated = if self.mutations.is_empty() {
+            bytes.clone()
+        } else {
+            mutate(&bytes, &std::mem::take(&mut self.mutations))?
+        };
+        self.print(&mutated);
+        if !self.dry_run {
+            let wait = self.stations.len() == 1;
+            let mut res = Err(io::ErrorKind::NotConnected.into());
+            for station in &mut self.stations {
+                let sent =
+                    station.send(mutated.as_ref(), &self.session, wait);
+                if res.is_err() {
+                    res = sent;
+                }
+            }
+            res?;
+        }
+        if let Some(recorder) = &mut self.recorder {
+            let sent = SystemClock::new().now()?;
+            recorder.record(mutated.as_ref(), sent)?;
+        }
+        // Also in a dry run, so that the messages that follow are checked
+        // against this one as they would be when sent.
+        self.session.observe(&bytes);
+        Ok(())
+    }
+
+    /// Prints a message as asked for, to standard output in a dry run and
+    /// to standard error otherwise, as standard output may carry the
+    /// messages themselves.
+    fn print(&self, bytes: &[u8]) {
+        let mut text = String::new();
+        if self.verbose {
+            text.push_str(&tree(bytes));
+        }
+        if self.hexdump {
+            text.push_str(&hexdump(bytes));
+        }
+        if text.is_empty() {
+            return;
+        }
+        if self.dry_run {
+            println!("{text}");
+        } else {
+            eprintln!("{text}");
+        }
+    }
+}
+
+fn main() {
+    let server_arg = clap::Arg::new("server")
+        .short('s')
+        .long("server")
+        .value_name("IP or IP:PORT")
+        .action(clap::ArgAction::Append)
+        .help(formatcp!("Connect to a BMP monitoring station on this address [default port: {DEF_BMP_PORT}], can be given several times to send the same messages to each station"));
+
+    let output_arg = clap::Arg::new("output")
+        .short('o')
+        .long("output")
+        .value_name("FILE or -")
+        .help("Write the messages to this file, or to standard output for -, instead of connecting to a monitoring station");
+
+    let listen_arg = clap::Arg::new("listen")
+        .short('l')
+        .long("listen")
+        .value_name("IP or IP:PORT")
+        .help(formatcp!("Wait for a BMP monitoring station to connect on this address instead of connecting to it [default port: {DEF_BMP_PORT}]"));
+
+    let accept_multiple_arg = clap::Arg::new("accept-multiple")
+        .long("accept-multiple")
+        .requires("listen")
+        .action(clap::ArgAction::SetTrue)
+        .help("When the monitoring station disconnects, wait for the next one and replay the session state to it");
+
+    let no_reconnect_arg = clap::Arg::new("no-reconnect")
+        .long("no-reconnect")
+        .requires("server")
+        .action(clap::ArgAction::SetTrue)
+        .help("Exit when the connection to the monitoring station is lost instead of reconnecting and replaying the session state");
+
+    let output_format_arg = clap::Arg::new("output-format")
+        .long("output-format")
+        .value_name("raw, mrt or pcap")
+        .default_value("raw")
+        .value_parser(clap::value_parser!(RecordFormat))
+        .help("Write the messages back to back, as MRT records of type BMP, or as a pcap capture of a TCP session to port 11019");
+
+    let dry_run_arg = clap::Arg::new("dry-run")
+        .long("dry-run")
+        .conflicts_with("record")
+        .action(clap::ArgAction::SetTrue)
+        .help("Print each message as with --verbose and --hexdump to standard output instead of sending it anywhere");
+
+    let verbose_arg = clap::Arg::new("verbose")
+        .short('v')
+        .long("verbose")
+        .action(clap::ArgAction::SetTrue)
+        .help("Print each message sent as decoded by routecore, to standard error");
+
+    let hexdump_arg = clap::Arg::new("hexdump")
+        .long("hexdump")
+        .action(clap::ArgAction::SetTrue)
+        .help("Print each message sent as a hex dump annotated with its fields, to standard error");
+
+    let strict_arg = clap::Arg::new("strict")
+        .long("strict")
+        .action(clap::ArgAction::SetTrue)
+        .help("Refuse to send messages that violate an RFC instead of warning about them");
+
+    let record_arg = clap::Arg::new("record")
+        .long("record")
+        .value_name("FILE")
+        .help("Also write every message sent to this capture file");
+
+    let record_format_arg = clap::Arg::new("record-format")
+        .long("record-format")
+        .value_name("raw, mrt or pcap")
+        .default_value("raw")
+        .value_parser(clap::value_parser!(RecordFormat))
+        .help("Record the messages back to back as sent, as MRT records of type BMP, or as a pcap capture of a TCP session to port 11019");
+
+    let replay_cmd = clap::Command::new("replay")
+        .about("Send the routing information in an MRT file or the messages in a BMP capture, then exit")
+        .arg(
+            clap::Arg::new("format")
+                .long("format")
+                .value_name("mrt or bmp")
+                .default_value("mrt")
+                .value_parser(clap::value_parser!(replay::Format))
+                .help("Read an MRT file and convert it into BMP messages, or read BMP messages back to back and send them as they are"),
+        )
+        .arg(
+            clap::Arg::new("speed")
+                .long("speed")
+                .value_name("FACTOR or max")
+                .default_value("max")
+                .value_parser(clap::value_parser!(replay::Speed))
+                .help("Pace messages by the MRT record or Per-Peer Header timestamps, sped up by this factor (1 for real-time), or send them as fast as possible"),
+        )
+        .arg(
+            clap::Arg::new("now")
+                .long("now")
+                .action(clap::ArgAction::SetTrue)
+                .help("Set Per-Peer Header timestamps to the time each message is sent"),
+        )
+        .arg(
+            clap::Arg::new("file")
+                .required(true)
+                .value_name("FILE")
+                .help("The uncompressed file to read, or - for standard input"),
+        );
+
+    let run_cmd = clap::Command::new("run")
+        .about("Run the REPL commands in a script with variables, loops and delays, then exit, with a non-zero exit status at the first error")
+        .arg(
+            clap::Arg::new("script")
+                .required(true)
+                .value_name("FILE")
+                .help("The script to run, or - for standard input"),
+        );
+
+    let bench_cmd = clap::Command::new("bench")
+        .about("Announce the routes of simulated peers to benchmark the monitoring station, then report the throughput and exit")
+        .arg(
+            clap::Arg::new("peers")
+                .long("peers")
+                .value_name("N")
+                .default_value("1")
+                .value_parser(clap::value_parser!(u32))
+                .help("The number of peers to simulate"),
+        )
+        .arg(
+            clap::Arg::new("table")
+                .long("table")
+                .value_name("sequential or synthetic")
+                .default_value("sequential")
+                .value_parser(clap::value_parser!(bench::TableKind))
+                .help("Announce consecutive IPv4 /24s or a seeded synthetic table that looks like the Internet table"),
+        )
+        .arg(
+            clap::Arg::new("prefixes")
+                .long("prefixes")
+                .value_name("N")
+                .default_value("100000")
+                .value_parser(clap::value_parser!(u32))
+                .help("The number of IPv4 prefixes each peer announces"),
+        )
+        .arg(
+            clap::Arg::new("ipv6-prefixes")
+                .long("ipv6-prefixes")
+                .value_name("N")
+                .default_value("0")
+                .value_parser(clap::value_parser!(u32))
+                .help("The number of IPv6 prefixes each peer announces with --table synthetic"),
+        )
+        .arg(
+            clap::Arg::new("attribute-sets")
+                .long("attribute-sets")
+                .value_name("N")
+                .default_value("1")
+                .value_parser(clap::value_parser!(u32))
+                .help("The number of distinct sets of path attributes the prefixes of each peer are divided over with --table sequential"),
+        )
+        .arg(
+            clap::Arg::new("seed")
+                .long("seed")
+                .value_name("N")
+                .default_value("0")
+                .value_parser(clap::value_parser!(u64))
+                .help("The seed of the synthetic table, the same seed giving the same table"),
+        )
+        .arg(
+            clap::Arg::new("batch")
+                .long("batch")
+                .value_name("N")
+                .default_value("1")
+                .value_parser(clap::value_parser!(u32))
+                .help("The number of prefixes per Route Monitoring message, fewer where the path attributes change"),
+        )
+        .arg(
+            clap::Arg::new("rate")
+                .long("rate")
+                .value_name("MSGS/S or max")
+                .default_value("max")
+                .value_parser(clap::value_parser!(bench::Rate))
+                .help("Send at most this many Route Monitoring messages per second, or as many as the monitoring station accepts"),
+        );
+
+    let churn_cmd = clap::Command::new("churn")
+        .about("Announce a synthetic table for simulated peers, then withdraw, replace and flap their routes for a while and exit")
+        .arg(
+            clap::Arg::new("peers")
+                .long("peers")
+                .value_name("N")
+                .default_value("1")
+                .value_parser(clap::value_parser!(u32))
+                .help("The number of peers to simulate"),
+        )
+        .arg(
+            clap::Arg::new("prefixes")
+                .long("prefixes")
+                .value_name("N")
+                .default_value("10000")
+                .value_parser(clap::value_parser!(usize))
+                .help("The number of IPv4 prefixes in the table of each peer"),
+        )
+        .arg(
+            clap::Arg::new("ipv6-prefixes")
+                .long("ipv6-prefixes")
+                .value_name("N")
+                .default_value("0")
+                .value_parser(clap::value_parser!(usize))
+                .help("The number of IPv6 prefixes in the table of each peer"),
+        )
+        .arg(
+            clap::Arg::new("seed")
+                .long("seed")
+                .value_name("N")
+                .default_value("0")
+                .value_parser(clap::value_parser!(u64))
+                .help("The seed of the table and the churn, the same seed giving the same messages"),
+        )
+        .arg(
+            clap::Arg::new("rate")
+                .long("rate")
+                .value_name("EVENTS/S")
+                .default_value("10")
+                .value_parser(churn::parse_rate)
+                .help("The mean number of route events per second over all peers"),
+        )
+        .arg(
+            clap::Arg::new("mix")
+                .long("mix")
+                .value_name("KIND=WEIGHT,...")
+                .default_value("announce=20,replace=50,withdraw=20,flap=10")
+                .value_parser(clap::value_parser!(EventMix))
+                .help("The relative weights of announcing withdrawn prefixes, replacing paths, withdrawing and flapping"),
+        )
+        .arg(
+            clap::Arg::new("flap-interval")
+                .long("flap-interval")
+                .value_name("DURATION")
+                .default_value("10s")
+                .value_parser(script::parse_duration)
+                .help("The mean time a flapping route stays withdrawn or announced"),
+        )
+        .arg(
+            clap::Arg::new("flap-count")
+                .long("flap-count")
+                .value_name("N")
+                .default_value("5")
+                .value_parser(clap::value_parser!(u32))
+                .help("How often a flapping route is withdrawn and announced again"),
+        )
+        .arg(
+            clap::Arg::new("burst-interval")
+                .long("burst-interval")
+                .value_name("DURATION or none")
+                .default_value("none")
+                .value_parser(churn::parse_burst_interval)
+                .help("The mean time between bursts in which a peer loses a share of its table at once"),
+        )
+        .arg(
+            clap::Arg::new("burst-share")
+                .long("burst-share")
+                .value_name("PERCENT")
+                .default_value("50")
+                .value_parser(churn::parse_percentage)
+                .help("The share of its routes a peer withdraws in a burst"),
+        )
+        .arg(
+            clap::Arg::new("burst-recovery")
+                .long("burst-recovery")
+                .value_name("DURATION")
+                .default_value("60s")
+                .value_parser(script::parse_duration)
+                .help("The mean time until a peer announces the routes lost in a burst again"),
+        )
+        .arg(
+            clap::Arg::new("duration")
+                .long("duration")
+                .value_name("DURATION")
+                .default_value("60s")
+                .value_parser(script::parse_duration)
+                .help("How long to produce churn for"),
+        )
+        .arg(
+            clap::Arg::new("speed")
+                .long("speed")
+                .value_name("FACTOR or max")
+                .default_value("1")
+                .value_parser(clap::value_parser!(replay::Speed))
+                .help("Send the churn in real time, sped up by this factor, or as fast as possible"),
+        );
+
+    let matches = clap::Command::new("bmp-speaker")
+        .version(clap::crate_version!())
+        .author(clap::crate_authors!())
+        .arg(server_arg)
+        .arg(listen_arg)
+        .arg(accept_multiple_arg)
+        .arg(no_reconnect_arg)
+        .arg(output_arg)
+        .arg(output_format_arg)
+        .arg(dry_run_arg)
+        .group(
+            clap::ArgGroup::new("destination")
+                .args(["server", "listen", "output", "dry-run"])
+                .required(true),
+        )
+        .arg(verbose_arg)
+        .arg(hexdump_arg)
+        .arg(strict_arg)
+        .arg(record_arg)
+        .arg(record_format_arg)
+        .subcommand(replay_cmd)
+        .subcommand(run_cmd)
+        .subcommand(bench_cmd)
+        .subcommand(churn_cmd)
+        .get_matches();
+
+    let strict = matches.get_flag("strict");
+
+    let recorder = matches.get_one::<String>("record").map(|path| {
+        let format = matches.get_one::<RecordFormat>("record-format");
+        create_recorder(path, *format.unwrap())
+    });
+
+    let reconnect = !matches.get_flag("no-reconnect");
+    let dry_run = matches.get_flag("dry-run");
+    let stations = if dry_run {
+        vec![]
+    } else if let Some(path) = matches.get_one::<String>("output") {
+        let format =
+            *matches.get_one::<RecordFormat>("output-format").unwrap();
+        let sink = if path == "-" {
+            match Recorder::new(io::stdout(), format) {
+                Ok(recorder) => Box::new(recorder) as Box<dyn Sink>,
+                Err(err) => {
+                    eprintln!(
+                        "Error: Failed to write to standard output: {}",
+                        err
+                    );
+                    std::process::exit(1);
+                }
+            }
+        } else {
+            Box::new(create_recorder(path, format))
+        };
+        vec![Station::new(path, sink, false)]
+    } else if let Some(addr) = matches.get_one::<String>("listen") {
+        let addr = with_default_port(addr);
+        match ListenSink::bind(&addr)
+            .and_then(|mut sink| sink.accept().map(|()| sink))
+        {
+            Ok(sink) => {
+                let multiple = matches.get_flag("accept-multiple");
+                vec![Station::new(addr, Box::new(sink), multiple)]
+            }
+            Err(err) => {
+                eprintln!("Error: Failed to listen on '{}': {}", addr, err);
+                std::process::exit(1);
+            }
+        }
+    } else {
+        matches
+            .get_many::<String>("server")
+            .unwrap()
+            .map(|server| {
+                let server = with_default_port(server);
+                match TcpSink::connect(&server) {
+                    Ok(sink) => {
+                        Station::new(server, Box::new(sink), reconnect)
+                    }
+                    Err(err) => {
+                        eprintln!(
+                            "Error: Failed to connect to server at '{}': {}",
+                            server, err
+                        );
+                        std::process::exit(1);
+                    }
+                }
+            })
+            .collect()
+    };
+    let mut output = Output::new(stations, recorder);
+    output.verbose = dry_run || matches.get_flag("verbose");
+    output.hexdump = dry_run || matches.get_flag("hexdump");
+    output.dry_run = dry_run;
+    let output = Arc::new(Mutex::new(output));
+
+    match matches.subcommand() {
+        Some(("replay", args)) => {
+            let file = args.get_one::<String>("file").unwrap();
+            let options = replay::Options {
+                format: *args.get_one::<replay::Format>("format").unwrap(),
+                speed: *args.get_one::<replay::Speed>("speed").unwrap(),
+                now: args.get_flag("now"),
+                strict,
+            };
+            if let Err(err) = replay::run(output, file, options) {
+                eprintln!("Error: {err:#}");
+                std::process::exit(1);
+            }
+        }
+        Some(("run", args)) => {
+            let script = args.get_one::<String>("script").unwrap();
+            if let Err(err) = script::run(output, script, strict) {
+                eprintln!("Error: {err:#}");
+                std::process::exit(1);
+            }
+        }
+        Some(("bench", args)) => {
+            let options = bench::Options {
+                peers: *args.get_one::<u32>("peers").unwrap(),
+                table: *args.get_one::<bench::TableKind>("table").unwrap(),
+                prefixes: *args.get_one::<u32>("prefixes").unwrap(),
+                ipv6_prefixes: *args.get_one::<u32>("ipv6-prefixes").unwrap(),
+                attribute_sets: *args
+                    .get_one::<u32>("attribute-sets")
+                    .unwrap(),
+                seed: *args.get_one::<u64>("seed").unwrap(),
+                batch: *args.get_one::<u32>("batch").unwrap(),
+                rate: *args.get_one::<bench::Rate>("rate").unwrap(),
+                strict,
+            };
+            if let Err(err) = bench::run(output, options) {
+                eprintln!("Error: {err:#}");
+                std::process::exit(1);
+            }
+        }
+        Some(("churn", args)) => {
+            let options = churn::Options {
+                peers: *args.get_one::<u32>("peers").unwrap(),
+                table: TableConfig {
+                    ipv4_prefixes: *args
+                        .get_one::<usize>("prefixes")
+                        .unwrap(),
+                    ipv6_prefixes: *args
+                        .get_one::<usize>("ipv6-prefixes")
+                        .unwrap(),
+                    seed: *args.get_one::<u64>("seed").unwrap(),
+                },
+                churn: ChurnConfig {
+                    rate: *args.get_one::<f64>("rate").unwrap(),
+                    mix: *args.get_one::<EventMix>("mix").unwrap(),
+                    flap_interval: *args
+                        .get_one::<Duration>("flap-interval")
+                        .unwrap(),
+                    flap_count: *args.get_one::<u32>("flap-count").unwrap(),
+                    burst_interval: *args
+                        .get_one::<Option<Duration>>("burst-interval")
+                        .unwrap(),
+                    burst_share: *args.get_one::<f64>("burst-share").unwrap(),
+                    burst_recovery: *args
+                        .get_one::<Duration>("burst-recovery")
+                        .unwrap(),
+                    seed: *args.get_one::<u64>("seed").unwrap(),
+                },
+                duration: *args.get_one::<Duration>("duration").unwrap(),
+                speed: *args.get_one::<replay::Speed>("speed").unwrap(),
+                strict,
+            };
+            if let Err(err) = churn::run(output, options) {
+                eprintln!("Error: {err:#}");
+                std::process::exit(1);
+            }
+        }
+        _ => repl::run(output, strict),
+    }
+}
+
+/// Adds the default BMP port to an address without a port.
+///
+/// A bare IPv6 address has colons of its own, so it is recognized as an
+/// address first, with or without brackets.
+fn with_default_port(addr: &str) -> String {
+    let ip = addr
+        .strip_prefix('[')
+        .and_then(|addr| addr.strip_suffix(']'))
+        .unwrap_or(addr);
+    if let Ok(ip) = ip.parse::<IpAddr>() {
+        SocketAddr::new(ip, DEF_BMP_PORT).to_string()
+    } else if !addr.contains(':') {
+        format!("{addr}:{DEF_BMP_PORT}")
+    } else {
+        addr.to_string()
+    }
+}
+
+/// Creates a file to write messages to in the given format, exiting if
+/// that fails.
+fn create_recorder(path: &str, format: RecordFormat) -> Recorder<File> {
+    match File::create(path).and_then(|file| Recorder::new(file, format)) {
+        Ok(recorder) => recorder,
+        Err(err) => {
+            eprintln!("Error: Failed to create '{}': {}", path, err);
+            std::process::exit(1);
+        }
+    }
+}
+
+/// Returns the encoded bytes, reporting or rejecting any warnings.
+///
+/// In strict mode the first warning is returned as an error, otherwise all
+/// warnings are printed and the bytes are returned.
+fn check_warnings<T>(
+    res: Result<(T, Vec<Warning>), EncodeError>,
+    strict_mode: bool,
+) -> Result<T, EncodeError> {
+    if strict_mode {
+        return strict(res);
+    }
+    let (bytes, warnings) = res?;
+    for msg in warnings {
+        eprintln!("Warning: {}", msg);
+    }
+    Ok(bytes)
+}
@@ -0,0 +1,703 @@
+//! The interactive mode: send messages as instructed on the command line.
+
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+
+use easy_repl::{command, CommandStatus, Repl};
+
+use routecore::{asn::Asn, bmp::message::PeerType};
+use routes::bmp::clock::{
+    Clock, FixedClock, SimulatedClock, SystemClock, Timestamp,
+};
+use routes::bmp::encode::{
+    mk_initiation_msg, mk_peer_down_notification_msg,
+    mk_peer_up_notification_msg, mk_raw_route_monitoring_msg,
+    mk_route_monitoring_msgs, mk_termination_msg, MyPeerType,
+    PerPeerHeader, Prefixes, Routes,
+};
+use routes::bmp::mutate::{fix_lengths, Mutation};
+use routes::hex;
+
+use crate::{check_warnings, SharedOutput};
+
+type SharedClock = Arc<Mutex<Box<dyn Clock + Send>>>;
+
+/// Per-Peer Headers by the name given to `peer_define`, without timestamp.
+type SharedPeers = Arc<Mutex<HashMap<String, PerPeerHeader>>>;
+
+/// initiation a b
+/// peer_up_notification global 0 10.0.0.1 12345 127.0.0.1 80 81 888 999 0 0
+/// route_monitoring global 0 10.0.0.1 12345 0 127.0.0.1/32
+/// route_monitoring global 0 10.0.0.1 12345 0 none "e [123,456,789] 10.0.0.1 none 127.0.0.1/32"
+/// route_monitoring global 0 10.0.0.1 12345 0 none "e [123,456,789] 10.0.0.1 BLACKHOLE,123:44 127.0.0.1/32"
+/// peer_define r1 global 0 10.0.0.1 12345 0
+/// route_monitoring r1 none "e [123,456,789] 10.0.0.1 none 127.0.0.1/32"
+pub fn run(output: SharedOutput, strict: bool) {
+    let mut repl = Repl::builder();
+    for (name, cmd) in commands(output, strict) {
+        repl = repl.add(name, cmd);
+    }
+    let mut repl = repl.build().expect("Failed to create REPL");
+
+    repl.run().expect("Critical REPL error");
+}
+
+/// Returns the commands of the REPL, which are also those of scripts.
+///
+/// A name appears once for each of its overloads.
+pub fn commands<'a>(
+    output: SharedOutput,
+    strict: bool,
+) -> Vec<(&'static str, easy_repl::Command<'a>)> {
+    let clock: SharedClock =
+        Arc::new(Mutex::new(Box::new(SystemClock::new())));
+    let peers: SharedPeers = Default::default();
+
+    vec![
+        ("initiation", initiate_cmd(output.clone())),
+        ("peer_define", peer_define_cmd(peers.clone())),
+        (
+            "peer_up_notification",
+            peer_up_cmd(output.clone(), clock.clone(), strict),
+        ),
+        (
+            "peer_up_notification",
+            named_peer_up_cmd(
+                output.clone(),
+                clock.clone(),
+                peers.clone(),
+                strict,
+            ),
+        ),
+        (
+            "route_monitoring",
+            route_monitoring_cmd(output.clone(), clock.clone(), strict),
+        ),
+        (
+            "route_monitoring",
+            named_route_monitoring_cmd(
+                output.clone(),
+                clock.clone(),
+                peers.clone(),
+                strict,
+            ),
+        ),
+        (
+            "raw_route_monitoring",
+            route_monitoring_raw_cmd(
+                output.clone(),
+                clock.clone(),
+                strict,
+            ),
+        ),
+        (
+            "raw_route_monitoring",
+            named_route_monitoring_raw_cmd(
+                output.clone(),
+                clock.clone(),
+                peers.clone(),
+                strict,
+            ),
+        ),
+        ("raw_bmp", raw_bmp_cmd(output.clone())),
+        ("raw_bmp", raw_bmp_lengths_cmd(output.clone())),
+        (
+            "peer_down_notification",
+            peer_down_cmd(output.clone(), clock.clone(), strict),
+        ),
+        (
+            "peer_down_notification",
+            named_peer_down_cmd(output.clone(), clock.clone(), peers, strict),
+        ),
+        ("clock_system", clock_system_cmd(clock.clone())),
+        ("clock_offset", clock_offset_cmd(clock.clone())),
+        ("clock_fixed", clock_fixed_cmd(clock.clone())),
+        ("clock_simulated", clock_simulated_cmd(clock)),
+        ("mutate", mutate_cmd(output.clone())),
+        ("clear_mutations", clear_mutations_cmd(output.clone())),
+        ("stations", stations_cmd(output.clone())),
+        ("show", show_cmd(output.clone())),
+        ("termination", terminate_cmd(output)),
+    ]
+}
+
+fn initiate_cmd<'a>(output: SharedOutput) -> easy_repl::Command<'a> {
+    command! {
+        "BMP Initiation Message",
+        (sys_name: String, sys_descr: String) => |sys_name: String, sys_descr: String| {
+            let bytes = mk_initiation_msg(&sys_name, &sys_descr)?;
+            output.lock().unwrap().send(bytes)?;
+            Ok(CommandStatus::Done)
+        }
+    }
+}
+
+fn peer_define_cmd<'a>(peers: SharedPeers) -> easy_repl::Command<'a> {
+    command! {
+        "Define a named peer whose Per-Peer Header fields other commands can use instead of repeating them",
+        (
+            name: String,
+            peer_type: MyPeerType,
+            peer_flags: u8,
+            peer_address: IpAddr,
+            peer_as: Asn,
+            peer_bgp_id: u32
+        ) => |
+            name: String,
+            peer_type: MyPeerType,
+            peer_flags: u8,
+            peer_address: IpAddr,
+            peer_as: Asn,
+            peer_bgp_id: u32,
+        | {
+            let per_peer_header = PerPeerHeader::new(peer_address, peer_as)
+                .peer_type(peer_type)
+                .flags(peer_flags)
+                .bgp_id(peer_bgp_id.to_be_bytes());
+            peers.lock().unwrap().insert(name, per_peer_header);
+            Ok(CommandStatus::Done)
+        }
+    }
+}
+
+/// Returns the Per-Peer Header of a peer defined with `peer_define`,
+/// timestamped by the clock.
+fn named_peer(
+    peers: &SharedPeers,
+    clock: &SharedClock,
+    name: &str,
+) -> anyhow::Result<PerPeerHeader> {
+    let per_peer_header = peers
+        .lock()
+        .unwrap()
+        .get(name)
+        .cloned()
+        .ok_or_else(|| {
+            anyhow::anyhow!(
+                "Unknown peer '{name}', define it with peer_define first"
+            )
+        })?;
+    Ok(per_peer_header.timestamp(clock.lock().unwrap().now()?))
+}
+
+/// Returns the Peer Distinguisher for a peer type.
+///
+/// Only global instance peers, which have none, are supported so far.
+fn peer_distinguisher(peer_type: &MyPeerType) -> anyhow::Result<[u8; 8]> {
+    match **peer_type {
+        PeerType::GlobalInstance => Ok([0u8; 8]),
+        _ => Err(anyhow::anyhow!(
+            "Peer type {} is not supported",
+            **peer_type
+        )),
+    }
+}
+
+fn peer_up_cmd<'a>(
+    output: SharedOutput,
+    clock: SharedClock,
+    strict: bool,
+) -> easy_repl::Command<'a> {
+    command! {
+        "BMP Peer Up Notification",
+        (
+            peer_type: MyPeerType,
+            peer_flags: u8,
+            peer_address: IpAddr,
+            peer_as: Asn,
+            local_address: IpAddr,
+            local_port: u16,
+            remote_port: u16,
+            sent_open_asn: u16,
+            received_open_asn: u16,
+            sent_bgp_identifier: u32,
+            received_bgp_identifier: u32
+        ) => |
+            peer_type: MyPeerType,
+            peer_flags: u8,
+            peer_address: IpAddr,
+            peer_as: Asn,
+            local_address: IpAddr,
+            local_port: u16,
+            remote_port: u16,
+            sent_open_asn: u16,
+            received_open_asn: u16,
+            sent_bgp_identifier: u32,
+            received_bgp_id: u32
+        | {
+            let peer_distinguisher = peer_distinguisher(&peer_type)?;
+            let peer_bgp_id = received_bgp_id.to_be_bytes();
+            let per_peer_header = PerPeerHeader {
+                peer_type,
+                peer_flags,
+                peer_distinguisher,
+                peer_address,
+                peer_as,
+                peer_bgp_id,
+                timestamp: Some(clock.lock().unwrap().now()?)};
+            let res = mk_peer_up_notification_msg(
+                &per_peer_header,
+                local_address,
+                local_port,
+                remote_port,
+                sent_open_asn,
+                received_open_asn,
+                sent_bgp_identifier,
+                received_bgp_id,
+                vec![],
+                true);
+            let bytes = check_warnings(res, strict)?;
+            output.lock().unwrap().send(bytes)?;
+            Ok(CommandStatus::Done)
+        }
+    }
+}
+
+fn named_peer_up_cmd<'a>(
+    output: SharedOutput,
+    clock: SharedClock,
+    peers: SharedPeers,
+    strict: bool,
+) -> easy_repl::Command<'a> {
+    command! {
+        "BMP Peer Up Notification for a peer defined with peer_define",
+        (
+            peer: String,
+            local_address: IpAddr,
+            local_port: u16,
+            remote_port: u16,
+            sent_open_asn: u16,
+            received_open_asn: u16,
+            sent_bgp_identifier: u32,
+            received_bgp_identifier: u32
+        ) => |
+            peer: String,
+            local_address: IpAddr,
+            local_port: u16,
+            remote_port: u16,
+            sent_open_asn: u16,
+            received_open_asn: u16,
+            sent_bgp_identifier: u32,
+            received_bgp_id: u32
+        | {
+            let per_peer_header = named_peer(&peers, &clock, &peer)?;
+            let res = mk_peer_up_notification_msg(
+                &per_peer_header,
+                local_address,
+                local_port,
+                remote_port,
+                sent_open_asn,
+                received_open_asn,
+                sent_bgp_identifier,
+                received_bgp_id,
+                vec![],
+                true);
+            let bytes = check_warnings(res, strict)?;
+            output.lock().unwrap().send(bytes)?;
+            Ok(CommandStatus::Done)
+        }
+    }
+}
+
+fn route_monitoring_cmd<'a>(
+    output: SharedOutput,
+    clock: SharedClock,
+    strict: bool,
+) -> easy_repl::Command<'a> {
+    command! {
+        "BMP Route Monitoring Message (from announcements & withdrawals)",
+        (
+            peer_type: MyPeerType,
+            peer_flags: u8,
+            peer_address: IpAddr,
+            peer_as: Asn,
+            peer_bgp_id: u32,
+            withdrawals: Prefixes,
+            announcements: Routes
+        ) => |
+            peer_type: MyPeerType,
+            peer_flags: u8,
+            peer_address: IpAddr,
+            peer_as: Asn,
+            peer_bgp_id: u32,
+            withdrawals: Prefixes,
+            announcements: Routes,
+        | {
+            let peer_distinguisher = peer_distinguisher(&peer_type)?;
+            let peer_bgp_id = peer_bgp_id.to_be_bytes();
+            let per_peer_header = PerPeerHeader {
+                peer_type,
+                peer_flags,
+                peer_distinguisher,
+                peer_address,
+                peer_as,
+                peer_bgp_id,
+                timestamp: Some(clock.lock().unwrap().now()?)};
+            let res = mk_route_monitoring_msgs(&per_peer_header, &withdrawals, &announcements, &[]);
+            let mut output = output.lock().unwrap();
+            for bytes in check_warnings(res, strict)? {
+                output.send(bytes)?;
+            }
+            Ok(CommandStatus::Done)
+        }
+    }
+}
+
+fn named_route_monitoring_cmd<'a>(
+    output: SharedOutput,
+    clock: SharedClock,
+    peers: SharedPeers,
+    strict: bool,
+) -> easy_repl::Command<'a> {
+    command! {
+        "BMP Route Monitoring Message (from announcements & withdrawals) for a peer defined with peer_define",
+        (
+            peer: String,
+            withdrawals: Prefixes,
+            announcements: Routes
+        ) => |
+            peer: String,
+            withdrawals: Prefixes,
+            announcements: Routes,
+        | {
+            let per_peer_header = named_peer(&peers, &clock, &peer)?;
+            let res = mk_route_monitoring_msgs(&per_peer_header, &withdrawals, &announcements, &[]);
+            let mut output = output.lock().unwrap();
+            for bytes in check_warnings(res, strict)? {
+                output.send(bytes)?;
+            }
+            Ok(CommandStatus::Done)
+        }
+    }
+}
+
+struct HexBytes(Vec<u8>);
+
+impl HexBytes {
+    pub fn into_vec(self) -> Vec<u8> {
+        self.0
+    }
+}
+
+impl FromStr for HexBytes {
+    type Err = anyhow::Error;
+
+    /// Parses hex in any format supported by [`hex::decode`], or reads it
+    /// from a file given as `@<path>`, e.g. a multi-line hex dump.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix('@') {
+            Some(path) => {
+                let text = std::fs::read_to_string(path).map_err(|err| {
+                    anyhow::anyhow!("Failed to read '{path}': {err}")
+                })?;
+                Ok(Self(hex::decode(&text)?))
+            }
+            None => Ok(Self(hex::decode(s)?)),
+        }
+    }
+}
+
+/// Whether to send a raw BMP message as is or with corrected lengths.
+enum Lengths {
+    AsIs,
+    Fix,
+}
+
+impl FromStr for Lengths {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "as_is" => Ok(Lengths::AsIs),
+            "fix" => Ok(Lengths::Fix),
+            _ => Err(anyhow::anyhow!("Expected as_is or fix")),
+        }
+    }
+}
+
+/// What the `show` command prints.
+enum Show {
+    Peers,
+    Routes,
+}
+
+impl FromStr for Show {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "peers" => Ok(Show::Peers),
+            "routes" => Ok(Show::Routes),
+            _ => Err(anyhow::anyhow!("Expected peers or routes")),
+        }
+    }
+}
+
+fn route_monitoring_raw_cmd<'a>(
+    output: SharedOutput,
+    clock: SharedClock,
+    strict: bool,
+) -> easy_repl::Command<'a> {
+    command! {
+        "BMP Route Monitoring Message (from hex BGP UPDATE bytes)",
+        (
+            peer_type: MyPeerType,
+            peer_flags: u8,
+            peer_address: IpAddr,
+            peer_as: Asn,
+            peer_bgp_id: u32,
+            bgp_msg_buf: HexBytes
+        ) => |
+            peer_type: MyPeerType,
+            peer_flags: u8,
+            peer_address: IpAddr,
+            peer_as: Asn,
+            peer_bgp_id: u32,
+            bgp_msg_buf: HexBytes,
+        | {
+            let peer_distinguisher = peer_distinguisher(&peer_type)?;
+            let peer_bgp_id = peer_bgp_id.to_be_bytes();
+            let per_peer_header = PerPeerHeader {
+                peer_type,
+                peer_flags,
+                peer_distinguisher,
+                peer_address,
+                peer_as,
+                peer_bgp_id,
+                timestamp: Some(clock.lock().unwrap().now()?)};
+            let res = mk_raw_route_monitoring_msg(&per_peer_header, bgp_msg_buf.into_vec().into());
+            let bytes = check_warnings(res, strict)?;
+            output.lock().unwrap().send(bytes)?;
+            Ok(CommandStatus::Done)
+        }
+    }
+}
+
+fn named_route_monitoring_raw_cmd<'a>(
+    output: SharedOutput,
+    clock: SharedClock,
+    peers: SharedPeers,
+    strict: bool,
+) -> easy_repl::Command<'a> {
+    command! {
+        "BMP Route Monitoring Message (from hex BGP UPDATE bytes) for a peer defined with peer_define",
+        (peer: String, bgp_msg_buf: HexBytes) => |peer: String, bgp_msg_buf: HexBytes| {
+            let per_peer_header = named_peer(&peers, &clock, &peer)?;
+            let res = mk_raw_route_monitoring_msg(&per_peer_header, bgp_msg_buf.into_vec().into());
+            let bytes = check_warnings(res, strict)?;
+            output.lock().unwrap().send(bytes)?;
+            Ok(CommandStatus::Done)
+        }
+    }
+}
+
+fn raw_bmp_cmd<'a>(output: SharedOutput) -> easy_repl::Command<'a> {
+    command! {
+        "Any BMP message (from hex bytes or @file)",
+        (msg: HexBytes) => |msg: HexBytes| {
+            output.lock().unwrap().send(msg.into_vec().into())?;
+            Ok(CommandStatus::Done)
+        }
+    }
+}
+
+fn raw_bmp_lengths_cmd<'a>(
+    output: SharedOutput,
+) -> easy_repl::Command<'a> {
+    command! {
+        "Any BMP message (from hex bytes or @file), with the BMP and BGP lengths sent as_is or fix'ed to match the actual lengths",
+        (msg: HexBytes, lengths: Lengths) => |msg: HexBytes, lengths: Lengths| {
+            let bytes = match lengths {
+                Lengths::AsIs => msg.into_vec().into(),
+                Lengths::Fix => fix_lengths(&msg.into_vec())?,
+            };
+            output.lock().unwrap().send(bytes)?;
+            Ok(CommandStatus::Done)
+        }
+    }
+}
+
+fn peer_down_cmd<'a>(
+    output: SharedOutput,
+    clock: SharedClock,
+    strict: bool,
+) -> easy_repl::Command<'a> {
+    command! {
+        "BMP Peer Down Notification",
+        (
+            peer_type: MyPeerType,
+            peer_flags: u8,
+            peer_address: IpAddr,
+            peer_as: Asn,
+            peer_bgp_id: u32
+        ) => |
+            peer_type: MyPeerType,
+            peer_flags: u8,
+            peer_address: IpAddr,
+            peer_as: Asn,
+            peer_bgp_id: u32,
+        | {
+            let peer_distinguisher = peer_distinguisher(&peer_type)?;
+            let peer_bgp_id = peer_bgp_id.to_be_bytes();
+            let per_peer_header = PerPeerHeader {
+                peer_type,
+                peer_flags,
+                peer_distinguisher,
+                peer_address,
+                peer_as,
+                peer_bgp_id,
+                timestamp: Some(clock.lock().unwrap().now()?)};
+            let res = mk_peer_down_notification_msg(&per_peer_header);
+            let bytes = check_warnings(res, strict)?;
+            output.lock().unwrap().send(bytes)?;
+            Ok(CommandStatus::Done)
+        }
+    }
+}
+
+fn named_peer_down_cmd<'a>(
+    output: SharedOutput,
+    clock: SharedClock,
+    peers: SharedPeers,
+    strict: bool,
+) -> easy_repl::Command<'a> {
+    command! {
+        "BMP Peer Down Notification for a peer defined with peer_define",
+        (peer: String) => |peer: String| {
+            let per_peer_header = named_peer(&peers, &clock, &peer)?;
+            let res = mk_peer_down_notification_msg(&per_peer_header);
+            let bytes = check_warnings(res, strict)?;
+            output.lock().unwrap().send(bytes)?;
+            Ok(CommandStatus::Done)
+        }
+    }
+}
+
+fn terminate_cmd<'a>(
+    output: SharedOutput,
+) -> easy_repl::Command<'a> {
+    command! {
+        "BMP Termination Message", () => || {
+            let bytes = mk_termination_msg()?;
+            output.lock().unwrap().send(bytes)?;
+            Ok(CommandStatus::Done)
+        }
+    }
+}
+
+fn clock_system_cmd<'a>(clock: SharedClock) -> easy_repl::Command<'a> {
+    command! {
+        "Use the system time for Per-Peer Header timestamps", () => || {
+            *clock.lock().unwrap() = Box::new(SystemClock::new());
+            Ok(CommandStatus::Done)
+        }
+    }
+}
+
+fn clock_offset_cmd<'a>(clock: SharedClock) -> easy_repl::Command<'a> {
+    command! {
+        "Use the system time shifted by a number of seconds (negative for the past) for Per-Peer Header timestamps",
+        (seconds: i64) => |seconds: i64| {
+            let offset = chrono::Duration::try_seconds(seconds)
+                .ok_or_else(|| anyhow::anyhow!("Offset out of range"))?;
+            *clock.lock().unwrap() = Box::new(SystemClock::with_offset(offset));
+            Ok(CommandStatus::Done)
+        }
+    }
+}
+
+fn clock_fixed_cmd<'a>(clock: SharedClock) -> easy_repl::Command<'a> {
+    command! {
+        "Use a fixed Per-Peer Header timestamp (seconds[.fraction] or RFC 3339)",
+        (timestamp: Timestamp) => |timestamp: Timestamp| {
+            *clock.lock().unwrap() = Box::new(FixedClock(timestamp));
+            Ok(CommandStatus::Done)
+        }
+    }
+}
+
+fn clock_simulated_cmd<'a>(clock: SharedClock) -> easy_repl::Command<'a> {
+    command! {
+        "Use Per-Peer Header timestamps that start at the given time and advance by step_ms milliseconds per message",
+        (start: Timestamp, step_ms: i64) => |start: Timestamp, step_ms: i64| {
+            let step = chrono::Duration::try_milliseconds(step_ms)
+                .ok_or_else(|| anyhow::anyhow!("Step out of range"))?;
+            *clock.lock().unwrap() = Box::new(SimulatedClock::new(start, step));
+            Ok(CommandStatus::Done)
+        }
+    }
+}
+
+fn mutate_cmd<'a>(output: SharedOutput) -> easy_repl::Command<'a> {
+    command! {
+        "Break the next message: bmp_length=N, bgp_length=N, truncate=N, corrupt_marker, version=N, duplicate_attribute=TYPE, attribute_flags=TYPE:FLAGS, prefix_length=N or trailing_garbage=HEX",
+        (mutation: Mutation) => |mutation: Mutation| {
+            output.lock().unwrap().mutations.push(mutation);
+            Ok(CommandStatus::Done)
+        }
+    }
+}
+
+fn stations_cmd<'a>(output: SharedOutput) -> easy_repl::Command<'a> {
+    command! {
+        "Show the state of the connection to each monitoring station", () => || {
+            for station in &output.lock().unwrap().stations {
+                eprintln!("{}", station);
+            }
+            Ok(CommandStatus::Done)
+        }
+    }
+}
+
+fn show_cmd<'a>(output: SharedOutput) -> easy_repl::Command<'a> {
+    command! {
+        "Show the peers or routes the monitoring stations were told about", (what: Show) => |what: Show| {
+            let output = output.lock().unwrap();
+            let session = &output.session;
+            match what {
+                Show::Peers => {
+                    if !session.is_initiated() {
+                        eprintln!("No Initiation message sent");
+                    }
+                    for peer in session.peers() {
+                        eprintln!("{}", peer);
+                    }
+                }
+                Show::Routes => {
+                    for peer in session.peers() {
+                        for rib in peer.ribs() {
+                            let policy = if rib.is_post_policy() {
+                                "post"
+                            } else {
+                                "pre"
+                            };
+                            eprintln!(
+                                "{} AS{} {}-policy:",
+                                peer.address(),
+                                peer.asn().into_u32(),
+                                policy
+                            );
+                            for (prefix, route) in rib.routes() {
+                                eprintln!("  {} {}", prefix, route);
+                            }
+                        }
+                    }
+                }
+            }
+            Ok(CommandStatus::Done)
+        }
+    }
+}
+
+fn clear_mutations_cmd<'a>(output: SharedOutput) -> easy_repl::Command<'a> {
+    command! {
+        "Forget the mutations for the next message", () => || {
+            output.lock().unwrap().mutations.clear();
+            Ok(CommandStatus::Done)
+        }
+    }
+}
@@ -0,0 +1,218 @@
+//! The replay mode: send the content of an MRT file or BMP capture.
+
+use std::{
+    fs::File,
+    io::{self, BufReader, Read},
+    str::FromStr,
+    thread,
+    time::{Duration, Instant},
+};
+
+use anyhow::{anyhow, Context};
+use bytes::Bytes;
+
+use routes::bmp::capture::{self, BmpReader};
+use routes::bmp::clock::{Clock, SystemClock, Timestamp};
+use routes::bmp::encode::{mk_initiation_msg, mk_termination_msg};
+use routes::bmp::replay::MrtToBmp;
+use routes::mrt::MrtReader;
+
+use crate::{check_warnings, Output, SharedOutput};
+
+/// How fast to send the messages of a replay.
+#[derive(Clone, Copy, Debug)]
+pub enum Speed {
+    /// As fast as possible.
+    Max,
+
+    /// With the delays between the MRT records divided by this factor.
+    Factor(f64),
+}
+
+impl FromStr for Speed {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "max" {
+            return Ok(Speed::Max);
+        }
+        match s.parse::<f64>() {
+            Ok(factor) if factor.is_finite() && factor > 0.0 => {
+                Ok(Speed::Factor(factor))
+            }
+            _ => Err(anyhow!("Expected a positive number or max")),
+        }
+    }
+}
+
+/// Paces messages according to the timestamps of the records they were made
+/// from.
+struct Pacer {
+    speed: Speed,
+
+    /// The timestamp of the first record and when it was sent.
+    start: Option<(Timestamp, Instant)>,
+}
+
+impl Pacer {
+    fn new(speed: Speed) -> Self {
+        Pacer { speed, start: None }
+    }
+
+    /// Sleeps until it is time to send a record with the given timestamp.
+    ///
+    /// Records with a timestamp before that of the first record are sent
+    /// right away.
+    fn wait(&mut self, ts: Timestamp) {
+        let Speed::Factor(factor) = self.speed else {
+            return;
+        };
+        let (first, started) =
+            *self.start.get_or_insert((ts, Instant::now()));
+        let since_first = micros(ts).saturating_sub(micros(first));
+        let due =
+            started + Duration::from_micros(since_first).div_f64(factor);
+        let now = Instant::now();
+        if due > now {
+            thread::sleep(due - now);
+        }
+    }
+}
+
+fn micros(ts: Timestamp) -> u64 {
+    u64::from(ts.secs) * 1_000_000 + u64::from(ts.micros)
+}
+
+/// The format of the file to replay.
+#[derive(Clone, Copy, Debug)]
+pub enum Format {
+    /// An MRT file, converted into BMP messages.
+    Mrt,
+
+    /// A capture of BMP messages, sent as they are.
+    Bmp,
+}
+
+impl FromStr for Format {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "mrt" => Ok(Format::Mrt),
+            "bmp" => Ok(Format::Bmp),
+            _ => Err(anyhow!("Expected mrt or bmp")),
+        }
+    }
+}
+
+/// How to replay a file.
+pub struct Options {
+    pub format: Format,
+    pub speed: Speed,
+
+    /// Whether to set Per-Peer Header timestamps to the time of sending.
+    pub now: bool,
+
+    pub strict: bool,
+}
+
+/// Replays a file, or standard input if `path` is `-`.
+pub fn run(
+    output: SharedOutput,
+    path: &str,
+    options: Options,
+) -> Result<(), anyhow::Error> {
+    let input: Box<dyn Read> = if path == "-" {
+        Box::new(io::stdin().lock())
+    } else {
+        let file = File::open(path)
+            .with_context(|| format!("Failed to open '{path}'"))?;
+        Box::new(BufReader::new(file))
+    };
+    let mut output = output.lock().unwrap();
+    match options.format {
+        Format::Mrt => replay_mrt(&mut output, input, path, &options),
+        Format::Bmp => replay_bmp(&mut output, input, &options),
+    }
+}
+
+fn replay_mrt(
+    output: &mut Output,
+    input: impl Read,
+    path: &str,
+    options: &Options,
+) -> Result<(), anyhow::Error> {
+    output.send(mk_initiation_msg(
+        "bmp-speaker",
+        &format!("MRT replay of {path}"),
+    )?)?;
+
+    let mut converter = MrtToBmp::new();
+    let mut pacer = Pacer::new(options.speed);
+    let mut records = 0u64;
+    let mut sent = 0u64;
+    for record in MrtReader::new(input) {
+        let record =
+            record.with_context(|| format!("MRT record {}", records + 1))?;
+        records += 1;
+        let messages = converter
+            .convert(&record)
+            .with_context(|| format!("MRT record {records}"))?;
+        pacer.wait(record.timestamp);
+        for res in messages {
+            send(output, check_warnings(Ok(res), options.strict)?, options)?;
+            sent += 1;
+        }
+    }
+    for res in converter.finish()? {
+        send(output, check_warnings(Ok(res), options.strict)?, options)?;
+        sent += 1;
+    }
+
+    output.send(mk_termination_msg()?)?;
+    eprintln!(
+        "Sent {sent} BMP messages for {records} MRT records, skipped {} \
+         unsupported records",
+        converter.skipped()
+    );
+    Ok(())
+}
+
+/// Sends the messages of a capture as they are, including any Initiation
+/// and Termination messages.
+///
+/// Messages are paced by their Per-Peer Header timestamps, messages
+/// without one are sent right after the previous message.
+fn replay_bmp(
+    output: &mut Output,
+    input: impl Read,
+    options: &Options,
+) -> Result<(), anyhow::Error> {
+    // Captures are sent as they are, whatever their order.
+    output.check_sequence = false;
+    let mut pacer = Pacer::new(options.speed);
+    let mut sent = 0u64;
+    for msg in BmpReader::new(input) {
+        let msg = msg.with_context(|| format!("BMP message {}", sent + 1))?;
+        if let Some(ts) = capture::timestamp(&msg) {
+            pacer.wait(ts);
+        }
+        send(output, msg, options)?;
+        sent += 1;
+    }
+    eprintln!("Sent {sent} BMP messages");
+    Ok(())
+}
+
+fn send(
+    output: &mut Output,
+    msg: Bytes,
+    options: &Options,
+) -> Result<(), anyhow::Error> {
+    let msg = if options.now {
+        capture::set_timestamp(msg, SystemClock::new().now()?)
+    } else {
+        msg
+    };
+    output.send(msg)
+}
@@ -0,0 +1,414 @@
+//! The script mode: run the REPL commands in a file, with variables, loops
+//! and delays, and stop at the first error.
+//!
+//! Besides REPL commands, a script knows these statements:
+//!
+//! ```text
+//! # A comment.
+//! let <name> = <value>
+//! repeat <count>
+//!     ...
+//! end
+//! for <name> in <from>..<to> | <prefix> split /<len> | <word> <word>...
+//!     ...
+//! end
+//! sleep <number>ms|s|m
+//! include <file>
+//! ```
+//!
+//! `$name` or `${name}` in a line is replaced by the value of a variable
+//! before the line is run, and `$$` by a single `$`.
+
+use std::{
+    collections::HashMap,
+    fmt, fs,
+    io::{self, Read},
+    path::Path,
+    rc::Rc,
+    thread,
+    time::Duration,
+};
+
+use anyhow::{anyhow, bail, Context};
+use easy_repl::command::ArgsError;
+use routes::bmp::encode::prefix_range;
+
+use crate::{repl, SharedOutput};
+
+/// How deeply `include` may nest, to catch a file including itself.
+const MAX_INCLUDE_DEPTH: usize = 16;
+
+/// Runs a script, or one read from standard input if `path` is `-`.
+pub fn run(
+    output: SharedOutput,
+    path: &str,
+    strict: bool,
+) -> Result<(), anyhow::Error> {
+    let script = if path == "-" {
+        let mut text = String::new();
+        io::stdin().read_to_string(&mut text)?;
+        parse(&text, Rc::from("-"), Path::new("."), 0)?
+    } else {
+        parse_file(Path::new(path), 0)?
+    };
+
+    let mut commands: HashMap<_, Vec<_>> = HashMap::new();
+    for (name, cmd) in repl::commands(output, strict) {
+        commands.entry(name).or_default().push(cmd);
+    }
+    Runner {
+        commands,
+        vars: HashMap::new(),
+    }
+    .run(&script)
+}
+
+//------------ Parsing -------------------------------------------------------
+
+/// Where a statement is in a script, for error messages.
+#[derive(Clone, Debug)]
+struct Location {
+    file: Rc<str>,
+    line: usize,
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+enum Stmt {
+    /// A REPL command.
+    Command {
+        at: Location,
+        text: String,
+    },
+    Let {
+        at: Location,
+        name: String,
+        value: String,
+    },
+    Sleep {
+        at: Location,
+        duration: String,
+    },
+    Repeat {
+        at: Location,
+        count: String,
+        body: Vec<Stmt>,
+    },
+    For {
+        at: Location,
+        name: String,
+        items: String,
+        body: Vec<Stmt>,
+    },
+}
+
+/// A `repeat` or `for` statement waiting for its `end`.
+struct Block {
+    at: Location,
+    head: Head,
+    body: Vec<Stmt>,
+}
+
+enum Head {
+    Repeat { count: String },
+    For { name: String, items: String },
+}
+
+fn parse_file(path: &Path, depth: usize) -> Result<Vec<Stmt>, anyhow::Error> {
+    let text = fs::read_to_string(path)
+        .with_context(|| format!("Failed to read '{}'", path.display()))?;
+    let dir = path.parent().unwrap_or(Path::new("."));
+    parse(&text, Rc::from(path.display().to_string()), dir, depth)
+}
+
+/// Parses a script, with files to include relative to `dir`.
+fn parse(
+    text: &str,
+    file: Rc<str>,
+    dir: &Path,
+    depth: usize,
+) -> Result<Vec<Stmt>, anyhow::Error> {
+    let mut top = vec![];
+    let mut blocks: Vec<Block> = vec![];
+    for (idx, line) in text.lines().enumerate() {
+        let at = Location {
+            file: file.clone(),
+            line: idx + 1,
+        };
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (keyword, rest) = line
+            .split_once(char::is_whitespace)
+            .map_or((line, ""), |(keyword, rest)| (keyword, rest.trim()));
+        let stmt = match keyword {
+            "let" => {
+                let (name, value) =
+                    rest.split_once('=').ok_or_else(|| {
+                        anyhow!("{at}: Expected let <name> = <value>")
+                    })?;
+                Stmt::Let {
+                    name: variable_name(name.trim(), &at)?,
+                    value: value.trim().to_string(),
+                    at,
+                }
+            }
+            "sleep" => Stmt::Sleep {
+                at,
+                duration: rest.to_string(),
+            },
+            "repeat" => {
+                blocks.push(Block {
+                    at,
+                    head: Head::Repeat {
+                        count: rest.to_string(),
+                    },
+                    body: vec![],
+                });
+                continue;
+            }
+            "for" => {
+                let (name, items) =
+                    rest.split_once(" in ").ok_or_else(|| {
+                        anyhow!("{at}: Expected for <name> in <items>")
+                    })?;
+                blocks.push(Block {
+                    head: Head::For {
+                        name: variable_name(name.trim(), &at)?,
+                        items: items.trim().to_string(),
+                    },
+                    at,
+                    body: vec![],
+                });
+                continue;
+            }
+            "end" => {
+                let block = blocks.pop().ok_or_else(|| {
+                    anyhow!("{at}: end without repeat or for")
+                })?;
+                let (at, body) = (block.at, block.body);
+                match block.head {
+                    Head::Repeat { count } => {
+                        Stmt::Repeat { at, count, body }
+                    }
+                    Head::For { name, items } => Stmt::For {
+                        at,
+                        name,
+                        items,
+                        body,
+                    },
+                }
+            }
+            "include" => {
+                if depth >= MAX_INCLUDE_DEPTH {
+                    bail!("{at}: Includes nested too deeply");
+                }
+                let stmts = parse_file(&dir.join(rest), depth + 1)
+                    .with_context(|| at.to_string())?;
+                match blocks.last_mut() {
+                    Some(block) => block.body.extend(stmts),
+                    None => top.extend(stmts),
+                }
+                continue;
+            }
+            _ => Stmt::Command {
+                at,
+                text: line.to_string(),
+            },
+        };
+        match blocks.last_mut() {
+            Some(block) => block.body.push(stmt),
+            None => top.push(stmt),
+        }
+    }
+    if let Some(block) = blocks.pop() {
+        bail!("{}: Missing end", block.at);
+    }
+    Ok(top)
+}
+
+fn variable_name(name: &str, at: &Location) -> Result<String, anyhow::Error> {
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        bail!("{at}: Invalid variable name '{name}'");
+    }
+    Ok(name.to_string())
+}
+
+//------------ Running -------------------------------------------------------
+
+struct Runner<'a> {
+    /// The REPL commands by name, with their overloads.
+    commands: HashMap<&'static str, Vec<easy_repl::Command<'a>>>,
+    vars: HashMap<String, String>,
+}
+
+impl Runner<'_> {
+    fn run(&mut self, stmts: &[Stmt]) -> Result<(), anyhow::Error> {
+        for stmt in stmts {
+            match stmt {
+                Stmt::Command { at, text } => self
+                    .expand(text)
+                    .and_then(|text| self.command(&text))
+                    .with_context(|| at.to_string())?,
+                Stmt::Let { at, name, value } => {
+                    let value =
+                        self.expand(value).with_context(|| at.to_string())?;
+                    self.vars.insert(name.clone(), value);
+                }
+                Stmt::Sleep { at, duration } => {
+                    let duration = self
+                        .expand(duration)
+                        .and_then(|duration| parse_duration(&duration))
+                        .with_context(|| at.to_string())?;
+                    thread::sleep(duration);
+                }
+                Stmt::Repeat { at, count, body } => {
+                    let count = self
+                        .expand(count)
+                        .and_then(|count| {
+                            count.parse::<u64>().map_err(|_| {
+                                anyhow!("Expected a number of repetitions")
+                            })
+                        })
+                        .with_context(|| at.to_string())?;
+                    for _ in 0..count {
+                        self.run(body)?;
+                    }
+                }
+                Stmt::For {
+                    at,
+                    name,
+                    items,
+                    body,
+                } => {
+                    let items = self
+                        .expand(items)
+                        .and_then(|items| parse_items(&items))
+                        .with_context(|| at.to_string())?;
+                    for item in items {
+                        self.vars.insert(name.clone(), item);
+                        self.run(body)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Replaces `$name`, `${name}` and `$$` in a line.
+    fn expand(&self, text: &str) -> Result<String, anyhow::Error> {
+        let mut out = String::with_capacity(text.len());
+        let mut rest = text;
+        while let Some(pos) = rest.find('$') {
+            out.push_str(&rest[..pos]);
+            rest = &rest[pos + 1..];
+            let name = if rest.starts_with('$') {
+                out.push('$');
+                rest = &rest[1..];
+                continue;
+            } else if let Some(braced) = rest.strip_prefix('{') {
+                let end = braced
+                    .find('}')
+                    .ok_or_else(|| anyhow!("Missing }} after ${{"))?;
+                rest = &braced[end + 1..];
+                &braced[..end]
+            } else {
+                let end = rest
+                    .find(|c: char| !c.is_ascii_alphanumeric() && c != '_')
+                    .unwrap_or(rest.len());
+                let name = &rest[..end];
+                rest = &rest[end..];
+                name
+            };
+            let value = self
+                .vars
+                .get(name)
+                .ok_or_else(|| anyhow!("Unknown variable '${name}'"))?;
+            out.push_str(value);
+        }
+        out.push_str(rest);
+        Ok(out)
+    }
+
+    /// Runs a REPL command, trying its overloads in turn like the REPL.
+    fn command(&mut self, text: &str) -> Result<(), anyhow::Error> {
+        let args = shell_words::split(text)?;
+        let Some((name, args)) = args.split_first() else {
+            return Ok(());
+        };
+        let cmds = self
+            .commands
+            .get_mut(name.as_str())
+            .ok_or_else(|| anyhow!("Unknown command '{name}'"))?;
+        let args: Vec<&str> = args.iter().map(String::as_str).collect();
+        let mut args_err = None;
+        for cmd in cmds.iter_mut() {
+            match cmd.run(&args) {
+                Ok(_) => return Ok(()),
+                Err(err) if err.is::<ArgsError>() => args_err = Some(err),
+                Err(err) => return Err(err),
+            }
+        }
+        let usage: Vec<String> = cmds
+            .iter()
+            .map(|cmd| format!("{name} {}", cmd.args_info.join(" ")))
+            .collect();
+        Err(anyhow!(
+            "{}, usage: {}",
+            args_err.unwrap(),
+            usage.join(" or ")
+        ))
+    }
+}
+
+/// Parses a duration such as `500ms`, `2s` or `1.5m`.
+pub fn parse_duration(s: &str) -> Result<Duration, anyhow::Error> {
+    let (number, unit) = s
+        .find(|c: char| !c.is_ascii_digit() && c != '.')
+        .map_or((s, ""), |pos| s.split_at(pos));
+    let number = number
+        .parse::<f64>()
+        .map_err(|_| anyhow!("Expected a duration such as 500ms or 2s"))?;
+    let secs = match unit {
+        "ms" => number / 1000.0,
+        "s" => number,
+        "m" => number * 60.0,
+        _ => bail!("Expected a duration unit of ms, s or m"),
+    };
+    Duration::try_from_secs_f64(secs)
+        .map_err(|_| anyhow!("Duration {s} is out of range"))
+}
+
+/// Parses what a `for` loop iterates over.
+fn parse_items(
+    s: &str,
+) -> Result<Box<dyn Iterator<Item = String>>, anyhow::Error> {
+    if let Some((from, to)) = s.split_once("..") {
+        if let (Ok(from), Ok(to)) =
+            (from.trim().parse::<i64>(), to.trim().parse::<i64>())
+        {
+            return Ok(Box::new((from..=to).map(|i| i.to_string())));
+        }
+    }
+    if s.contains('/')
+        && (s.contains(" split ")
+            || s.contains(" first ")
+            || s.contains(".."))
+    {
+        let prefixes = prefix_range(s)?;
+        return Ok(Box::new(prefixes.into_iter().map(|p| p.to_string())));
+    }
+    let words: Vec<String> =
+        s.split_whitespace().map(str::to_string).collect();
+    Ok(Box::new(words.into_iter()))
+}
@@ -0,0 +1,126 @@
+//! Destinations for the messages sent.
+
+use std::io::{self, Write};
+use std::net::{TcpListener, TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use routes::bmp::clock::{Clock, SystemClock};
+use routes::bmp::record::Recorder;
+
+/// Something that BMP messages can be sent to.
+pub trait Sink: Send {
+    /// Sends a single complete BMP message.
+    fn send(&mut self, msg: &[u8]) -> Result<(), io::Error>;
+
+    /// Replaces a lost connection with a new one.
+    ///
+    /// Sinks that are not connections cannot reconnect.
+    fn reconnect(&mut self) -> Result<(), io::Error> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+/// How long to wait for a station to accept a connection.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// Connects to a monitoring station and writes messages to it.
+///
+/// Each message is flushed right away so that the station sees it as soon
+/// as the command that sent it completes.
+pub struct TcpSink {
+    addr: String,
+    stream: TcpStream,
+}
+
+impl TcpSink {
+    pub fn connect(addr: &str) -> Result<Self, io::Error> {
+        Ok(TcpSink {
+            addr: addr.to_string(),
+            stream: connect(addr)?,
+        })
+    }
+}
+
+impl Sink for TcpSink {
+    fn send(&mut self, msg: &[u8]) -> Result<(), io::Error> {
+        self.stream.write_all(msg)?;
+        self.stream.flush()
+    }
+
+    fn reconnect(&mut self) -> Result<(), io::Error> {
+        self.stream = connect(&self.addr)?;
+        Ok(())
+    }
+}
+
+/// Connects to the first address the name resolves to that accepts the
+/// connection, giving up on each address after [`CONNECT_TIMEOUT`].
+fn connect(addr: &str) -> Result<TcpStream, io::Error> {
+    let mut last_err = None;
+    for addr in addr.to_socket_addrs()? {
+        match TcpStream::connect_timeout(&addr, CONNECT_TIMEOUT) {
+            Ok(stream) => return Ok(stream),
+            Err(err) => last_err = Some(err),
+        }
+    }
+    Err(last_err.unwrap_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "could not resolve to any addresses",
+        )
+    }))
+}
+
+/// Waits for a monitoring station to connect and writes messages to it.
+///
+/// RFC 7854 section 3.2 allows either side to open the connection. To
+/// reconnect, the sink waits for the next station to connect.
+pub struct ListenSink {
+    listener: TcpListener,
+    stream: Option<TcpStream>,
+}
+
+impl ListenSink {
+    pub fn bind(addr: &str) -> Result<Self, io::Error> {
+        let listener = TcpListener::bind(addr)?;
+        eprintln!(
+            "Waiting for a monitoring station to connect to {}",
+            listener.local_addr()?
+        );
+        Ok(ListenSink {
+            listener,
+            stream: None,
+        })
+    }
+
+    /// Waits for the next station to connect.
+    pub fn accept(&mut self) -> Result<(), io::Error> {
+        let (stream, peer) = self.listener.accept()?;
+        eprintln!("Monitoring station {peer} connected");
+        self.stream = Some(stream);
+        Ok(())
+    }
+}
+
+impl Sink for ListenSink {
+    fn send(&mut self, msg: &[u8]) -> Result<(), io::Error> {
+        let Some(stream) = &mut self.stream else {
+            return Err(io::ErrorKind::NotConnected.into());
+        };
+        stream.write_all(msg)
+    }
+
+    fn reconnect(&mut self) -> Result<(), io::Error> {
+        self.stream = None;
+        self.accept()
+    }
+}
+
+/// Writes messages to a file or standard output in one of the capture
+/// formats, with the time they were sent.
+impl<W: Write + Send> Sink for Recorder<W> {
+    fn send(&mut self, msg: &[u8]) -> Result<(), io::Error> {
+        let sent = SystemClock::new().now().map_err(io::Error::other)?;
+        self.record(msg, sent)
+    }
+}
@@ -0,0 +1,229 @@
+//! The monitoring stations messages are sent to and the state of the
+//! connection to each of them.
+
+use std::fmt;
+use std::io;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+
+use routes::bmp::clock::{Clock, SystemClock};
+use routes::bmp::session::Session;
+
+use crate::sink::Sink;
+
+/// The delay before the first attempt to reconnect to a station.
+const MIN_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The longest delay between attempts to reconnect to a station.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// A destination for messages, e.g. one monitoring station.
+pub struct Station {
+    /// The address or file name, for reporting.
+    name: String,
+    sink: Box<dyn Sink>,
+
+    /// Whether to reconnect when the connection is lost.
+    reconnect: bool,
+
+    /// Why and since when the station is unreachable, if it is.
+    down: Option<Down>,
+
+    /// The number of messages sent, not counting replays.
+    sent: u64,
+
+    /// The number of times the connection was established again.
+    reconnects: u64,
+
+    /// The time spent writing messages, including replays, e.g. blocked
+    /// because the station does not keep up.
+    write_time: Duration,
+}
+
+struct Down {
+    since: Instant,
+    error: String,
+
+    /// The delay before the next attempt to reconnect, doubling with each
+    /// failed attempt like a router would.
+    backoff: Duration,
+    retry_at: Instant,
+}
+
+impl Down {
+    fn new(error: String) -> Self {
+        let now = Instant::now();
+        Down {
+            since: now,
+            error,
+            backoff: MIN_BACKOFF,
+            retry_at: now + MIN_BACKOFF,
+        }
+    }
+}
+
+impl Station {
+    pub fn new(
+        name: impl Into<String>,
+        sink: Box<dyn Sink>,
+        reconnect: bool,
+    ) -> Self {
+        Station {
+            name: name.into(),
+            sink,
+            reconnect,
+            down: None,
+            sent: 0,
+            reconnects: 0,
+            write_time: Duration::ZERO,
+        }
+    }
+
+    /// Sends a message to the station.
+    ///
+    /// If the station is unreachable and reconnecting is enabled, the
+    /// station is reconnected to first and brought up to date with the
+    /// session state. With `wait` the attempts to reconnect are repeated
+    /// until one succeeds, otherwise a single attempt is made once the
+    /// backoff delay has passed, so that an unreachable station does not
+    /// hold up the others.
+    pub fn send(
+        &mut self,
+        msg: &[u8],
+        session: &Session,
+        wait: bool,
+    ) -> Result<(), io::Error> {
+        loop {
+            if let Some(down) = &self.down {
+                if !self.reconnect {
+                    return Err(io::Error::new(
+                        io::ErrorKind::NotConnected,
+                        down.error.clone(),
+                    ));
+                }
+                let now = Instant::now();
+                if now < down.retry_at {
+                    if !wait {
+                        return Err(io::ErrorKind::NotConnected.into());
+                    }
+                    sleep(down.retry_at - now);
+                }
+                if let Err(err) = self.resync(session) {
+                    if wait {
+                        continue;
+                    }
+                    return Err(err);
+                }
+            }
+            match self.write(msg) {
+                Ok(()) => {
+                    self.sent += 1;
+                    return Ok(());
+                }
+                Err(err) => {
+                    self.lost(&err);
+                    if !self.reconnect {
+                        return Err(err);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Returns the time spent writing messages to the station.
+    pub fn write_time(&self) -> Duration {
+        self.write_time
+    }
+
+    fn write(&mut self, msg: &[u8]) -> Result<(), io::Error> {
+        let start = Instant::now();
+        let res = self.sink.send(msg);
+        self.write_time += start.elapsed();
+        res
+    }
+
+    /// Marks the station as unreachable.
+    fn lost(&mut self, err: &io::Error) {
+        eprintln!(
+            "Warning: Lost the connection to the monitoring station {}: {}",
+            self.name, err
+        );
+        self.down = Some(Down::new(err.to_string()));
+    }
+
+    /// Makes a single attempt to reconnect, and replays the session state
+    /// if it succeeds.
+    ///
+    /// The station only counts as connected again once the whole replay
+    /// has been written, so that a failed attempt is retried later.
+    fn resync(&mut self, session: &Session) -> Result<(), io::Error> {
+        let Some(down) = &mut self.down else {
+            return Ok(());
+        };
+        let res = SystemClock::new()
+            .now()
+            .and_then(|now| session.replay(now))
+            .map_err(io::Error::other)
+            .and_then(|msgs| self.sink.reconnect().map(|()| msgs));
+        let msgs = match res {
+            Ok(msgs) => msgs,
+            Err(err) => {
+                down.backoff = (down.backoff * 2).min(MAX_BACKOFF);
+                down.retry_at = Instant::now() + down.backoff;
+                eprintln!(
+                    "Warning: Failed to reconnect to {}: {}, retrying in {}s",
+                    self.name,
+                    err,
+                    down.backoff.as_secs()
+                );
+                down.error = err.to_string();
+                return Err(err);
+            }
+        };
+        eprintln!(
+            "Reconnected to {}, replaying {} messages with {} routes",
+            self.name,
+            msgs.len(),
+            session.route_count()
+        );
+        for msg in msgs {
+            if let Err(err) = self.write(msg.as_ref()) {
+                self.lost(&err);
+                return Err(err);
+            }
+        }
+        self.down = None;
+        self.reconnects += 1;
+        Ok(())
+    }
+}
+
+/// Shows the connection state of the station, e.g. for the `stations`
+/// command.
+impl fmt::Display for Station {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}: ", self.name)?;
+        match &self.down {
+            None => write!(f, "connected")?,
+            Some(down) => {
+                write!(
+                    f,
+                    "down for {}s ({})",
+                    down.since.elapsed().as_secs(),
+                    down.error
+                )?;
+                if self.reconnect {
+                    let wait = down
+                        .retry_at
+                        .saturating_duration_since(Instant::now());
+                    write!(f, ", next attempt in {}s", wait.as_secs())?;
+                }
+            }
+        }
+        write!(f, ", messages sent: {}", self.sent)?;
+        if self.reconnects > 0 {
+            write!(f, ", reconnects: {}", self.reconnects)?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,385 @@
+//! Property-based generators for BMP messages.
+//!
+//! The strategies in this module produce valid, but otherwise arbitrary,
+//! inputs for the builders in [`builder`], e.g. to feed a monitoring
+//! station with unusual BMP streams:
+//!
+//! ```no_run
+//! use proptest::prelude::*;
+//! use routes::bmp::arbitrary::bmp_message;
+//!
+//! proptest! {
+//!     #[test]
+//!     fn station_accepts(msg in bmp_message()) {
+//!         let (bytes, _warnings) = msg.build().unwrap();
+//!         // send bytes to the station under test ...
+//!     }
+//! }
+//! ```
+//!
+//! All strategies are composed from the proptest combinators and so shrink
+//! when a test fails: towards fewer prefixes, communities and AS path hops,
+//! towards IPv4 rather than IPv6 and towards smaller numbers, until a
+//! minimal failing message remains.
+//!
+//! This module is only available with the `arbitrary` feature.
+//!
+//! [`builder`]: crate::bmp::builder
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use proptest::collection::vec;
+use proptest::prelude::*;
+use routecore::addr::Prefix;
+use routecore::asn::Asn;
+use routecore::bgp::aspath::HopPath;
+use routecore::bgp::communities::Community;
+use routecore::bgp::types::{NextHop, OriginType};
+use routecore::bmp::message::InformationTlvType;
+
+use crate::bmp::builder::{
+    Initiation, PeerDown, PeerUp, RouteMonitoring, StatisticsReport,
+    Termination,
+};
+use crate::bmp::clock::Timestamp;
+use crate::bmp::encode::{
+    Announcements, EncodeError, Open, PerPeerHeader, Prefixes, Warning,
+};
+
+/// AS_TRANS, used in an OPEN for ASNs that do not fit in 16 bits.
+const AS_TRANS: u16 = 23456;
+
+/// The maximum number of prefixes in a single withdrawal or announcement.
+///
+/// Together with the other limits this keeps a Route Monitoring message
+/// well within the 4096 byte maximum BGP message size.
+const MAX_PREFIXES: usize = 32;
+
+const MAX_COMMUNITIES: usize = 8;
+
+const MAX_AS_PATH_LEN: usize = 8;
+
+const MAX_INFORMATION_TLVS: usize = 4;
+
+//------------ BmpMessage ----------------------------------------------------
+
+/// Any of the BMP messages that can be built with [`builder`].
+///
+/// [`builder`]: crate::bmp::builder
+#[derive(Clone, Debug)]
+pub enum BmpMessage {
+    Initiation(Initiation),
+    PeerUp(PeerUp),
+    RouteMonitoring(RouteMonitoring),
+    StatisticsReport(StatisticsReport),
+    PeerDown(PeerDown),
+    Termination(Termination),
+}
+
+impl BmpMessage {
+    pub fn build(self) -> Result<(Bytes, Vec<Warning>), EncodeError> {
+        match self {
+            BmpMessage::Initiation(msg) => Ok((msg.build()?, vec![])),
+            BmpMessage::PeerUp(msg) => msg.build(),
+            BmpMessage::RouteMonitoring(msg) => msg.build(),
+            BmpMessage::StatisticsReport(msg) => msg.build(),
+            BmpMessage::PeerDown(msg) => msg.build(),
+            BmpMessage::Termination(msg) => Ok((msg.build()?, vec![])),
+        }
+    }
+}
+
+/// Generates any kind of BMP message.
+pub fn bmp_message() -> impl Strategy<Value = BmpMessage> {
+    prop_oneof![
+        initiation().prop_map(BmpMessage::Initiation),
+        peer_up().prop_map(BmpMessage::PeerUp),
+        route_monitoring().prop_map(BmpMessage::RouteMonitoring),
+        statistics_report().prop_map(BmpMessage::StatisticsReport),
+        peer_down().prop_map(BmpMessage::PeerDown),
+        termination().prop_map(BmpMessage::Termination),
+    ]
+}
+
+//------------ Messages ------------------------------------------------------
+
+pub fn initiation() -> impl Strategy<Value = Initiation> {
+    (
+        information_string(),
+        information_string(),
+        vec(information_string(), 0..MAX_INFORMATION_TLVS),
+    )
+        .prop_map(|(sys_name, sys_descr, strings)| {
+            strings.into_iter().fold(
+                Initiation::new().sys_name(sys_name).sys_descr(sys_descr),
+                Initiation::string,
+            )
+        })
+}
+
+/// Generates a Peer Up Notification.
+///
+/// The local address has the same family as the peer address and the
+/// received OPEN carries the peer AS and BGP ID from the Per-Peer Header.
+pub fn peer_up() -> impl Strategy<Value = PeerUp> {
+    per_peer_header()
+        .prop_flat_map(|pph| {
+            let local_address = address_of_family(pph.peer_address);
+            (
+                Just(pph),
+                local_address,
+                any::<u16>(),
+                any::<u16>(),
+                open(),
+                hold_time(),
+                any::<bool>(),
+                vec(information_string(), 0..MAX_INFORMATION_TLVS),
+            )
+        })
+        .prop_map(
+            |(
+                pph,
+                local_address,
+                local_port,
+                remote_port,
+                sent_open,
+                hold_time,
+                eor_capable,
+                strings,
+            )| {
+                let asn =
+                    u16::try_from(pph.peer_as.into_u32()).unwrap_or(AS_TRANS);
+                let received_open =
+                    Open::new(asn, u32::from_be_bytes(pph.peer_bgp_id))
+                        .hold_time(hold_time)
+                        .eor_capable(eor_capable);
+                strings.into_iter().fold(
+                    PeerUp::new(pph)
+                        .local(local_address, local_port)
+                        .remote_port(remote_port)
+                        .sent_open(sent_open)
+                        .received_open(received_open),
+                    |msg, s| msg.information(InformationTlvType::String, s),
+                )
+            },
+        )
+}
+
+/// Generates a Route Monitoring message.
+///
+/// If the A flag is set in the Per-Peer Header, the AS path only contains
+/// 2-byte ASNs so the message does not cause a warning.
+pub fn route_monitoring() -> impl Strategy<Value = RouteMonitoring> {
+    (per_peer_header(), any::<bool>())
+        .prop_flat_map(|(pph, legacy)| {
+            let announcements = prop_oneof![
+                1 => Just(Announcements::None),
+                3 => any::<bool>().prop_flat_map(move |ipv6| {
+                    announcements_for(ipv6, legacy)
+                }),
+            ];
+            (Just(pph.legacy_as_path(legacy)), prefixes(), announcements)
+        })
+        .prop_map(|(pph, withdrawals, announcements)| {
+            RouteMonitoring::new(pph)
+                .withdraw(withdrawals)
+                .announce(announcements)
+        })
+}
+
+pub fn statistics_report() -> impl Strategy<Value = StatisticsReport> {
+    per_peer_header().prop_map(StatisticsReport::new)
+}
+
+/// Generates a Peer Down Notification with any of the reasons defined in
+/// RFC 7854 and the data that goes with it.
+pub fn peer_down() -> impl Strategy<Value = PeerDown> {
+    let reason = prop_oneof![
+        notification().prop_map(|pdu| (1u8, pdu)),
+        any::<u16>().prop_map(|fsm_event| {
+            (2u8, Bytes::copy_from_slice(&fsm_event.to_be_bytes()))
+        }),
+        notification().prop_map(|pdu| (3u8, pdu)),
+        Just((4u8, Bytes::new())),
+        Just((5u8, Bytes::new())),
+    ];
+    (per_peer_header(), reason).prop_map(|(pph, (reason, data))| {
+        PeerDown::new(pph).reason(reason, data)
+    })
+}
+
+pub fn termination() -> impl Strategy<Value = Termination> {
+    Just(Termination::new())
+}
+
+//------------ Message parts -------------------------------------------------
+
+/// Generates a Per-Peer Header for a global instance peer.
+///
+/// The V flag matches the peer address, the L flag is random and the A
+/// flag is never set. The timestamp is always set, so that the encoded
+/// message only depends on the generated value.
+pub fn per_peer_header() -> impl Strategy<Value = PerPeerHeader> {
+    (
+        address(),
+        asn(false),
+        any::<[u8; 4]>(),
+        any::<bool>(),
+        timestamp(),
+    )
+        .prop_map(|(address, asn, bgp_id, post_policy, timestamp)| {
+            PerPeerHeader::new(address, asn)
+                .bgp_id(bgp_id)
+                .post_policy(post_policy)
+                .timestamp(timestamp)
+        })
+}
+
+pub fn timestamp() -> impl Strategy<Value = Timestamp> {
+    (any::<u32>(), 0u32..1_000_000)
+        .prop_map(|(secs, micros)| Timestamp { secs, micros })
+}
+
+/// Generates an OPEN with a hold time of zero or at least three seconds,
+/// as required by RFC 4271.
+pub fn open() -> impl Strategy<Value = Open> {
+    (any::<u16>(), any::<u32>(), hold_time(), any::<bool>()).prop_map(
+        |(asn, bgp_id, hold_time, eor_capable)| {
+            Open::new(asn, bgp_id)
+                .hold_time(hold_time)
+                .eor_capable(eor_capable)
+        },
+    )
+}
+
+/// Generates a mix of IPv4 and IPv6 prefixes, e.g. to withdraw.
+pub fn prefixes() -> impl Strategy<Value = Prefixes> {
+    vec(prefix(), 0..MAX_PREFIXES).prop_map(Prefixes::new)
+}
+
+pub fn prefix() -> impl Strategy<Value = Prefix> {
+    prop_oneof![prefix_v4(), prefix_v6()]
+}
+
+pub fn prefix_v4() -> impl Strategy<Value = Prefix> {
+    (any::<[u8; 4]>(), 0u8..=32).prop_map(|(octets, len)| {
+        Prefix::new_v4_relaxed(Ipv4Addr::from(octets), len)
+            .expect("valid IPv4 prefix length")
+    })
+}
+
+pub fn prefix_v6() -> impl Strategy<Value = Prefix> {
+    (any::<[u8; 16]>(), 0u8..=128).prop_map(|(octets, len)| {
+        Prefix::new_v6_relaxed(Ipv6Addr::from(octets), len)
+            .expect("valid IPv6 prefix length")
+    })
+}
+
+/// Generates announcements of IPv4 or IPv6 prefixes with a next hop of the
+/// same address family.
+pub fn announcements() -> impl Strategy<Value = Announcements> {
+    any::<bool>().prop_flat_map(|ipv6| announcements_for(ipv6, false))
+}
+
+fn announcements_for(
+    ipv6: bool,
+    legacy: bool,
+) -> BoxedStrategy<Announcements> {
+    let (next_hop, prefix) = if ipv6 {
+        (ipv6_address().boxed(), prefix_v6().boxed())
+    } else {
+        (ipv4_address().boxed(), prefix_v4().boxed())
+    };
+    (
+        origin(),
+        vec(asn(legacy), 0..MAX_AS_PATH_LEN),
+        next_hop,
+        vec(community(), 0..MAX_COMMUNITIES),
+        vec(prefix, 1..MAX_PREFIXES),
+    )
+        .prop_map(|(origin, as_path, next_hop, communities, prefixes)| {
+            Announcements::Some {
+                origin: origin.into(),
+                as_path: HopPath::from(as_path).into(),
+                next_hop: NextHop::Unicast(next_hop).into(),
+                communities: communities.into(),
+                prefixes: Prefixes::new(prefixes),
+            }
+        })
+        .boxed()
+}
+
+pub fn origin() -> impl Strategy<Value = OriginType> {
+    prop_oneof![
+        Just(OriginType::Igp),
+        Just(OriginType::Egp),
+        Just(OriginType::Incomplete),
+    ]
+}
+
+/// Generates a standard, extended or large community.
+///
+/// IPv6 extended communities are not generated as the encoder does not
+/// support them.
+pub fn community() -> impl Strategy<Value = Community> {
+    prop_oneof![
+        any::<[u8; 4]>().prop_map(Community::from),
+        any::<[u8; 8]>().prop_map(Community::from),
+        any::<[u8; 12]>().prop_map(Community::from),
+    ]
+}
+
+fn asn(two_byte: bool) -> BoxedStrategy<Asn> {
+    if two_byte {
+        any::<u16>()
+            .prop_map(|asn| Asn::from_u32(asn.into()))
+            .boxed()
+    } else {
+        any::<u32>().prop_map(Asn::from_u32).boxed()
+    }
+}
+
+fn address() -> impl Strategy<Value = IpAddr> {
+    prop_oneof![ipv4_address(), ipv6_address()]
+}
+
+fn address_of_family(addr: IpAddr) -> BoxedStrategy<IpAddr> {
+    match addr {
+        IpAddr::V4(_) => ipv4_address().boxed(),
+        IpAddr::V6(_) => ipv6_address().boxed(),
+    }
+}
+
+fn ipv4_address() -> impl Strategy<Value = IpAddr> {
+    any::<[u8; 4]>().prop_map(|octets| Ipv4Addr::from(octets).into())
+}
+
+fn ipv6_address() -> impl Strategy<Value = IpAddr> {
+    any::<[u8; 16]>().prop_map(|octets| Ipv6Addr::from(octets).into())
+}
+
+fn hold_time() -> impl Strategy<Value = u16> {
+    prop_oneof![Just(0u16), 3u16..]
+}
+
+fn information_string() -> impl Strategy<Value = String> {
+    "[ -~]{0,32}"
+}
+
+/// Generates a BGP NOTIFICATION PDU as carried in a Peer Down Notification
+/// with reason 1 or 3.
+fn notification() -> impl Strategy<Value = Bytes> {
+    (1u8..=6, any::<u8>(), vec(any::<u8>(), 0..16)).prop_map(
+        |(code, subcode, data)| {
+            let mut buf = BytesMut::new();
+            buf.put_slice(&[0xFF; 16]);
+            buf.put_u16(21 + data.len() as u16);
+            buf.put_u8(3);
+            buf.put_u8(code);
+            buf.put_u8(subcode);
+            buf.put_slice(&data);
+            buf.freeze()
+        },
+    )
+}
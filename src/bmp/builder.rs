@@ -0,0 +1,335 @@
+//! Fluent builders for BMP messages.
+//!
+//! These are an alternative to the `mk_*` functions in [`encode`] for
+//! callers that only want to set the fields they care about, e.g.:
+//!
+//! ```
+//! use routecore::asn::Asn;
+//! use routes::bmp::builder::PeerUp;
+//! use routes::bmp::encode::{Open, PerPeerHeader};
+//!
+//! let pph = PerPeerHeader::new("10.0.0.1".parse().unwrap(), Asn::from_u32(65001));
+//! let (bytes, warnings) = PeerUp::new(pph)
+//!     .local("10.0.0.2".parse().unwrap(), 179)
+//!     .sent_open(Open::new(65002, 0x0a000002))
+//!     .build()
+//!     .unwrap();
+//! assert!(warnings.is_empty());
+//! ```
+//!
+//! [`encode`]: crate::bmp::encode
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use bytes::Bytes;
+use routecore::bmp::message::InformationTlvType;
+
+use crate::bmp::encode::{
+    mk_bgp_update, mk_initiation_msg_with_strings, mk_peer_down_msg,
+    mk_peer_up_msg, mk_raw_route_monitoring_msg, mk_route_monitoring_msgs,
+    mk_statistics_report_msg, mk_termination_msg, pack_announcements,
+    Announcements, EncodeError, Open, PerPeerHeader, Prefixes, Warning,
+};
+
+/// AS_TRANS, used in an OPEN for ASNs that do not fit in 16 bits.
+const AS_TRANS: u16 = 23456;
+
+/// The well-known BGP port.
+const BGP_PORT: u16 = 179;
+
+//------------ Initiation ----------------------------------------------------
+
+/// Builds an Initiation message.
+///
+/// Unless set, sysName is "bmp-speaker" and sysDescr is empty.
+#[derive(Clone, Debug)]
+pub struct Initiation {
+    sys_name: String,
+    sys_descr: String,
+    strings: Vec<String>,
+}
+
+impl Initiation {
+    pub fn new() -> Self {
+        Initiation {
+            sys_name: "bmp-speaker".to_string(),
+            sys_descr: String::new(),
+            strings: vec![],
+        }
+    }
+
+    pub fn sys_name(mut self, sys_name: impl Into<String>) -> Self {
+        self.sys_name = sys_name.into();
+        self
+    }
+
+    pub fn sys_descr(mut self, sys_descr: impl Into<String>) -> Self {
+        self.sys_descr = sys_descr.into();
+        self
+    }
+
+    /// Adds a String Information TLV, may be used multiple times.
+    pub fn string(mut self, string: impl Into<String>) -> Self {
+        self.strings.push(string.into());
+        self
+    }
+
+    pub fn build(self) -> Result<Bytes, EncodeError> {
+        mk_initiation_msg_with_strings(
+            &self.sys_name,
+            &self.sys_descr,
+            &self.strings,
+        )
+    }
+}
+
+impl Default for Initiation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+//------------ PeerUp --------------------------------------------------------
+
+/// Builds a Peer Up Notification message.
+///
+/// Unless set, the session looks like an iBGP session from the loopback
+/// address on the well-known BGP port, i.e.:
+///
+/// - the local address is the loopback address of the peer address family,
+///   the local port is 179 and the remote port is 0.
+/// - the received OPEN carries the peer AS (or AS_TRANS if it does not fit
+///   in 16 bits) and the peer BGP ID from the Per-Peer Header, and
+///   advertises the End-of-RIB capability, as with the
+///   `peer_up_notification` REPL command.
+/// - the sent OPEN carries the same AS and BGP ID 127.0.0.1, without
+///   capabilities.
+#[derive(Clone, Debug)]
+pub struct PeerUp {
+    per_peer_header: PerPeerHeader,
+    local_address: IpAddr,
+    local_port: u16,
+    remote_port: u16,
+    sent_open: Open,
+    received_open: Open,
+    information_tlvs: Vec<(InformationTlvType, String)>,
+}
+
+impl PeerUp {
+    pub fn new(per_peer_header: PerPeerHeader) -> Self {
+        let local_address = match per_peer_header.peer_address {
+            IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+            IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+        };
+        let asn = u16::try_from(per_peer_header.peer_as.into_u32())
+            .unwrap_or(AS_TRANS);
+        let received_open =
+            Open::new(asn, u32::from_be_bytes(per_peer_header.peer_bgp_id))
+                .eor_capable(true);
+        let sent_open = Open::new(asn, u32::from(Ipv4Addr::LOCALHOST));
+        PeerUp {
+            per_peer_header,
+            local_address,
+            local_port: BGP_PORT,
+            remote_port: 0,
+            sent_open,
+            received_open,
+            information_tlvs: vec![],
+        }
+    }
+
+    pub fn local(mut self, local_address: IpAddr, local_port: u16) -> Self {
+        self.local_address = local_address;
+        self.local_port = local_port;
+        self
+    }
+
+    pub fn remote_port(mut self, remote_port: u16) -> Self {
+        self.remote_port = remote_port;
+        self
+    }
+
+    pub fn sent_open(mut self, open: Open) -> Self {
+        self.sent_open = open;
+        self
+    }
+
+    pub fn received_open(mut self, open: Open) -> Self {
+        self.received_open = open;
+        self
+    }
+
+    /// Adds an Information TLV, may be used multiple times.
+    pub fn information(
+        mut self,
+        typ: InformationTlvType,
+        value: impl Into<String>,
+    ) -> Self {
+        self.information_tlvs.push((typ, value.into()));
+        self
+    }
+
+    pub fn build(self) -> Result<(Bytes, Vec<Warning>), EncodeError> {
+        mk_peer_up_msg(
+            &self.per_peer_header,
+            self.local_address,
+            self.local_port,
+            self.remote_port,
+            &self.sent_open,
+            &self.received_open,
+            &self.information_tlvs,
+        )
+    }
+}
+
+//------------ RouteMonitoring -----------------------------------------------
+
+/// Builds a Route Monitoring message.
+///
+/// Unless set, nothing is withdrawn or announced, i.e. the BGP UPDATE is an
+/// IPv4 unicast End-of-RIB marker.
+#[derive(Clone, Debug)]
+pub struct RouteMonitoring {
+    per_peer_header: PerPeerHeader,
+    withdrawals: Prefixes,
+    announcements: Vec<Announcements>,
+    extra_path_attributes: Vec<u8>,
+}
+
+impl RouteMonitoring {
+    pub fn new(per_peer_header: PerPeerHeader) -> Self {
+        RouteMonitoring {
+            per_peer_header,
+            withdrawals: Prefixes::default(),
+            announcements: vec![],
+            extra_path_attributes: vec![],
+        }
+    }
+
+    pub fn withdraw(mut self, withdrawals: Prefixes) -> Self {
+        self.withdrawals = withdrawals;
+        self
+    }
+
+    /// Adds announcements, possibly with other path attributes than those
+    /// added before.
+    ///
+    /// Announcements with the same path attributes are merged, and a prefix
+    /// announced again is only announced with its last path attributes.
+    pub fn announce(mut self, announcements: Announcements) -> Self {
+        self.announcements.push(announcements);
+        self
+    }
+
+    /// Appends already encoded path attributes to the BGP UPDATE.
+    pub fn extra_path_attributes(mut self, attributes: &[u8]) -> Self {
+        self.extra_path_attributes.extend_from_slice(attributes);
+        self
+    }
+
+    /// Builds a single Route Monitoring message.
+    ///
+    /// Fails with [`EncodeError::MultipleAttributeSets`] if the
+    /// announcements have different path attributes, use
+    /// [`build_split`](Self::build_split) for those.
+    pub fn build(self) -> Result<(Bytes, Vec<Warning>), EncodeError> {
+        let mut groups = pack_announcements(&self.announcements);
+        if groups.len() > 1 {
+            return Err(EncodeError::MultipleAttributeSets(groups.len()));
+        }
+        let announcements = groups.pop().unwrap_or_default();
+        let (bgp_msg_buf, mut warnings) = mk_bgp_update(
+            &self.per_peer_header,
+            &self.withdrawals,
+            &announcements,
+            &self.extra_path_attributes,
+        )?;
+        let (bytes, mut more_warnings) =
+            mk_raw_route_monitoring_msg(&self.per_peer_header, bgp_msg_buf)?;
+        warnings.append(&mut more_warnings);
+        Ok((bytes, warnings))
+    }
+
+    /// Builds as many Route Monitoring messages as it takes to announce
+    /// each set of path attributes and keep each BGP UPDATE within the 4096
+    /// octet maximum, see [`mk_route_monitoring_msgs`].
+    pub fn build_split(
+        self,
+    ) -> Result<(Vec<Bytes>, Vec<Warning>), EncodeError> {
+        mk_route_monitoring_msgs(
+            &self.per_peer_header,
+            &self.withdrawals,
+            &self.announcements,
+            &self.extra_path_attributes,
+        )
+    }
+}
+
+//------------ StatisticsReport ----------------------------------------------
+
+/// Builds a Statistics Report message without any counters.
+#[derive(Clone, Debug)]
+pub struct StatisticsReport {
+    per_peer_header: PerPeerHeader,
+}
+
+impl StatisticsReport {
+    pub fn new(per_peer_header: PerPeerHeader) -> Self {
+        StatisticsReport { per_peer_header }
+    }
+
+    pub fn build(self) -> Result<(Bytes, Vec<Warning>), EncodeError> {
+        mk_statistics_report_msg(&self.per_peer_header)
+    }
+}
+
+//------------ PeerDown ------------------------------------------------------
+
+/// Builds a Peer Down Notification message.
+///
+/// Unless set, the reason is 5: the peer was de-configured, which carries
+/// no data.
+#[derive(Clone, Debug)]
+pub struct PeerDown {
+    per_peer_header: PerPeerHeader,
+    reason: u8,
+    data: Bytes,
+}
+
+impl PeerDown {
+    pub fn new(per_peer_header: PerPeerHeader) -> Self {
+        PeerDown {
+            per_peer_header,
+            reason: 5,
+            data: Bytes::new(),
+        }
+    }
+
+    /// Sets the reason code and the data that goes with it, e.g. a BGP
+    /// NOTIFICATION PDU for reasons 1 and 3.
+    pub fn reason(mut self, reason: u8, data: Bytes) -> Self {
+        self.reason = reason;
+        self.data = data;
+        self
+    }
+
+    pub fn build(self) -> Result<(Bytes, Vec<Warning>), EncodeError> {
+        mk_peer_down_msg(&self.per_peer_header, self.reason, &self.data)
+    }
+}
+
+//------------ Termination ---------------------------------------------------
+
+/// Builds a Termination message with reason "administratively closed".
+#[derive(Clone, Debug, Default)]
+pub struct Termination;
+
+impl Termination {
+    pub fn new() -> Self {
+        Termination
+    }
+
+    pub fn build(self) -> Result<Bytes, EncodeError> {
+        mk_termination_msg()
+    }
+}
@@ -0,0 +1,130 @@
+//! Reading of BMP capture files.
+//!
+//! A capture is a file of BMP messages back to back, as received from a
+//! router or written by [`Recorder`] in the [`RecordFormat::Raw`] format.
+//! [`BmpReader`] splits it into messages and [`timestamp`] and
+//! [`set_timestamp`] give access to the Per-Peer Header timestamp of a
+//! message, e.g. to pace or re-time a replay.
+//!
+//! [`Recorder`]: crate::bmp::record::Recorder
+//! [`RecordFormat::Raw`]: crate::bmp::record::RecordFormat::Raw
+
+use std::io::{self, Read};
+
+use bytes::Bytes;
+use routecore::bmp::message::MessageType;
+
+use crate::bmp::clock::Timestamp;
+
+/// The length of the BMP common header.
+const COMMON_HEADER_LEN: usize = 6;
+
+/// The offset of the Per-Peer Header timestamp: after the common header
+/// and the peer type, flags, distinguisher, address, AS and BGP ID.
+const TIMESTAMP_OFFSET: usize = COMMON_HEADER_LEN + 34;
+
+//------------ BmpReader -----------------------------------------------------
+
+/// Reads BMP messages from a capture file or stream.
+///
+/// Messages are split by the length in their common header only, so a
+/// capture of deliberately malformed messages is read as it was sent as
+/// long as the lengths are right. A truncated last message is reported as
+/// an [`io::ErrorKind::UnexpectedEof`] error.
+pub struct BmpReader<R> {
+    input: R,
+}
+
+impl<R: Read> BmpReader<R> {
+    pub fn new(input: R) -> Self {
+        BmpReader { input }
+    }
+
+    /// Reads the next message, or returns `None` at the end of the input.
+    pub fn read_message(&mut self) -> Result<Option<Bytes>, io::Error> {
+        let mut header = [0u8; COMMON_HEADER_LEN];
+        let mut filled = 0;
+        while filled < header.len() {
+            match self.input.read(&mut header[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+                Ok(n) => filled += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err),
+            }
+        }
+        let len =
+            u32::from_be_bytes([header[1], header[2], header[3], header[4]])
+                as usize;
+        if len < COMMON_HEADER_LEN {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!(
+                    "BMP message length {len} is shorter than its header"
+                ),
+            ));
+        }
+        let mut msg = vec![0u8; len];
+        msg[..COMMON_HEADER_LEN].copy_from_slice(&header);
+        self.input.read_exact(&mut msg[COMMON_HEADER_LEN..])?;
+        Ok(Some(msg.into()))
+    }
+}
+
+impl<R: Read> Iterator for BmpReader<R> {
+    type Item = Result<Bytes, io::Error>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_message().transpose()
+    }
+}
+
+//------------ Per-Peer Header timestamps ------------------------------------
+
+/// Returns whether a message of this type starts with a Per-Peer Header.
+fn has_per_peer_header(msg: &[u8]) -> bool {
+    msg.len() >= TIMESTAMP_OFFSET + 8
+        && matches!(
+            MessageType::from(msg[5]),
+            MessageType::RouteMonitoring
+                | MessageType::StatisticsReport
+                | MessageType::PeerDownNotification
+                | MessageType::PeerUpNotification
+                | MessageType::RouteMirroring
+        )
+}
+
+/// Returns the Per-Peer Header timestamp of a message.
+///
+/// Returns `None` for messages without a Per-Peer Header, for a zero
+/// timestamp, which means that the time is unavailable, and for a timestamp
+/// with invalid microseconds.
+pub fn timestamp(msg: &[u8]) -> Option<Timestamp> {
+    if !has_per_peer_header(msg) {
+        return None;
+    }
+    let field = |pos: usize| {
+        u32::from_be_bytes(msg[pos..pos + 4].try_into().unwrap())
+    };
+    let ts = Timestamp::try_new(
+        field(TIMESTAMP_OFFSET),
+        field(TIMESTAMP_OFFSET + 4),
+    )
+    .ok()?;
+    (ts != Timestamp::default()).then_some(ts)
+}
+
+/// Returns the message with its Per-Peer Header timestamp replaced.
+///
+/// Messages without a Per-Peer Header are returned unchanged.
+pub fn set_timestamp(msg: Bytes, ts: Timestamp) -> Bytes {
+    if !has_per_peer_header(&msg) {
+        return msg;
+    }
+    let mut buf = msg.to_vec();
+    buf[TIMESTAMP_OFFSET..TIMESTAMP_OFFSET + 4]
+        .copy_from_slice(&ts.secs.to_be_bytes());
+    buf[TIMESTAMP_OFFSET + 4..TIMESTAMP_OFFSET + 8]
+        .copy_from_slice(&ts.micros.to_be_bytes());
+    buf.into()
+}
@@ -0,0 +1,650 @@
+//! Steady-state route churn on top of a synthetic table.
+//!
+//! After a router has sent its table to a monitoring station, the station
+//! mostly processes churn: new prefixes, paths that change, prefixes that
+//! go away and prefixes that flap. [`Churn`] produces this for a number of
+//! peers that each start out with all routes of a [`Table`]. It tracks the
+//! routes each peer currently announces, so that only announced prefixes
+//! are withdrawn or replaced and only withdrawn ones announced again.
+//!
+//! Route events arrive as a Poisson process at a configured rate and are
+//! spread over the peers. Independently of these, a peer may lose a share
+//! of its table at once, e.g. when a transit session goes down, and learn
+//! it again a while later. The same seed always gives the same events.
+//!
+//! ```
+//! use std::time::Duration;
+//! use routecore::asn::Asn;
+//! use routes::bmp::churn::{Churn, ChurnConfig};
+//! use routes::bmp::encode::PerPeerHeader;
+//! use routes::bmp::table::{Table, TableConfig};
+//!
+//! let table = Table::generate(&TableConfig {
+//!     ipv4_prefixes: 1000,
+//!     ipv6_prefixes: 100,
+//!     seed: 1,
+//! });
+//! let address = [10, 0, 0, 1].into();
+//! let pph = PerPeerHeader::new(address, Asn::from_u32(65001));
+//! let churn = Churn::new(&table, 1, ChurnConfig::default());
+//! for event in churn.take_while(|event| event.at < Duration::from_secs(60))
+//! {
+//!     let (messages, _warnings) = event
+//!         .messages(&pph, address, "fd00::1".parse().unwrap())
+//!         .unwrap();
+//!     // send the messages at event.at ...
+//! }
+//! ```
+
+use std::cmp::Reverse;
+use std::collections::BinaryHeap;
+use std::fmt;
+use std::net::IpAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use bytes::Bytes;
+use routecore::addr::Prefix;
+use routecore::asn::Asn;
+
+use crate::bmp::builder::RouteMonitoring;
+use crate::bmp::encode::{EncodeError, PerPeerHeader, Prefixes, Warning};
+use crate::bmp::table::{
+    group_by_attributes, PathAttributes, Rng, Route, Table,
+};
+
+//------------ ChurnConfig ---------------------------------------------------
+
+/// How much churn to produce.
+#[derive(Clone, Copy, Debug)]
+pub struct ChurnConfig {
+    /// The mean number of route events per second over all peers.
+    pub rate: f64,
+
+    /// How the route events divide into kinds.
+    pub mix: EventMix,
+
+    /// The mean time a flapping route stays withdrawn or announced.
+    pub flap_interval: Duration,
+
+    /// How often a flapping route is withdrawn and announced again.
+    pub flap_count: u32,
+
+    /// The mean time between bursts, or `None` or zero for no bursts.
+    pub burst_interval: Option<Duration>,
+
+    /// The share of its routes, from 0 to 1, that a peer withdraws in a
+    /// burst.
+    pub burst_share: f64,
+
+    /// The mean time until a peer announces the routes of a burst again.
+    pub burst_recovery: Duration,
+
+    pub seed: u64,
+}
+
+impl Default for ChurnConfig {
+    fn default() -> Self {
+        ChurnConfig {
+            rate: 10.0,
+            mix: EventMix::default(),
+            flap_interval: Duration::from_secs(10),
+            flap_count: 5,
+            burst_interval: None,
+            burst_share: 0.5,
+            burst_recovery: Duration::from_secs(60),
+            seed: 0,
+        }
+    }
+}
+
+//------------ EventMix ------------------------------------------------------
+
+/// The relative weights of the kinds of route events.
+///
+/// Parses from a comma separated list such as `replace=60,flap=5`, where
+/// kinds not mentioned keep their default weight.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct EventMix {
+    /// Announcing a prefix that is not announced.
+    pub announce: u32,
+
+    /// Announcing a prefix with another path, an implicit withdrawal of
+    /// the old one.
+    pub replace: u32,
+
+    /// Withdrawing a prefix.
+    pub withdraw: u32,
+
+    /// Withdrawing and announcing a prefix several times.
+    pub flap: u32,
+}
+
+impl Default for EventMix {
+    fn default() -> Self {
+        EventMix {
+            announce: 20,
+            replace: 50,
+            withdraw: 20,
+            flap: 10,
+        }
+    }
+}
+
+impl FromStr for EventMix {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut mix = EventMix::default();
+        for item in s.split(',') {
+            let (kind, weight) = item.split_once('=').ok_or_else(|| {
+                anyhow!("Expected <kind>=<weight>, e.g. replace=50")
+            })?;
+            let weight = weight
+                .trim()
+                .parse()
+                .map_err(|_| anyhow!("Expected a whole number weight"))?;
+            match kind.trim() {
+                "announce" => mix.announce = weight,
+                "replace" => mix.replace = weight,
+                "withdraw" => mix.withdraw = weight,
+                "flap" => mix.flap = weight,
+                _ => {
+                    return Err(anyhow!(
+                        "Expected announce, replace, withdraw or flap"
+                    ))
+                }
+            }
+        }
+        Ok(mix)
+    }
+}
+
+//------------ EventKind -----------------------------------------------------
+
+/// What happened in a [`ChurnEvent`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum EventKind {
+    Announce,
+    Replace,
+    Withdraw,
+
+    /// A flapping route is withdrawn.
+    FlapDown,
+
+    /// A flapping route is announced again.
+    FlapUp,
+
+    /// A peer withdraws a share of its routes.
+    Burst,
+
+    /// A peer announces the routes of a burst again.
+    Recovery,
+}
+
+impl fmt::Display for EventKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            EventKind::Announce => "announce",
+            EventKind::Replace => "replace",
+            EventKind::Withdraw => "withdraw",
+            EventKind::FlapDown => "flap down",
+            EventKind::FlapUp => "flap up",
+            EventKind::Burst => "burst",
+            EventKind::Recovery => "recovery",
+        })
+    }
+}
+
+//------------ ChurnEvent ----------------------------------------------------
+
+/// Routes that a peer withdraws or announces at some point in time.
+#[derive(Clone, Debug)]
+pub struct ChurnEvent {
+    /// The time since the churn started.
+    pub at: Duration,
+
+    /// The index of the peer.
+    pub peer: usize,
+
+    pub kind: EventKind,
+    pub withdrawals: Vec<Prefix>,
+
+    /// The announced prefixes, grouped by address family and path
+    /// attributes.
+    pub announcements: Vec<(Arc<PathAttributes>, Vec<Prefix>)>,
+}
+
+impl ChurnEvent {
+    /// Returns the Route Monitoring messages for the event, with the peer
+    /// ASN of the Per-Peer Header in front of the AS paths, and the
+    /// warnings about them without repeats.
+    pub fn messages(
+        &self,
+        per_peer_header: &PerPeerHeader,
+        ipv4_next_hop: IpAddr,
+        ipv6_next_hop: IpAddr,
+    ) -> Result<(Vec<Bytes>, Vec<Warning>), EncodeError> {
+        let mut builder = RouteMonitoring::new(per_peer_header.clone())
+            .withdraw(Prefixes::new(self.withdrawals.clone()));
+        for (attributes, prefixes) in &self.announcements {
+            let next_hop = if prefixes[0].is_v4() {
+                ipv4_next_hop
+            } else {
+                ipv6_next_hop
+            };
+            builder = builder.announce(attributes.announcements(
+                per_peer_header.peer_as,
+                next_hop,
+                prefixes.clone(),
+            ));
+        }
+        builder.build_split()
+    }
+}
+
+//------------ Churn ---------------------------------------------------------
+
+/// An endless sequence of churn events in time order.
+pub struct Churn {
+    config: ChurnConfig,
+    rng: Rng,
+
+    /// The routes of the table, by which the peers start out.
+    routes: Vec<Route>,
+    peers: Vec<PeerRib>,
+
+    /// The mean time between route events, if there are any.
+    event_interval: Option<Duration>,
+
+    /// The time of the next route event, if there are any.
+    next_event: Option<Duration>,
+
+    /// The time of the next burst, if there are any.
+    next_burst: Option<Duration>,
+
+    /// Events following from earlier ones: flaps and recoveries.
+    scheduled: BinaryHeap<Reverse<Scheduled>>,
+
+    /// Keeps scheduled events due at the same time in order.
+    sequence: u64,
+}
+
+impl Churn {
+    /// Creates churn for `peers` peers that each announce all routes of
+    /// `table`.
+    pub fn new(table: &Table, peers: usize, config: ChurnConfig) -> Self {
+        let routes = table.routes().to_vec();
+        let active = peers > 0 && !routes.is_empty();
+        let mix = config.mix;
+        let mut rng = Rng::new(config.seed);
+        // A rate of zero, or one so small that the mean time between events
+        // doesn't fit a Duration, means no route events at all.
+        let event_interval = Duration::try_from_secs_f64(1.0 / config.rate)
+            .ok()
+            .filter(|_| {
+                active
+                    && mix.announce + mix.replace + mix.withdraw + mix.flap
+                        > 0
+            });
+        let next_event =
+            event_interval.map(|interval| rng.exponential(interval));
+        // With a zero interval every burst would be due at once, so time
+        // would never move on to the recoveries.
+        let next_burst = config
+            .burst_interval
+            .filter(|interval| {
+                !interval.is_zero() && active && config.burst_share > 0.0
+            })
+            .map(|interval| rng.exponential(interval));
+        Churn {
+            peers: (0..peers).map(|_| PeerRib::new(&routes)).collect(),
+            config,
+            rng,
+            routes,
+            event_interval,
+            next_event,
+            next_burst,
+            scheduled: BinaryHeap::new(),
+            sequence: 0,
+        }
+    }
+
+    /// Returns the routes a peer currently announces, in prefix order.
+    pub fn routes(
+        &self,
+        peer: usize,
+    ) -> impl Iterator<Item = (Prefix, &Arc<PathAttributes>)> + '_ {
+        let rib = &self.peers[peer];
+        self.routes
+            .iter()
+            .enumerate()
+            .filter(|(idx, _)| rib.is_announced[*idx])
+            .map(|(idx, route)| (route.prefix, &rib.attributes[idx]))
+    }
+
+    fn schedule(&mut self, at: Duration, peer: usize, action: Action) {
+        self.sequence += 1;
+        self.scheduled.push(Reverse(Scheduled {
+            at,
+            sequence: self.sequence,
+            peer,
+            action,
+        }));
+    }
+
+    fn event(
+        &self,
+        at: Duration,
+        peer: usize,
+        kind: EventKind,
+        withdrawn: &[usize],
+        announced: &[usize],
+    ) -> ChurnEvent {
+        let rib = &self.peers[peer];
+        ChurnEvent {
+            at,
+            peer,
+            kind,
+            withdrawals: withdrawn
+                .iter()
+                .map(|idx| self.routes[*idx].prefix)
+                .collect(),
+            announcements: group_by_attributes(announced.iter().map(|idx| {
+                (self.routes[*idx].prefix, &rib.attributes[*idx])
+            })),
+        }
+    }
+
+    /// Produces a route event for a random peer.
+    fn route_event(&mut self, at: Duration) -> Option<ChurnEvent> {
+        let peer = self.rng.below(self.peers.len() as u64) as usize;
+        let mix = self.config.mix;
+        let mut kind = self.rng.weighted(&[
+            (EventKind::Announce, mix.announce),
+            (EventKind::Replace, mix.replace),
+            (EventKind::Withdraw, mix.withdraw),
+            (EventKind::FlapDown, mix.flap),
+        ]);
+        let rib = &self.peers[peer];
+        if kind == EventKind::Announce && rib.withdrawn.is_empty() {
+            kind = EventKind::Replace;
+        } else if kind != EventKind::Announce && rib.announced.is_empty() {
+            kind = EventKind::Announce;
+        }
+
+        let rib = &mut self.peers[peer];
+        match kind {
+            EventKind::Announce => {
+                let idx = rib.withdrawn.take(&mut self.rng)?;
+                rib.attributes[idx] = self.routes[idx].attributes.clone();
+                rib.announce(idx);
+                Some(self.event(at, peer, kind, &[], &[idx]))
+            }
+            EventKind::Replace => {
+                let idx = rib.announced.pick(&mut self.rng)?;
+                let donor = self.rng.below(self.routes.len() as u64) as usize;
+                rib.attributes[idx] = Arc::new(reroute(
+                    &rib.attributes[idx],
+                    &self.routes[donor].attributes,
+                ));
+                Some(self.event(at, peer, kind, &[], &[idx]))
+            }
+            EventKind::Withdraw => {
+                let idx = rib.announced.take(&mut self.rng)?;
+                rib.is_announced[idx] = false;
+                rib.withdrawn.insert(idx);
+                Some(self.event(at, peer, kind, &[idx], &[]))
+            }
+            _ => {
+                let idx = rib.announced.take(&mut self.rng)?;
+                rib.is_announced[idx] = false;
+                let up = at.saturating_add(
+                    self.rng.exponential(self.config.flap_interval),
+                );
+                let remaining = self.config.flap_count.max(1);
+                self.schedule(up, peer, Action::FlapUp(idx, remaining));
+                Some(self.event(at, peer, kind, &[idx], &[]))
+            }
+        }
+    }
+
+    /// Withdraws a share of the routes of a random peer.
+    fn burst(&mut self, at: Duration) -> Option<ChurnEvent> {
+        let peer = self.rng.below(self.peers.len() as u64) as usize;
+        let rib = &mut self.peers[peer];
+        let count = (rib.announced.len() as f64 * self.config.burst_share)
+            .ceil() as usize;
+        let mut lost: Vec<usize> = (0..count)
+            .filter_map(|_| rib.announced.take(&mut self.rng))
+            .collect();
+        if lost.is_empty() {
+            return None;
+        }
+        lost.sort_unstable();
+        for idx in &lost {
+            rib.is_announced[*idx] = false;
+        }
+        let event = self.event(at, peer, EventKind::Burst, &lost, &[]);
+        let up = at
+            .saturating_add(self.rng.exponential(self.config.burst_recovery));
+        self.schedule(up, peer, Action::Recover(lost));
+        Some(event)
+    }
+
+    fn scheduled_event(&mut self, scheduled: Scheduled) -> ChurnEvent {
+        let Scheduled {
+            at, peer, action, ..
+        } = scheduled;
+        match action {
+            Action::FlapUp(idx, remaining) => {
+                self.peers[peer].is_announced[idx] = true;
+                if remaining > 1 {
+                    let down = at.saturating_add(
+                        self.rng.exponential(self.config.flap_interval),
+                    );
+                    self.schedule(
+                        down,
+                        peer,
+                        Action::FlapDown(idx, remaining - 1),
+                    );
+                } else {
+                    self.peers[peer].announced.insert(idx);
+                }
+                self.event(at, peer, EventKind::FlapUp, &[], &[idx])
+            }
+            Action::FlapDown(idx, remaining) => {
+                self.peers[peer].is_announced[idx] = false;
+                let up = at.saturating_add(
+                    self.rng.exponential(self.config.flap_interval),
+                );
+                self.schedule(up, peer, Action::FlapUp(idx, remaining));
+                self.event(at, peer, EventKind::FlapDown, &[idx], &[])
+            }
+            Action::Recover(lost) => {
+                for idx in &lost {
+                    self.peers[peer].announce(*idx);
+                }
+                self.event(at, peer, EventKind::Recovery, &[], &lost)
+            }
+        }
+    }
+}
+
+impl Iterator for Churn {
+    type Item = ChurnEvent;
+
+    fn next(&mut self) -> Option<ChurnEvent> {
+        loop {
+            let scheduled = self.scheduled.peek().map(|s| s.0.at);
+            let next = [scheduled, self.next_burst, self.next_event]
+                .into_iter()
+                .flatten()
+                .min()?;
+            if scheduled == Some(next) {
+                let scheduled = self.scheduled.pop().unwrap().0;
+                return Some(self.scheduled_event(scheduled));
+            }
+            if self.next_burst == Some(next) {
+                let interval = self.config.burst_interval.unwrap();
+                self.next_burst =
+                    Some(next.saturating_add(self.rng.exponential(interval)));
+                if let Some(event) = self.burst(next) {
+                    return Some(event);
+                }
+            } else {
+                self.next_event = self.event_interval.map(|mean| {
+                    next.saturating_add(self.rng.exponential(mean))
+                });
+                if let Some(event) = self.route_event(next) {
+                    return Some(event);
+                }
+            }
+        }
+    }
+}
+
+//------------ Scheduled -----------------------------------------------------
+
+/// An event due at a later time.
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Scheduled {
+    at: Duration,
+    sequence: u64,
+    peer: usize,
+    action: Action,
+}
+
+#[derive(Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Action {
+    /// Announce a flapping route, which then flaps this many more times.
+    FlapUp(usize, u32),
+
+    /// Withdraw a flapping route, which then flaps this many more times.
+    FlapDown(usize, u32),
+
+    /// Announce the routes lost in a burst.
+    Recover(Vec<usize>),
+}
+
+//------------ PeerRib -------------------------------------------------------
+
+/// The routes of a peer, by their index in the table.
+struct PeerRib {
+    /// The current path attributes of each route.
+    attributes: Vec<Arc<PathAttributes>>,
+    is_announced: Vec<bool>,
+
+    /// The announced and withdrawn routes that no flap or burst holds on
+    /// to, from which route events pick.
+    announced: Pool,
+    withdrawn: Pool,
+}
+
+impl PeerRib {
+    fn new(routes: &[Route]) -> Self {
+        PeerRib {
+            attributes: routes
+                .iter()
+                .map(|route| route.attributes.clone())
+                .collect(),
+            is_announced: vec![true; routes.len()],
+            announced: Pool::full(routes.len()),
+            withdrawn: Pool::empty(routes.len()),
+        }
+    }
+
+    fn announce(&mut self, idx: usize) {
+        self.is_announced[idx] = true;
+        self.announced.insert(idx);
+    }
+}
+
+//------------ Pool ----------------------------------------------------------
+
+/// A set of route indexes to pick random members from.
+struct Pool {
+    items: Vec<usize>,
+
+    /// The position of each route in `items`, if it is a member.
+    positions: Vec<Option<usize>>,
+}
+
+impl Pool {
+    fn full(len: usize) -> Self {
+        Pool {
+            items: (0..len).collect(),
+            positions: (0..len).map(Some).collect(),
+        }
+    }
+
+    fn empty(len: usize) -> Self {
+        Pool {
+            items: vec![],
+            positions: vec![None; len],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    fn insert(&mut self, idx: usize) {
+        if self.positions[idx].is_none() {
+            self.positions[idx] = Some(self.items.len());
+            self.items.push(idx);
+        }
+    }
+
+    /// Returns a random member.
+    fn pick(&self, rng: &mut Rng) -> Option<usize> {
+        if self.items.is_empty() {
+            return None;
+        }
+        Some(self.items[rng.below(self.items.len() as u64) as usize])
+    }
+
+    /// Removes and returns a random member.
+    fn take(&mut self, rng: &mut Rng) -> Option<usize> {
+        let idx = self.pick(rng)?;
+        let pos = self.positions[idx].take().unwrap();
+        self.items.swap_remove(pos);
+        if let Some(moved) = self.items.get(pos) {
+            self.positions[*moved] = Some(pos);
+        }
+        Some(idx)
+    }
+}
+
+//------------ Helpers -------------------------------------------------------
+
+/// Returns the path attributes of a route after its path changed to go
+/// through the upstreams of the donor path instead.
+fn reroute(
+    current: &PathAttributes,
+    donor: &PathAttributes,
+) -> PathAttributes {
+    let origin = current.origin_asn();
+    let upstreams = |path: &[Asn], origin: Asn| {
+        path.iter()
+            .rposition(|asn| *asn != origin)
+            .map_or(0, |pos| pos + 1)
+    };
+    let mut as_path = donor.as_path
+        [..upstreams(&donor.as_path, donor.origin_asn())]
+        .to_vec();
+    as_path.extend_from_slice(
+        &current.as_path[upstreams(&current.as_path, origin)..],
+    );
+    PathAttributes {
+        origin: current.origin,
+        as_path,
+        communities: donor.communities.clone(),
+    }
+}
@@ -0,0 +1,182 @@
+//! Timestamp sources for the BMP Per-Peer Header.
+//!
+//! By default the encoders stamp each Per-Peer Header with the current
+//! system time. Setting [`PerPeerHeader::timestamp`] overrides this for a
+//! single message, while a [`Clock`] can be used to produce the timestamps
+//! for a series of messages, e.g. to get reproducible output.
+//!
+//! [`PerPeerHeader::timestamp`]: crate::bmp::encode::PerPeerHeader::timestamp
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, Utc};
+
+use crate::bmp::encode::EncodeError;
+
+/// The number of microseconds in a second.
+const MICROS_PER_SEC: u32 = 1_000_000;
+
+//------------ Timestamp -----------------------------------------------------
+
+/// Seconds and microseconds since the Unix epoch, as carried in the BMP
+/// Per-Peer Header.
+///
+/// A zero timestamp means that the time is unavailable.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Timestamp {
+    pub secs: u32,
+    pub micros: u32,
+}
+
+impl Timestamp {
+    /// Creates a timestamp, or returns an error if `micros` is a second or
+    /// more.
+    pub fn try_new(secs: u32, micros: u32) -> Result<Self, EncodeError> {
+        if micros < MICROS_PER_SEC {
+            Ok(Timestamp { secs, micros })
+        } else {
+            Err(EncodeError::InvalidMicroseconds(micros))
+        }
+    }
+
+    /// Returns the timestamp shifted by the given duration.
+    pub fn checked_add(
+        self,
+        duration: Duration,
+    ) -> Result<Self, EncodeError> {
+        let dt = DateTime::<Utc>::try_from(self)?
+            .checked_add_signed(duration)
+            .ok_or(EncodeError::TimestampOutOfRange(
+                i64::from(self.secs).saturating_add(duration.num_seconds()),
+            ))?;
+        Timestamp::try_from(dt)
+    }
+}
+
+impl TryFrom<DateTime<Utc>> for Timestamp {
+    type Error = EncodeError;
+
+    /// A leap second, which chrono gives as a second or more of
+    /// microseconds, is counted as the start of the next second.
+    fn try_from(dt: DateTime<Utc>) -> Result<Self, Self::Error> {
+        let micros = dt.timestamp_subsec_micros();
+        let secs = dt.timestamp() + i64::from(micros / MICROS_PER_SEC);
+        let secs = u32::try_from(secs)
+            .map_err(|_| EncodeError::TimestampOutOfRange(secs))?;
+        Timestamp::try_new(secs, micros % MICROS_PER_SEC)
+    }
+}
+
+impl TryFrom<Timestamp> for DateTime<Utc> {
+    type Error = EncodeError;
+
+    fn try_from(ts: Timestamp) -> Result<Self, Self::Error> {
+        let ts = Timestamp::try_new(ts.secs, ts.micros)?;
+        ts.micros
+            .checked_mul(1000)
+            .and_then(|nanos| {
+                DateTime::from_timestamp(i64::from(ts.secs), nanos)
+            })
+            .ok_or(EncodeError::InvalidMicroseconds(ts.micros))
+    }
+}
+
+impl FromStr for Timestamp {
+    type Err = anyhow::Error;
+
+    /// Parses `<secs>`, `<secs>.<fraction>` or an RFC 3339 date and time.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Ok(dt) = DateTime::parse_from_rfc3339(s) {
+            return Ok(Timestamp::try_from(dt.with_timezone(&Utc))?);
+        }
+        let (secs, fraction) = s.split_once('.').unwrap_or((s, ""));
+        let secs = secs.parse::<u32>()?;
+        let micros = if fraction.is_empty() {
+            0
+        } else if fraction.len() <= 6 {
+            fraction.parse::<u32>()? * 10u32.pow(6 - fraction.len() as u32)
+        } else {
+            return Err(anyhow::anyhow!(
+                "Expected at most 6 fractional digits"
+            ));
+        };
+        Ok(Timestamp::try_new(secs, micros)?)
+    }
+}
+
+impl fmt::Display for Timestamp {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}.{:06}", self.secs, self.micros)
+    }
+}
+
+//------------ Clock ---------------------------------------------------------
+
+/// A source of Per-Peer Header timestamps.
+pub trait Clock {
+    fn now(&mut self) -> Result<Timestamp, EncodeError>;
+}
+
+/// The system time, optionally shifted into the past or the future.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock {
+    offset: Duration,
+}
+
+impl SystemClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_offset(offset: Duration) -> Self {
+        SystemClock { offset }
+    }
+}
+
+impl Clock for SystemClock {
+    fn now(&mut self) -> Result<Timestamp, EncodeError> {
+        let now = Utc::now();
+        let shifted = now
+            .checked_add_signed(self.offset)
+            .ok_or(EncodeError::TimestampOutOfRange(now.timestamp()))?;
+        Timestamp::try_from(shifted)
+    }
+}
+
+/// Always returns the same timestamp.
+#[derive(Clone, Copy, Debug)]
+pub struct FixedClock(pub Timestamp);
+
+impl Clock for FixedClock {
+    fn now(&mut self) -> Result<Timestamp, EncodeError> {
+        Ok(self.0)
+    }
+}
+
+/// Starts at a given timestamp and advances by a fixed step on every read.
+#[derive(Clone, Copy, Debug)]
+pub struct SimulatedClock {
+    next: Timestamp,
+    step: Duration,
+}
+
+impl SimulatedClock {
+    pub fn new(start: Timestamp, step: Duration) -> Self {
+        SimulatedClock { next: start, step }
+    }
+
+    /// Moves the clock by the given, possibly negative, duration.
+    pub fn advance(&mut self, duration: Duration) -> Result<(), EncodeError> {
+        self.next = self.next.checked_add(duration)?;
+        Ok(())
+    }
+}
+
+impl Clock for SimulatedClock {
+    fn now(&mut self) -> Result<Timestamp, EncodeError> {
+        let now = self.next;
+        self.next = now.checked_add(self.step)?;
+        Ok(now)
+    }
+}
@@ -0,0 +1,845 @@
+//! Human-readable descriptions of BMP messages.
+//!
+//! [`tree`] decodes a message with routecore's parser and shows its fields
+//! indented by nesting, while [`hexdump`] shows its octets next to the
+//! field each belongs to, e.g. to see what a command is about to send:
+//!
+//! ```
+//! use routes::bmp::describe::{hexdump, tree};
+//! use routes::bmp::encode::mk_termination_msg;
+//!
+//! let bytes = mk_termination_msg().unwrap();
+//! assert!(tree(&bytes).starts_with("Termination, 12 octets\n"));
+//! assert!(hexdump(&bytes).starts_with("0000  03 "));
+//! ```
+//!
+//! Both describe as much of a malformed message as they can, e.g. one sent
+//! with mutations, and then say where they gave up rather than fail.
+
+use std::fmt::{self, Display, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::ops::Range;
+
+use chrono::{DateTime, Utc};
+use routecore::bgp::communities::Community;
+use routecore::bgp::message::open::CapabilityType;
+use routecore::bgp::message::update::SessionConfig;
+use routecore::bgp::message::UpdateMessage;
+use routecore::bgp::path_attributes::PathAttributeType;
+use routecore::bgp::ParseError;
+use routecore::bmp::message::{
+    InformationTlvType, Message, MessageType, PeerDownReason, PeerType,
+    PerPeerHeader, TerminationInformation,
+};
+
+use crate::bmp::clock::Timestamp;
+use crate::bmp::update::{self, MpNlri, BGP_HEADER_LEN, BGP_UPDATE};
+
+/// The length of the BMP common header.
+const COMMON_HEADER_LEN: usize = 6;
+
+/// The length of the Per-Peer Header.
+const PER_PEER_HEADER_LEN: usize = 42;
+
+/// The length of the local address and ports of a Peer Up Notification.
+const PEER_UP_FIELDS_LEN: usize = 20;
+
+/// The length of a BGP OPEN from its version up to its optional
+/// parameters.
+const OPEN_FIELDS_LEN: usize = 10;
+
+/// The number of octets per line of a hex dump.
+const OCTETS_PER_LINE: usize = 16;
+
+/// The BGP message type code of an OPEN.
+const BGP_OPEN: u8 = 1;
+
+/// The type code of the Capabilities optional parameter of an OPEN.
+const CAPABILITIES_PARAMETER: u8 = 2;
+
+//------------ tree ----------------------------------------------------------
+
+/// Returns the fields of a message as decoded by routecore, one per line
+/// and indented by two spaces per level of nesting.
+pub fn tree(bytes: &[u8]) -> String {
+    let mut out = Tree::default();
+    match bytes.get(5) {
+        Some(&typ) => out.line(
+            0,
+            format_args!("{}, {} octets", TypeName(typ), bytes.len()),
+        ),
+        None => {
+            out.line(0, format_args!("Truncated, {} octets", bytes.len()))
+        }
+    }
+    if let Err(err) = message(&mut out, bytes) {
+        out.line(1, format_args!("cannot be parsed: {err}"));
+    }
+    out.text
+}
+
+/// Text indented by nesting.
+#[derive(Default)]
+struct Tree {
+    text: String,
+}
+
+impl Tree {
+    fn line(&mut self, depth: usize, text: impl Display) {
+        let _ = writeln!(self.text, "{:1$}{text}", "", depth * 2);
+    }
+
+    /// Adds a heading with the number of items and the items below it.
+    fn list<T: Display>(
+        &mut self,
+        depth: usize,
+        heading: &str,
+        items: impl IntoIterator<Item = T>,
+    ) {
+        let items: Vec<T> = items.into_iter().collect();
+        self.line(depth, format_args!("{heading}: {}", items.len()));
+        for item in items {
+            self.line(depth + 1, item);
+        }
+    }
+}
+
+/// Adds the fields of a message.
+///
+/// Many of routecore's accessors index into the message and panic rather
+/// than return an error where it is malformed, so only those are used
+/// whose fields `Message::from_octets` has checked to be there. The rest
+/// is decoded here.
+fn message(out: &mut Tree, bytes: &[u8]) -> Result<(), ParseError> {
+    // routecore also panics while checking a Peer Up Notification with
+    // OPENs that do not add up, so these are checked before.
+    let typ = bytes.get(5).map(|&typ| MessageType::from(typ));
+    let [sent, received] = if typ == Some(MessageType::PeerUpNotification) {
+        peer_up_opens(bytes)?
+    } else {
+        Default::default()
+    };
+    match Message::from_octets(bytes)? {
+        Message::RouteMonitoring(msg) => {
+            let pph = per_peer_header(out, bytes);
+            let config = if pph.is_legacy_format() {
+                SessionConfig::legacy()
+            } else {
+                SessionConfig::modern()
+            };
+            bgp_update(out, &msg.bgp_update(config)?)
+        }
+        Message::StatisticsReport(msg) => {
+            per_peer_header(out, bytes);
+            out.list(1, "statistics", msg.stats());
+            Ok(())
+        }
+        Message::PeerDownNotification(msg) => {
+            per_peer_header(out, bytes);
+            let reason = msg.reason();
+            out.line(1, format_args!("reason: {reason:?}"));
+            let data_start = COMMON_HEADER_LEN + PER_PEER_HEADER_LEN + 1;
+            let data = &bytes[data_start..];
+            if matches!(
+                reason,
+                PeerDownReason::LocalNotification
+                    | PeerDownReason::RemoteNotification
+            ) && !data.is_empty()
+            {
+                out.line(
+                    1,
+                    format_args!("notification: {} octets", data.len()),
+                );
+            }
+            Ok(())
+        }
+        Message::PeerUpNotification(msg) => {
+            per_peer_header(out, bytes);
+            out.line(
+                1,
+                format_args!("local address: {}", msg.local_address()),
+            );
+            out.line(1, format_args!("local port: {}", msg.local_port()));
+            out.line(1, format_args!("remote port: {}", msg.remote_port()));
+            out.line(1, "sent OPEN");
+            open(out, &bytes[sent])?;
+            out.line(1, "received OPEN");
+            open(out, &bytes[received.clone()])?;
+            // routecore 0.4 looks for the TLVs in the wrong place.
+            out.list(
+                1,
+                "information TLVs",
+                Tlvs(&bytes[received.end..]).map(information_tlv),
+            );
+            Ok(())
+        }
+        Message::InitiationMessage(_) => {
+            out.list(
+                1,
+                "information TLVs",
+                Tlvs(&bytes[COMMON_HEADER_LEN..]).map(information_tlv),
+            );
+            Ok(())
+        }
+        Message::TerminationMessage(_) => {
+            out.list(
+                1,
+                "information TLVs",
+                Tlvs(&bytes[COMMON_HEADER_LEN..]).map(termination_tlv),
+            );
+            Ok(())
+        }
+        Message::RouteMirroring(_) => {
+            per_peer_header(out, bytes);
+            Ok(())
+        }
+    }
+}
+
+/// Adds the Per-Peer Header of a message that routecore has checked to
+/// have one, and returns it.
+fn per_peer_header<'a>(
+    out: &mut Tree,
+    bytes: &'a [u8],
+) -> PerPeerHeader<&'a [u8]> {
+    let octets =
+        &bytes[COMMON_HEADER_LEN..COMMON_HEADER_LEN + PER_PEER_HEADER_LEN];
+    let pph = PerPeerHeader::for_slice(octets);
+    out.line(1, "Per-Peer Header");
+    out.line(2, format_args!("peer type: {}", pph.peer_type()));
+    out.line(
+        2,
+        format_args!(
+            "flags: {:#04x} ({}, {}, {} AS path)",
+            pph.flags(),
+            if pph.is_ipv6() { "IPv6" } else { "IPv4" },
+            if pph.is_post_policy() {
+                "post-policy"
+            } else {
+                "pre-policy"
+            },
+            if pph.is_legacy_format() {
+                "2-octet"
+            } else {
+                "4-octet"
+            }
+        ),
+    );
+    out.line(
+        2,
+        format_args!("distinguisher: {}", Hex(pph.distinguisher())),
+    );
+    out.line(2, format_args!("address: {}", pph.address()));
+    out.line(2, format_args!("AS: {}", pph.asn().into_u32()));
+    out.line(2, format_args!("BGP ID: {}", Ipv4Addr::from(pph.bgp_id())));
+    // routecore's timestamp() overflows on large microseconds.
+    let ts = Timestamp {
+        secs: be_u32(&octets[34..38]),
+        micros: be_u32(&octets[38..42]),
+    };
+    match DateTime::<Utc>::try_from(ts) {
+        Ok(dt) => out.line(2, format_args!("timestamp: {dt}")),
+        Err(err) => out.line(2, format_args!("timestamp: {ts} ({err})")),
+    }
+    pph
+}
+
+/// Returns the positions of the sent and received OPEN of a Peer Up
+/// Notification, or an error if their optional parameters or capabilities
+/// do not add up.
+fn peer_up_opens(bytes: &[u8]) -> Result<[Range<usize>; 2], ParseError> {
+    let mut start =
+        COMMON_HEADER_LEN + PER_PEER_HEADER_LEN + PEER_UP_FIELDS_LEN;
+    let mut opens = [0..0, 0..0];
+    for open in &mut opens {
+        let params = start + BGP_HEADER_LEN + OPEN_FIELDS_LEN;
+        let header =
+            bytes.get(start..params).ok_or(ParseError::ShortInput)?;
+        let len = usize::from(u16::from_be_bytes([header[16], header[17]]));
+        let end = params + usize::from(header[params - start - 1]);
+        if end - start != len {
+            return Err(ParseError::form_error(
+                "OPEN length does not match its optional parameters",
+            ));
+        }
+        let params = bytes.get(params..end).ok_or(ParseError::ShortInput)?;
+        for (typ, value) in capabilities(params)? {
+            let empty_multisession = matches!(
+                typ,
+                CapabilityType::Multisession
+                    | CapabilityType::PrestandardMultisession
+            ) && value.is_empty();
+            if empty_multisession {
+                return Err(ParseError::form_error(
+                    "empty Multisession capability",
+                ));
+            }
+        }
+        *open = start..end;
+        start = end;
+    }
+    Ok(opens)
+}
+
+/// Returns the type and value of the capabilities in the optional
+/// parameters of an OPEN, or an error if these overrun each other.
+fn capabilities(
+    mut params: &[u8],
+) -> Result<Vec<(CapabilityType, &[u8])>, ParseError> {
+    let overrun =
+        || ParseError::form_error("OPEN optional parameters do not add up");
+    let mut res = Vec::new();
+    while let [typ, len, rest @ ..] = params {
+        let mut value = rest.get(..usize::from(*len)).ok_or_else(overrun)?;
+        params = &rest[value.len()..];
+        if *typ != CAPABILITIES_PARAMETER {
+            continue;
+        }
+        while let [typ, len, rest @ ..] = value {
+            let cap = rest.get(..usize::from(*len)).ok_or_else(overrun)?;
+            res.push((CapabilityType::from(*typ), cap));
+            value = &rest[cap.len()..];
+        }
+        if !value.is_empty() {
+            return Err(overrun());
+        }
+    }
+    if !params.is_empty() {
+        return Err(overrun());
+    }
+    Ok(res)
+}
+
+/// Adds the fields of an OPEN checked by [`peer_up_opens`].
+fn open(out: &mut Tree, octets: &[u8]) -> Result<(), ParseError> {
+    let fields = &octets[BGP_HEADER_LEN..];
+    let capabilities = capabilities(&fields[OPEN_FIELDS_LEN..])?;
+    let asn = capabilities
+        .iter()
+        .find(|(typ, value)| {
+            *typ == CapabilityType::FourOctetAsn && value.len() == 4
+        })
+        .map_or(u32::from(be_u16(&fields[1..3])), |(_, value)| be_u32(value));
+    out.line(2, format_args!("version: {}", fields[0]));
+    out.line(2, format_args!("AS: {asn}"));
+    out.line(2, format_args!("hold time: {}", be_u16(&fields[3..5])));
+    out.line(
+        2,
+        format_args!("BGP ID: {}", Ipv4Addr::from(be_u32(&fields[5..9]))),
+    );
+    out.list(2, "capabilities", capabilities.iter().map(|(typ, _)| typ));
+    Ok(())
+}
+
+fn bgp_update(
+    out: &mut Tree,
+    update: &UpdateMessage<&[u8]>,
+) -> Result<(), ParseError> {
+    out.line(1, format_args!("BGP UPDATE, {} octets", update.length()));
+    if let Some((afi, safi)) = update.is_eor()? {
+        out.line(2, format_args!("End-of-RIB for {afi} {safi}"));
+        return Ok(());
+    }
+    let withdrawals = update.unicast_withdrawals_vec()?;
+    out.list(2, "withdrawals", withdrawals.iter().map(|nlri| nlri.prefix));
+    let attributes =
+        update.path_attributes()?.collect::<Result<Vec<_>, _>>()?;
+    out.line(2, format_args!("path attributes: {}", attributes.len()));
+    for attribute in attributes {
+        let typ = attribute.type_code();
+        let value = attribute_value(update, typ)?;
+        out.line(
+            3,
+            format_args!(
+                "{typ}{}{value}",
+                if value.is_empty() { "" } else { ": " }
+            ),
+        );
+    }
+    let announcements = update.unicast_announcements_vec()?;
+    out.list(
+        2,
+        "announcements",
+        announcements.iter().map(|nlri| nlri.prefix),
+    );
+    Ok(())
+}
+
+/// Returns the value of a path attribute as text, or an empty string if
+/// routecore does not decode it.
+fn attribute_value(
+    update: &UpdateMessage<&[u8]>,
+    typ: PathAttributeType,
+) -> Result<String, ParseError> {
+    let value = match typ {
+        PathAttributeType::Origin => {
+            update.origin()?.map(|origin| origin.to_string())
+        }
+        PathAttributeType::AsPath => update
+            .aspath()?
+            .map(|as_path| as_path.to_hop_path().to_string()),
+        PathAttributeType::NextHop => update
+            .conventional_next_hop()?
+            .map(|next_hop| next_hop.to_string()),
+        PathAttributeType::MultiExitDisc => {
+            update.multi_exit_disc()?.map(|med| med.to_string())
+        }
+        PathAttributeType::LocalPref => {
+            update.local_pref()?.map(|pref| pref.to_string())
+        }
+        PathAttributeType::MpReachNlri => update
+            .mp_next_hop()?
+            .map(|next_hop| format!("next hop {next_hop}")),
+        PathAttributeType::Communities => update.communities()?.map(join),
+        PathAttributeType::ExtendedCommunities => update
+            .ext_communities()?
+            .map(|communities| join(communities.map(Community::from))),
+        PathAttributeType::LargeCommunities => update
+            .large_communities()?
+            .map(|communities| join(communities.map(Community::from))),
+        _ => None,
+    };
+    Ok(value.unwrap_or_default())
+}
+
+fn join<T: Display>(items: impl Iterator<Item = T>) -> String {
+    items
+        .map(|item| item.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+/// Shows a message type by name.
+struct TypeName(u8);
+
+impl Display for TypeName {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match MessageType::from(self.0) {
+            MessageType::RouteMonitoring => f.write_str("Route Monitoring"),
+            MessageType::StatisticsReport => f.write_str("Statistics Report"),
+            MessageType::PeerDownNotification => {
+                f.write_str("Peer Down Notification")
+            }
+            MessageType::PeerUpNotification => {
+                f.write_str("Peer Up Notification")
+            }
+            MessageType::InitiationMessage => f.write_str("Initiation"),
+            MessageType::TerminationMessage => f.write_str("Termination"),
+            MessageType::RouteMirroring => f.write_str("Route Mirroring"),
+            MessageType::Unimplemented(typ) => {
+                write!(f, "Unknown type {typ}")
+            }
+        }
+    }
+}
+
+/// The type and value of TLVs with two octet types and lengths, without a
+/// value for a last TLV that is cut short.
+struct Tlvs<'a>(&'a [u8]);
+
+impl<'a> Iterator for Tlvs<'a> {
+    type Item = (u16, Option<&'a [u8]>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let header = self.0.get(..4)?;
+        let typ = be_u16(&header[..2]);
+        let len = usize::from(be_u16(&header[2..]));
+        let value = self.0.get(4..4 + len);
+        self.0 = self.0.get(4 + len..).unwrap_or_default();
+        Some((typ, value))
+    }
+}
+
+/// Shows an Information TLV of an Initiation or Peer Up message.
+fn information_tlv((typ, value): (u16, Option<&[u8]>)) -> String {
+    let typ = InformationTlvType::from(typ);
+    let Some(value) = value else {
+        return format!("{typ:?}: truncated");
+    };
+    match typ {
+        InformationTlvType::String
+        | InformationTlvType::SysDesc
+        | InformationTlvType::SysName
+        | InformationTlvType::VrfTableName
+        | InformationTlvType::AdminLabel => {
+            format!("{typ:?}: {}", String::from_utf8_lossy(value))
+        }
+        _ => format!("{typ:?}: {}", Hex(value)),
+    }
+}
+
+/// Shows a TLV of a Termination message, as routecore does.
+fn termination_tlv((typ, value): (u16, Option<&[u8]>)) -> String {
+    let info = match (typ, value) {
+        (_, None) => return format!("type {typ}: truncated"),
+        (0, Some(value)) => TerminationInformation::CustomString(
+            String::from_utf8_lossy(value).into_owned(),
+        ),
+        (1, Some(&[high, low])) => match u16::from_be_bytes([high, low]) {
+            0 => TerminationInformation::AdminClose,
+            1 => TerminationInformation::Unspecified,
+            2 => TerminationInformation::OutOfResources,
+            3 => TerminationInformation::RedundantConnection,
+            4 => TerminationInformation::PermAdminClose,
+            reason => TerminationInformation::Undefined(reason),
+        },
+        (_, Some(value)) => return format!("type {typ}: {}", Hex(value)),
+    };
+    info.to_string()
+}
+
+fn be_u16(octets: &[u8]) -> u16 {
+    u16::from_be_bytes([octets[0], octets[1]])
+}
+
+fn be_u32(octets: &[u8]) -> u32 {
+    u32::from_be_bytes([octets[0], octets[1], octets[2], octets[3]])
+}
+
+/// Shows flags as hex.
+struct Flags(u8);
+
+impl Display for Flags {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#04x}", self.0)
+    }
+}
+
+/// Shows octets as space separated hex.
+struct Hex<'a>(&'a [u8]);
+
+impl Display for Hex<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (idx, octet) in self.0.iter().enumerate() {
+            if idx > 0 {
+                f.write_str(" ")?;
+            }
+            write!(f, "{octet:02x}")?;
+        }
+        Ok(())
+    }
+}
+
+//------------ hexdump -------------------------------------------------------
+
+/// Returns the octets of a message, one field per line with its offset and
+/// what it is.
+///
+/// Fields longer than 16 octets continue on the next lines. The fields are
+/// found by the lengths in the message itself, so a malformed message is
+/// dumped up to where these stop making sense and the rest as a single
+/// field.
+///
+/// The dump has the layout of a Wireshark hex dump, so [`hex::decode`]
+/// turns it back into the message.
+///
+/// [`hex::decode`]: crate::hex::decode
+pub fn hexdump(bytes: &[u8]) -> String {
+    let mut dump = Dump {
+        bytes,
+        pos: 0,
+        text: String::new(),
+    };
+    dump_message(&mut dump);
+    dump.rest("unexpected octets");
+    dump.text
+}
+
+/// A hex dump in the making.
+struct Dump<'a> {
+    bytes: &'a [u8],
+
+    /// The offset of the next field.
+    pos: usize,
+
+    text: String,
+}
+
+impl<'a> Dump<'a> {
+    /// Returns the number of octets left.
+    fn remaining(&self) -> usize {
+        self.bytes.len() - self.pos
+    }
+
+    /// Adds a field of up to `len` octets.
+    fn field(&mut self, len: usize, label: impl Display) {
+        let end = self.pos + len.min(self.remaining());
+        let field = &self.bytes[self.pos..end];
+        for (idx, chunk) in field.chunks(OCTETS_PER_LINE).enumerate() {
+            let offset = self.pos + idx * OCTETS_PER_LINE;
+            let hex = Hex(chunk).to_string();
+            if idx == 0 {
+                let _ = writeln!(
+                    self.text,
+                    "{offset:04x}  {hex:width$}   {label}",
+                    width = OCTETS_PER_LINE * 3 - 1
+                );
+            } else {
+                let _ = writeln!(self.text, "{offset:04x}  {hex}");
+            }
+        }
+        self.pos = end;
+    }
+
+    /// Adds a field of `len` octets labelled with its value, returning the
+    /// value if all of the field is there.
+    fn decoded<T: Display>(
+        &mut self,
+        len: usize,
+        label: &str,
+        decode: impl FnOnce(&'a [u8]) -> T,
+    ) -> Option<T> {
+        match self.bytes.get(self.pos..self.pos + len) {
+            Some(octets) => {
+                let value = decode(octets);
+                self.field(len, format_args!("{label}: {value}"));
+                Some(value)
+            }
+            None => {
+                self.field(len, label);
+                None
+            }
+        }
+    }
+
+    fn u8(&mut self, label: &str) -> Option<u8> {
+        self.decoded(1, label, |octets| octets[0])
+    }
+
+    fn u16(&mut self, label: &str) -> Option<u16> {
+        self.decoded(2, label, |octets| {
+            u16::from_be_bytes([octets[0], octets[1]])
+        })
+    }
+
+    fn u32(&mut self, label: &str) -> Option<u32> {
+        self.decoded(4, label, |octets| {
+            u32::from_be_bytes([octets[0], octets[1], octets[2], octets[3]])
+        })
+    }
+
+    /// Adds a field of four octets shown as an IPv4 address.
+    fn ipv4(&mut self, label: &str) {
+        self.decoded(4, label, |octets| {
+            Ipv4Addr::new(octets[0], octets[1], octets[2], octets[3])
+        });
+    }
+
+    /// Adds a field of 16 octets shown as an IPv6 address, or as an IPv4
+    /// address if the first 12 octets are zero.
+    fn address(&mut self, label: &str) {
+        self.decoded(16, label, |octets| {
+            let mut addr = [0u8; 16];
+            addr.copy_from_slice(octets);
+            match Ipv6Addr::from(addr).to_ipv4() {
+                Some(v4) if addr[..12].iter().all(|&octet| octet == 0) => {
+                    IpAddr::V4(v4)
+                }
+                _ => IpAddr::V6(Ipv6Addr::from(addr)),
+            }
+        });
+    }
+
+    /// Adds the octets up to `end` as a single field, if there are any.
+    fn until(&mut self, end: usize, label: &str) {
+        if end > self.pos {
+            self.field(end - self.pos, label);
+        }
+    }
+
+    /// Adds the remaining octets as a single field, if there are any.
+    fn rest(&mut self, label: &str) {
+        self.until(self.bytes.len(), label);
+    }
+
+    /// Returns the end of a part of `len` octets starting here, but not
+    /// beyond `limit` unless the fields before already went past it.
+    fn end(&self, len: usize, limit: usize) -> usize {
+        (self.pos + len).min(limit).max(self.pos)
+    }
+}
+
+fn dump_message(dump: &mut Dump) {
+    dump.u8("version");
+    let len = dump.u32("message length");
+    let typ = dump.decoded(1, "message type", |octets| TypeName(octets[0]));
+    let (Some(len), Some(typ)) = (len, typ) else {
+        return;
+    };
+    let end = (len as usize).clamp(COMMON_HEADER_LEN, dump.bytes.len());
+    match MessageType::from(typ.0) {
+        MessageType::RouteMonitoring => {
+            dump_per_peer_header(dump);
+            dump_bgp_update(dump, end);
+        }
+        MessageType::StatisticsReport => {
+            dump_per_peer_header(dump);
+            let count = dump.u32("statistics count").unwrap_or(0);
+            for _ in 0..count {
+                if dump.pos >= end {
+                    break;
+                }
+                dump_tlv(dump, end, "statistic");
+            }
+        }
+        MessageType::PeerDownNotification => {
+            dump_per_peer_header(dump);
+            dump.u8("reason");
+            dump.until(end, "data");
+        }
+        MessageType::PeerUpNotification => {
+            dump_per_peer_header(dump);
+            dump.address("local address");
+            dump.u16("local port");
+            dump.u16("remote port");
+            dump_open(dump, end, "sent");
+            dump_open(dump, end, "received");
+            dump_tlvs(dump, end, "information");
+        }
+        MessageType::InitiationMessage | MessageType::TerminationMessage => {
+            dump_tlvs(dump, end, "information");
+        }
+        MessageType::RouteMirroring => {
+            dump_per_peer_header(dump);
+            dump_tlvs(dump, end, "route mirroring");
+        }
+        MessageType::Unimplemented(_) => {}
+    }
+    dump.until(end, "unexpected octets");
+}
+
+fn dump_per_peer_header(dump: &mut Dump) {
+    dump.decoded(1, "peer type", |octets| PeerType::from(octets[0]));
+    dump.decoded(1, "peer flags", |octets| Flags(octets[0]));
+    dump.field(8, "peer distinguisher");
+    dump.address("peer address");
+    dump.u32("peer AS");
+    dump.ipv4("peer BGP ID");
+    dump.u32("timestamp seconds");
+    dump.u32("timestamp microseconds");
+}
+
+/// Adds a BGP message header, returning the end of the message and its
+/// type.
+fn dump_bgp_header(dump: &mut Dump, limit: usize) -> Option<(usize, u8)> {
+    dump.field(16, "BGP marker");
+    let len = dump.u16("BGP length")?;
+    let typ = dump.u8("BGP type")?;
+    let start = dump.pos - BGP_HEADER_LEN;
+    Some(((start + usize::from(len)).min(limit), typ))
+}
+
+fn dump_bgp_update(dump: &mut Dump, limit: usize) {
+    let Some((end, typ)) = dump_bgp_header(dump, limit) else {
+        return;
+    };
+    if typ != BGP_UPDATE {
+        dump.until(end, "BGP message");
+        return;
+    }
+    let Some(len) = dump.u16("withdrawn routes length") else {
+        return;
+    };
+    let withdrawn_end = dump.end(usize::from(len), end);
+    dump_prefixes(dump, withdrawn_end, false, "withdrawn");
+    let Some(len) = dump.u16("path attributes length") else {
+        return;
+    };
+    let attributes_end = dump.end(usize::from(len), end);
+    while dump.pos < attributes_end {
+        dump_attribute(dump, attributes_end);
+    }
+    dump_prefixes(dump, end, false, "announced");
+}
+
+fn dump_attribute(dump: &mut Dump, limit: usize) {
+    let Some(flags) =
+        dump.decoded(1, "attribute flags", |octets| Flags(octets[0]))
+    else {
+        return;
+    };
+    let Some(typ) = dump.decoded(1, "attribute type", |octets| {
+        PathAttributeType::from(octets[0])
+    }) else {
+        return;
+    };
+    let len = if flags.0 & 0x10 != 0 {
+        dump.u16("attribute length").map(usize::from)
+    } else {
+        dump.u8("attribute length").map(usize::from)
+    };
+    let Some(len) = len else {
+        return;
+    };
+    let end = dump.end(len, limit);
+    let value = &dump.bytes[dump.pos..end];
+    match typ {
+        PathAttributeType::MpReachNlri => {
+            if let Some(mp) = MpNlri::reach(value) {
+                dump.field(3, "AFI and SAFI");
+                dump.u8("next hop length");
+                dump.field(mp.next_hop.len(), "next hop");
+                dump.field(1, "reserved");
+                dump_prefixes(dump, end, mp.ipv6, "announced");
+            }
+        }
+        PathAttributeType::MpUnreachNlri => {
+            if let Some(mp) = MpNlri::unreach(value) {
+                dump.field(3, "AFI and SAFI");
+                dump_prefixes(dump, end, mp.ipv6, "withdrawn");
+            }
+        }
+        _ => {}
+    }
+    dump.until(end, &format!("{typ} value"));
+}
+
+/// Adds a field per prefix in the NLRI encoding up to `end`.
+fn dump_prefixes(dump: &mut Dump, end: usize, ipv6: bool, label: &str) {
+    while dump.pos < end {
+        let len = usize::from(dump.bytes[dump.pos]);
+        let field_len = 1 + len.div_ceil(8);
+        if dump.pos + field_len > end {
+            break;
+        }
+        let octets = &dump.bytes[dump.pos..dump.pos + field_len];
+        match update::prefixes(octets, ipv6) {
+            Some(prefixes) => {
+                dump.field(field_len, format_args!("{label} {}", prefixes[0]))
+            }
+            None => dump.field(field_len, label),
+        };
+    }
+    dump.until(end, label);
+}
+
+fn dump_open(dump: &mut Dump, limit: usize, label: &str) {
+    let Some((end, typ)) = dump_bgp_header(dump, limit) else {
+        return;
+    };
+    if typ == BGP_OPEN {
+        dump.u8("BGP version");
+        dump.u16("my AS");
+        dump.u16("hold time");
+        dump.ipv4("BGP identifier");
+        if let Some(len) = dump.u8("optional parameters length") {
+            let params_end = dump.end(usize::from(len), end);
+            dump.until(params_end, "optional parameters");
+        }
+    }
+    dump.until(end, &format!("{label} BGP message"));
+}
+
+fn dump_tlvs(dump: &mut Dump, end: usize, label: &str) {
+    while dump.pos < end {
+        dump_tlv(dump, end, label);
+    }
+}
+
+fn dump_tlv(dump: &mut Dump, limit: usize, label: &str) {
+    dump.u16(&format!("{label} type"));
+    if let Some(len) = dump.u16(&format!("{label} length")) {
+        let end = dump.end(usize::from(len), limit);
+        dump.until(end, &format!("{label} value"));
+    }
+}
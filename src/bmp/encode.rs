@@ -1,11 +1,13 @@
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
+use std::fmt;
+use std::net::{Ipv4Addr, Ipv6Addr};
 use std::{net::IpAddr, ops::Deref, str::FromStr};
 
 use bytes::{BufMut, Bytes, BytesMut};
-use chrono::Utc;
 use routecore::addr::Prefix;
 use routecore::asn::Asn;
-use routecore::bgp::aspath::HopPath;
+use routecore::bgp::aspath::{Hop, HopPath};
 use routecore::bgp::communities::Community;
 use routecore::bgp::types::{
     NextHop, OriginType, PathAttributeType, Afi, Safi,
@@ -14,7 +16,265 @@ use routecore::bmp::message::{
     InformationTlvType, MessageType, PeerType, TerminationInformation,
 };
 
-pub fn mk_initiation_msg(sys_name: &str, sys_descr: &str) -> Bytes {
+use crate::bmp::clock::{Clock, SystemClock, Timestamp};
+
+/// The maximum length of a BGP message without RFC 8654 extended messages.
+pub(crate) const MAX_BGP_MSG_LEN: usize = 4096;
+
+//------------ Errors --------------------------------------------------------
+
+/// The reason a BMP or BGP message could not be encoded.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum EncodeError {
+    /// The peer address given to `mk_per_peer_header` is not an IP address.
+    InvalidPeerAddress(String),
+
+    /// The Peer Up local address family doesn't match the Per-Peer Header
+    /// V flag.
+    LocalAddressFamilyMismatch(IpAddr),
+
+    /// The BGP next hop cannot be used for the address family of a prefix
+    /// being announced.
+    NextHopFamilyMismatch(NextHop),
+
+    /// The path attribute type is not supported by the encoder.
+    UnsupportedPathAttribute(PathAttributeType),
+
+    /// The community type is not supported by the encoder.
+    UnsupportedCommunity(Community),
+
+    /// The Information TLV type is not supported by the encoder.
+    UnsupportedInformationTlv(InformationTlvType),
+
+    /// The Termination TLV reason code is not supported by the encoder.
+    UnsupportedTerminationReason(u16),
+
+    /// A path attribute value is longer than 65535 octets.
+    PathAttributeTooLong(PathAttributeType, usize),
+
+    /// The path attributes together are longer than 65535 octets.
+    PathAttributesTooLong(usize),
+
+    /// The withdrawn routes are longer than 65535 octets.
+    WithdrawnRoutesTooLong(usize),
+
+    /// The BGP OPEN optional parameters are longer than 255 octets.
+    OptionalParametersTooLong(usize),
+
+    /// An Information TLV value is longer than 65535 octets.
+    InformationTlvTooLong(usize),
+
+    /// The BGP message is shorter than the 19 octet BGP header.
+    BgpMessageTooShort(usize),
+
+    /// The BGP message is longer than the 4096 octet maximum.
+    BgpMessageTooLong(usize),
+
+    /// The BMP message length doesn't fit the 32-bit length field.
+    BmpMessageTooLong(usize),
+
+    /// The announcements have this many different sets of path attributes,
+    /// which cannot share a single BGP UPDATE.
+    MultipleAttributeSets(usize),
+
+    /// The timestamp cannot be expressed as 32-bit seconds since the epoch.
+    TimestampOutOfRange(i64),
+
+    /// The timestamp has a microseconds field of a second or more.
+    InvalidMicroseconds(u32),
+
+    /// A warning was raised while encoding in strict mode.
+    Strict(Warning),
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EncodeError::InvalidPeerAddress(s) => {
+                write!(f, "invalid peer address '{s}'")
+            }
+            EncodeError::LocalAddressFamilyMismatch(addr) => {
+                write!(
+                    f,
+                    "local address {addr} is not of the address family \
+                    indicated by the Per-Peer Header V flag"
+                )
+            }
+            EncodeError::NextHopFamilyMismatch(next_hop) => {
+                write!(
+                    f,
+                    "next hop {next_hop} cannot be used for the address \
+                    family of the announced prefixes"
+                )
+            }
+            EncodeError::UnsupportedPathAttribute(typ) => {
+                write!(f, "unsupported path attribute type {typ}")
+            }
+            EncodeError::UnsupportedCommunity(c) => {
+                write!(f, "unsupported community {c}")
+            }
+            EncodeError::UnsupportedInformationTlv(typ) => {
+                write!(f, "unsupported Information TLV type {typ:?}")
+            }
+            EncodeError::UnsupportedTerminationReason(reason) => {
+                write!(f, "unsupported Termination TLV reason {reason}")
+            }
+            EncodeError::PathAttributeTooLong(typ, len) => {
+                write!(f, "path attribute {typ} is too long ({len} octets)")
+            }
+            EncodeError::PathAttributesTooLong(len) => {
+                write!(f, "path attributes are too long ({len} octets)")
+            }
+            EncodeError::WithdrawnRoutesTooLong(len) => {
+                write!(f, "withdrawn routes are too long ({len} octets)")
+            }
+            EncodeError::OptionalParametersTooLong(len) => {
+                write!(
+                    f,
+                    "BGP OPEN optional parameters are too long ({len} octets)"
+                )
+            }
+            EncodeError::InformationTlvTooLong(len) => {
+                write!(f, "Information TLV is too long ({len} octets)")
+            }
+            EncodeError::BgpMessageTooShort(len) => {
+                write!(f, "BGP message is too short ({len} octets)")
+            }
+            EncodeError::BgpMessageTooLong(len) => {
+                write!(f, "BGP message is too long ({len} octets)")
+            }
+            EncodeError::BmpMessageTooLong(len) => {
+                write!(f, "BMP message is too long ({len} octets)")
+            }
+            EncodeError::MultipleAttributeSets(count) => {
+                write!(
+                    f,
+                    "announcements with {count} different sets of path \
+                    attributes need a BGP UPDATE each"
+                )
+            }
+            EncodeError::TimestampOutOfRange(secs) => {
+                write!(f, "timestamp {secs} does not fit in 32 bits")
+            }
+            EncodeError::InvalidMicroseconds(micros) => {
+                write!(f, "timestamp microseconds {micros} exceed 999999")
+            }
+            EncodeError::Strict(warning) => warning.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+impl EncodeError {
+    /// Returns whether the error is about something being too long, which
+    /// spreading the prefixes over more messages may solve.
+    pub fn is_size_error(&self) -> bool {
+        matches!(
+            self,
+            EncodeError::PathAttributeTooLong(..)
+                | EncodeError::PathAttributesTooLong(_)
+                | EncodeError::WithdrawnRoutesTooLong(_)
+                | EncodeError::BgpMessageTooLong(_)
+                | EncodeError::BmpMessageTooLong(_)
+        )
+    }
+}
+
+//------------ Warnings ------------------------------------------------------
+
+/// An RFC violation in an otherwise successfully encoded message.
+///
+/// The encoders deliberately produce such messages so that monitoring
+/// stations can be tested with them, but report what is wrong with them.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Warning {
+    /// The Peer Flags V bit is set for an IPv4 peer address.
+    PeerFlagsVBitSet { peer_flags: u8, peer_address: Ipv4Addr },
+
+    /// The Peer Flags V bit is not set for an IPv6 peer address.
+    PeerFlagsVBitUnset { peer_flags: u8, peer_address: Ipv6Addr },
+
+    /// The Peer Flags A bit is set but the AS path contains an ASN that
+    /// cannot be encoded in 16 bits.
+    PeerFlagsABitSet { peer_flags: u8, asn: Asn },
+}
+
+impl Warning {
+    /// Returns the RFC and section that is violated.
+    pub fn rfc_section(&self) -> &'static str {
+        match self {
+            Warning::PeerFlagsVBitSet { .. }
+            | Warning::PeerFlagsVBitUnset { .. }
+            | Warning::PeerFlagsABitSet { .. } => "RFC 7854 section 4.2",
+        }
+    }
+
+    /// Returns the name of the offending message field.
+    pub fn field(&self) -> &'static str {
+        match self {
+            Warning::PeerFlagsVBitSet { .. }
+            | Warning::PeerFlagsVBitUnset { .. } => "Peer Flags V-bit",
+            Warning::PeerFlagsABitSet { .. } => "Peer Flags A-bit",
+        }
+    }
+}
+
+impl fmt::Display for Warning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} Per-Peer Header violation: ", self.rfc_section())?;
+        match self {
+            Warning::PeerFlagsVBitSet { peer_address, .. } => write!(
+                f,
+                "{} is SET but should be unset because peer address \
+                {peer_address} is an IPv4 address, not IPv6.",
+                self.field()
+            ),
+            Warning::PeerFlagsVBitUnset { peer_address, .. } => write!(
+                f,
+                "{} is NOT set but should be set because peer address \
+                {peer_address} is an IPv6 address, not IPv4.",
+                self.field()
+            ),
+            Warning::PeerFlagsABitSet { .. } => write!(
+                f,
+                "{} is SET but should be unset because the AS PATH being \
+                encoded contains ASNs that cannot be encoded in 16-bits: \
+                ASN too large",
+                self.field()
+            ),
+        }
+    }
+}
+
+/// Turns the warnings of an encoder result into an error.
+///
+/// This is the strict mode of the encoders: the first warning, if any, is
+/// returned as [`EncodeError::Strict`] instead of alongside the bytes.
+pub fn strict<T>(
+    res: Result<(T, Vec<Warning>), EncodeError>,
+) -> Result<T, EncodeError> {
+    let (bytes, warnings) = res?;
+    match warnings.into_iter().next() {
+        Some(warning) => Err(EncodeError::Strict(warning)),
+        None => Ok(bytes),
+    }
+}
+
+//------------ Encoders ------------------------------------------------------
+
+pub fn mk_initiation_msg(
+    sys_name: &str,
+    sys_descr: &str,
+) -> Result<Bytes, EncodeError> {
+    mk_initiation_msg_with_strings(sys_name, sys_descr, &[])
+}
+
+pub(crate) fn mk_initiation_msg_with_strings(
+    sys_name: &str,
+    sys_descr: &str,
+    strings: &[String],
+) -> Result<Bytes, EncodeError> {
     let mut buf = BytesMut::new();
     push_bmp_common_header(&mut buf, MessageType::InitiationMessage);
 
@@ -45,20 +305,26 @@ pub fn mk_initiation_msg(sys_name: &str, sys_descr: &str) -> Bytes {
         &mut buf,
         InformationTlvType::SysName,
         sys_name.as_bytes(),
-    );
+    )?;
     push_bmp_information_tlv(
         &mut buf,
         InformationTlvType::SysDesc,
         sys_descr.as_bytes(),
-    );
+    )?;
+    for string in strings {
+        push_bmp_information_tlv(
+            &mut buf,
+            InformationTlvType::String,
+            string.as_bytes(),
+        )?;
+    }
 
-    finalize_bmp_msg_len(&mut buf);
-    buf.freeze()
+    finalize_bmp_msg_len(&mut buf)?;
+    Ok(buf.freeze())
 }
 
 // Returns the generated bytes and a, possibly empty, set of warning messages.
 #[allow(clippy::too_many_arguments)]
-#[allow(clippy::vec_init_then_push)]
 pub fn mk_peer_up_notification_msg(
     per_peer_header: &PerPeerHeader,
     local_address: IpAddr,
@@ -70,10 +336,34 @@ pub fn mk_peer_up_notification_msg(
     received_bgp_identifier: u32,
     information_tlvs: Vec<(InformationTlvType, String)>,
     eor_capable: bool,
-) -> (Bytes, Vec<String>) {
+) -> Result<(Bytes, Vec<Warning>), EncodeError> {
+    let sent_open = Open::new(sent_open_asn, sent_bgp_identifier);
+    let received_open = Open::new(received_open_asn, received_bgp_identifier)
+        .eor_capable(eor_capable);
+    mk_peer_up_msg(
+        per_peer_header,
+        local_address,
+        local_port,
+        remote_port,
+        &sent_open,
+        &received_open,
+        &information_tlvs,
+    )
+}
+
+// Returns the generated bytes and a, possibly empty, set of warning messages.
+pub(crate) fn mk_peer_up_msg(
+    per_peer_header: &PerPeerHeader,
+    local_address: IpAddr,
+    local_port: u16,
+    remote_port: u16,
+    sent_open: &Open,
+    received_open: &Open,
+    information_tlvs: &[(InformationTlvType, String)],
+) -> Result<(Bytes, Vec<Warning>), EncodeError> {
     let mut buf = BytesMut::new();
     push_bmp_common_header(&mut buf, MessageType::PeerUpNotification);
-    let warnings = push_bmp_per_peer_header(&mut buf, per_peer_header);
+    let warnings = push_bmp_per_peer_header(&mut buf, per_peer_header)?;
 
     // 4.10.  Peer Up Notification
     //
@@ -98,20 +388,41 @@ pub fn mk_peer_up_notification_msg(
     // From: https://www.rfc-editor.org/rfc/rfc7854.html#section-4.10
 
     match local_address {
-        IpAddr::V4(addr) => {
-            assert!(per_peer_header.is_ipv4());
+        IpAddr::V4(addr) if per_peer_header.is_ipv4() => {
             buf.resize(buf.len() + 12, 0u8);
             buf.extend_from_slice(&addr.octets());
         }
-        IpAddr::V6(addr) => {
-            assert!(per_peer_header.is_ipv6());
+        IpAddr::V6(addr) if per_peer_header.is_ipv6() => {
             buf.extend_from_slice(&addr.octets());
         }
+        _ => {
+            return Err(EncodeError::LocalAddressFamilyMismatch(
+                local_address,
+            ));
+        }
     }
 
     buf.extend_from_slice(&local_port.to_be_bytes());
     buf.extend_from_slice(&remote_port.to_be_bytes());
 
+    push_bgp_open_msg(&mut buf, sent_open)?;
+    push_bgp_open_msg(&mut buf, received_open)?;
+
+    for (typ, val) in information_tlvs {
+        push_bmp_information_tlv(&mut buf, *typ, val.as_bytes())?;
+    }
+
+    finalize_bmp_msg_len(&mut buf)?;
+
+    Ok((buf.freeze(), warnings))
+}
+
+// Appends a BGP OPEN message with the given content to the buffer.
+#[allow(clippy::vec_init_then_push)]
+fn push_bgp_open_msg(
+    buf: &mut BytesMut,
+    open: &Open,
+) -> Result<(), EncodeError> {
     // 4.2.  OPEN Message Format
     //
     // After a TCP connection is established, the first message sent by each
@@ -141,7 +452,6 @@ pub fn mk_peer_up_notification_msg(
     //
     // From: https://datatracker.ietf.org/doc/html/rfc4271#section-4.2
 
-    // insert fake BGP open sent message
     let mut bgp_msg_buf = BytesMut::new();
     // Fixed size BGP header
     bgp_msg_buf.resize(bgp_msg_buf.len() + 16, 0xFFu8); // marker
@@ -150,29 +460,11 @@ pub fn mk_peer_up_notification_msg(
     bgp_msg_buf.extend_from_slice(&4u8.to_be_bytes()); // BGP version 4
 
     // Other fields
-    bgp_msg_buf.extend_from_slice(&sent_open_asn.to_be_bytes());
-    bgp_msg_buf.extend_from_slice(&0u16.to_be_bytes()); // 0 hold time - disables keep alive
-    bgp_msg_buf.extend_from_slice(&sent_bgp_identifier.to_be_bytes());
-    bgp_msg_buf.extend_from_slice(&0u8.to_be_bytes()); // 0 optional parameters
-
-    // Finalize BGP message
-    finalize_bgp_msg_len(&mut bgp_msg_buf);
-    buf.extend_from_slice(&bgp_msg_buf);
+    bgp_msg_buf.extend_from_slice(&open.asn.to_be_bytes());
+    bgp_msg_buf.extend_from_slice(&open.hold_time.to_be_bytes()); // 0 hold time disables keep alive
+    bgp_msg_buf.extend_from_slice(&open.bgp_id.to_be_bytes());
 
-    // insert fake BGP open received message
-    let mut bgp_msg_buf = BytesMut::new();
-    // Fixed size BGP header
-    bgp_msg_buf.resize(bgp_msg_buf.len() + 16, 0xFFu8); // marker
-    bgp_msg_buf.resize(bgp_msg_buf.len() + 2, 0); // placeholder length, to be replaced later
-    bgp_msg_buf.extend_from_slice(&1u8.to_be_bytes()); // 1 - OPEN
-    bgp_msg_buf.extend_from_slice(&4u8.to_be_bytes()); // BGP version 4
-
-    // Other fields
-    bgp_msg_buf.extend_from_slice(&received_open_asn.to_be_bytes());
-    bgp_msg_buf.extend_from_slice(&0u16.to_be_bytes()); // 0 hold time - disables keep alive
-    bgp_msg_buf.extend_from_slice(&received_bgp_identifier.to_be_bytes());
-
-    if !eor_capable {
+    if !open.eor_capable {
         bgp_msg_buf.extend_from_slice(&0u8.to_be_bytes()); // 0 optional parameter bytes
     } else {
         // A peer capable of sending the special End-Of-Rib marker BGP
@@ -372,14 +664,18 @@ pub fn mk_peer_up_notification_msg(
         optpi_value.push(cap_code);
         optpi_value.push(cap_len);
         optpi_value.extend_from_slice(&cap_val.to_be_bytes());
-        let optpi_len = u8::try_from(optpi_value.len()).unwrap();
+        let optpi_len = u8::try_from(optpi_value.len()).map_err(|_| {
+            EncodeError::OptionalParametersTooLong(optpi_value.len())
+        })?;
 
         // outer layer
         let mut optp_params = Vec::<u8>::new();
         optp_params.push(optpi_type);
         optp_params.push(optpi_len);
         optp_params.append(&mut optpi_value);
-        let optp_len = u8::try_from(optp_params.len()).unwrap();
+        let optp_len = u8::try_from(optp_params.len()).map_err(|_| {
+            EncodeError::OptionalParametersTooLong(optp_params.len())
+        })?;
 
         // extend the BGP OPEN message with the optional parameters
         bgp_msg_buf.extend_from_slice(&[optp_len]);
@@ -387,16 +683,9 @@ pub fn mk_peer_up_notification_msg(
     }
 
     // Finalize BGP message
-    finalize_bgp_msg_len(&mut bgp_msg_buf);
+    finalize_bgp_msg_len(&mut bgp_msg_buf)?;
     buf.extend_from_slice(&bgp_msg_buf);
-
-    for (typ, val) in information_tlvs {
-        push_bmp_information_tlv(&mut buf, typ, val.as_bytes());
-    }
-
-    finalize_bmp_msg_len(&mut buf);
-    
-    (buf.freeze(), warnings)
+    Ok(())
 }
 
 #[allow(clippy::vec_init_then_push)]
@@ -405,21 +694,202 @@ pub fn mk_route_monitoring_msg(
     withdrawals: &Prefixes,
     announcements: &Announcements,
     extra_path_attributes: &[u8],
-) -> (Bytes, Vec<String>) {
+) -> Result<(Bytes, Vec<Warning>), EncodeError> {
     let (bgp_msg_buf, mut warnings) =
-        mk_bgp_update(per_peer_header, withdrawals, announcements, extra_path_attributes);
-    let (bytes, mut more_warnings) = mk_raw_route_monitoring_msg(per_peer_header, bgp_msg_buf);
+        mk_bgp_update(per_peer_header, withdrawals, announcements, extra_path_attributes)?;
+    let (bytes, mut more_warnings) = mk_raw_route_monitoring_msg(per_peer_header, bgp_msg_buf)?;
 
     warnings.append(&mut more_warnings);
 
-    (bytes, warnings)
+    Ok((bytes, warnings))
+}
+
+/// Returns Route Monitoring messages for withdrawals and announcements with
+/// possibly different path attributes, packed into as few BGP UPDATEs as
+/// the 4096 octet maximum allows.
+///
+/// As a router does, the prefixes of all announcements with the same path
+/// attributes are announced together, and a prefix announced more than
+/// once only with its last path attributes. The withdrawals go with the
+/// first path attributes. The warnings are those of all messages, without
+/// repeats.
+pub fn mk_route_monitoring_msgs(
+    per_peer_header: &PerPeerHeader,
+    withdrawals: &Prefixes,
+    routes: &[Announcements],
+    extra_path_attributes: &[u8],
+) -> Result<(Vec<Bytes>, Vec<Warning>), EncodeError> {
+    let mut groups = pack_announcements(routes);
+    if groups.is_empty() {
+        groups.push(Announcements::None);
+    }
+    let no_withdrawals = Prefixes::default();
+    let mut msgs = vec![];
+    let mut warnings: Vec<Warning> = vec![];
+    for (idx, announcements) in groups.iter().enumerate() {
+        let (more_msgs, more_warnings) = split_route_monitoring_msg(
+            per_peer_header,
+            if idx == 0 {
+                withdrawals
+            } else {
+                &no_withdrawals
+            },
+            announcements,
+            extra_path_attributes,
+        )?;
+        msgs.extend(more_msgs);
+        for warning in more_warnings {
+            if !warnings.contains(&warning) {
+                warnings.push(warning);
+            }
+        }
+    }
+    Ok((msgs, warnings))
+}
+
+/// Merges announcements with the same path attributes, in the order the
+/// path attributes first appear.
+///
+/// A prefix announced more than once is only kept with the last path
+/// attributes. Path attributes left without prefixes that way are dropped.
+pub(crate) fn pack_announcements(
+    routes: &[Announcements],
+) -> Vec<Announcements> {
+    let mut groups: Vec<(&Announcements, Vec<Prefix>)> = vec![];
+    let mut last = HashMap::new();
+    for announcements in routes {
+        if let Announcements::None = announcements {
+            continue;
+        }
+        let idx = match groups
+            .iter()
+            .position(|(group, _)| group.same_attributes(announcements))
+        {
+            Some(idx) => idx,
+            None => {
+                groups.push((announcements, vec![]));
+                groups.len() - 1
+            }
+        };
+        for prefix in announcements.prefixes() {
+            last.insert(*prefix, idx);
+            groups[idx].1.push(*prefix);
+        }
+    }
+    let mut seen = HashSet::new();
+    groups
+        .into_iter()
+        .enumerate()
+        .filter_map(|(idx, (announcements, prefixes))| {
+            if prefixes.is_empty() {
+                return Some(announcements.clone());
+            }
+            let prefixes: Vec<Prefix> = prefixes
+                .into_iter()
+                .filter(|prefix| last[prefix] == idx && seen.insert(*prefix))
+                .collect();
+            (!prefixes.is_empty())
+                .then(|| announcements.with_prefixes(prefixes))
+        })
+        .collect()
+}
+
+/// Returns Route Monitoring messages for withdrawals and announcements,
+/// splitting the prefixes over as many BGP UPDATEs as it takes to keep each
+/// within the 4096 octet maximum.
+///
+/// Each message carries as many prefixes as fit, withdrawals first.
+fn split_route_monitoring_msg(
+    per_peer_header: &PerPeerHeader,
+    withdrawals: &Prefixes,
+    announcements: &Announcements,
+    extra_path_attributes: &[u8],
+) -> Result<(Vec<Bytes>, Vec<Warning>), EncodeError> {
+    let whole = mk_route_monitoring_msg(
+        per_peer_header,
+        withdrawals,
+        announcements,
+        extra_path_attributes,
+    );
+    let announced = announcements.prefixes();
+    let total = withdrawals.len() + announced.len();
+    match whole {
+        Err(err) if err.is_size_error() && total > 1 => {}
+        res => return res.map(|(bytes, warnings)| (vec![bytes], warnings)),
+    }
+
+    // The message with prefixes `start..end` of the withdrawals followed by
+    // the announcements.
+    let encode = |start: usize, end: usize| {
+        let split = withdrawals.len();
+        let withdrawn = &withdrawals[start.min(split)..end.min(split)];
+        let announced = &announced
+            [start.saturating_sub(split)..end.saturating_sub(split)];
+        let announcements = match announced.is_empty() {
+            true => Announcements::None,
+            false => announcements.with_prefixes(announced.to_vec()),
+        };
+        mk_route_monitoring_msg(
+            per_peer_header,
+            &Prefixes(withdrawn.to_vec()),
+            &announcements,
+            extra_path_attributes,
+        )
+    };
+    let fits = |res: &Result<_, EncodeError>| match res {
+        Err(err) if err.is_size_error() => Ok(false),
+        Err(err) => Err(err.clone()),
+        Ok(_) => Ok(true),
+    };
+
+    let mut msgs = vec![];
+    let mut warnings = vec![];
+    let mut start = 0;
+    while start < total {
+        // A single prefix that doesn't fit cannot be helped.
+        let mut best = (start + 1, encode(start, start + 1)?);
+
+        // Double the number of prefixes until they no longer fit, then
+        // bisect between the most that fit and the fewest that don't.
+        let mut too_many = None;
+        while too_many.is_none() && best.0 < total {
+            let end = (start + 2 * (best.0 - start)).min(total);
+            let res = encode(start, end);
+            if fits(&res)? {
+                best = (end, res?);
+            } else {
+                too_many = Some(end);
+            }
+        }
+        if let Some(mut too_many) = too_many {
+            while too_many - best.0 > 1 {
+                let end = best.0 + (too_many - best.0) / 2;
+                let res = encode(start, end);
+                if fits(&res)? {
+                    best = (end, res?);
+                } else {
+                    too_many = end;
+                }
+            }
+        }
+
+        let (end, (bytes, more_warnings)) = best;
+        msgs.push(bytes);
+        for warning in more_warnings {
+            if !warnings.contains(&warning) {
+                warnings.push(warning);
+            }
+        }
+        start = end;
+    }
+    Ok((msgs, warnings))
 }
 
 // Returns the generated bytes and a, possibly empty, set of warning messages.
 pub fn mk_raw_route_monitoring_msg(
     per_peer_header: &PerPeerHeader,
     bgp_msg_buf: Bytes,
-) -> (Bytes, Vec<String>) {
+) -> Result<(Bytes, Vec<Warning>), EncodeError> {
     // 4.6.  Route Monitoring
     //
     // "Following the common BMP header and per-peer header is a BGP Update
@@ -429,11 +899,11 @@ pub fn mk_raw_route_monitoring_msg(
 
     let mut buf = BytesMut::new();
     push_bmp_common_header(&mut buf, MessageType::RouteMonitoring);
-    let warnings = push_bmp_per_peer_header(&mut buf, per_peer_header);
+    let warnings = push_bmp_per_peer_header(&mut buf, per_peer_header)?;
     buf.extend_from_slice(&bgp_msg_buf);
-    finalize_bmp_msg_len(&mut buf);
-    
-    (buf.freeze(), warnings)
+    finalize_bmp_msg_len(&mut buf)?;
+
+    Ok((buf.freeze(), warnings))
 }
 
 // Returns the generated bytes and a, possibly empty, set of warning messages.
@@ -443,7 +913,7 @@ pub fn mk_bgp_update(
     withdrawals: &Prefixes,
     announcements: &Announcements,
     extra_path_attributes: &[u8],
-) -> (Bytes, Vec<String>) {
+) -> Result<(Bytes, Vec<Warning>), EncodeError> {
     let mut warnings = vec![];
 
     // 4.3. UPDATE Message Format
@@ -466,17 +936,6 @@ pub fn mk_bgp_update(
     //
     // From: https://datatracker.ietf.org/doc/html/rfc4271#section-4.3
 
-    let mut buf = BytesMut::new();
-
-    // Fixed size BGP header
-    buf.resize(buf.len() + 16, 0xFFu8);
-    // marker
-    buf.resize(buf.len() + 2, 0);
-    // placeholder length, to be replaced later
-    buf.extend_from_slice(&2u8.to_be_bytes());
-    // 2 - UPDATE
-
-    // Other fields
     // Route withdrawals
     // "Withdrawn Routes Length:
     //
@@ -523,40 +982,12 @@ pub fn mk_bgp_update(
     let mut mp_unreach_nlri = BytesMut::new();
 
     for prefix in withdrawals.iter() {
-        let (addr, len) = prefix.addr_and_len();
-        match addr {
-            IpAddr::V4(addr) => {
-                withdrawn_routes.extend_from_slice(&[len]);
-                if len > 0 {
-                    let min_bytes = div_ceil(len, 8) as usize;
-                    withdrawn_routes
-                        .extend_from_slice(&addr.octets()[..min_bytes]);
-                }
-            }
-            IpAddr::V6(addr) => {
-                // https://datatracker.ietf.org/doc/html/rfc4760#section-4
-                if mp_unreach_nlri.is_empty() {
-                    mp_unreach_nlri.put_u16(Afi::Ipv6.into());
-                    mp_unreach_nlri.put_u8(
-                        u8::from(Safi::Unicast) | u8::from(Safi::Multicast),
-                    );
-                }
-                mp_unreach_nlri.extend_from_slice(&[len]);
-                if len > 0 {
-                    let min_bytes = div_ceil(len, 8) as usize;
-                    mp_unreach_nlri
-                        .extend_from_slice(&addr.octets()[..min_bytes]);
-                }
-            }
+        if prefix.is_v4() {
+            push_prefix(&mut withdrawn_routes, *prefix);
+        } else {
+            push_prefix(&mut mp_unreach_nlri, *prefix);
         }
     }
-    let num_withdrawn_route_bytes =
-        u16::try_from(withdrawn_routes.len()).unwrap();
-    buf.extend_from_slice(&num_withdrawn_route_bytes.to_be_bytes());
-    // N withdrawn route bytes
-    if num_withdrawn_route_bytes > 0 {
-        buf.extend(&withdrawn_routes); // the withdrawn routes
-    }
 
     // Route announcements
     // "Total Path Attribute Length:
@@ -633,10 +1064,17 @@ pub fn mk_bgp_update(
     //     irrelevant."
     //
     // From: https://datatracker.ietf.org/doc/html/rfc4271#section-4.3
+    let mut path_attributes = Vec::<u8>::new();
+
+    if !mp_unreach_nlri.is_empty() {
+        push_mp_unreach_nlri(&mut path_attributes, &mp_unreach_nlri)?;
+    }
+
+    // Now add the list of NLRI IP addresses
+    let mut announced_routes = Vec::<u8>::new();
+
     match announcements {
-        Announcements::None => {
-            buf.extend_from_slice(&0u16.to_be_bytes()); // 0 path attributes and no NLRI field
-        }
+        Announcements::None => {}
         Announcements::Some {
             origin,
             as_path,
@@ -644,115 +1082,6 @@ pub fn mk_bgp_update(
             communities,
             prefixes,
         } => {
-            fn push_attributes(
-                out_bytes: &mut Vec<u8>,
-                r#type: PathAttributeType,
-                pa_bytes: &[u8],
-            ) {
-                // Path Attributes:
-                // 
-                // A variable-length sequence of path attributes is present in
-                // every UPDATE message, except for an UPDATE message that carries
-                // only the withdrawn routes.  Each path attribute is a triple
-                // <attribute type, attribute length, attribute value> of variable
-                // length.
-                // 
-                // Attribute Type is a two-octet field that consists of the
-                // Attribute Flags octet, followed by the Attribute Type Code
-                // octet.
-                // 
-                //       0                   1
-                //       0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5
-                //       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-                //       |  Attr. Flags  |Attr. Type Code|
-                //       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
-                // 
-                // The high-order bit (bit 0) of the Attribute Flags octet is the
-                // Optional bit.  It defines whether the attribute is optional (if
-                // set to 1) or well-known (if set to 0).
-                // 
-                // The second high-order bit (bit 1) of the Attribute Flags octet
-                // is the Transitive bit.  It defines whether an optional
-                // attribute is transitive (if set to 1) or non-transitive (if set
-                // to 0).
-                // 
-                // For well-known attributes, the Transitive bit MUST be set to 1.
-                // (See Section 5 for a discussion of transitive attributes.)
-                // 
-                // The third high-order bit (bit 2) of the Attribute Flags octet
-                // is the Partial bit.  It defines whether the information
-                // contained in the optional transitive attribute is partial (if
-                // set to 1) or complete (if set to 0).  For well-known attributes
-                // and for optional non-transitive attributes, the Partial bit
-                // MUST be set to 0.
-                // 
-                // The fourth high-order bit (bit 3) of the Attribute Flags octet
-                // is the Extended Length bit.  It defines whether the Attribute
-                // Length is one octet (if set to 0) or two octets (if set to 1).
-                // 
-                // The lower-order four bits of the Attribute Flags octet are
-                // unused.  They MUST be zero when sent and MUST be ignored when
-                // received.
-                // 
-                // The Attribute Type Code octet contains the Attribute Type Code.
-                // Currently defined Attribute Type Codes are discussed in Section
-                // 5.
-                // 
-                // If the Extended Length bit of the Attribute Flags octet is set
-                // to 0, the third octet of the Path Attribute contains the length
-                // of the attribute data in octets.
-                // 
-                // If the Extended Length bit of the Attribute Flags octet is set
-                // to 1, the third and fourth octets of the path attribute contain
-                // the length of the attribute data in octets.
-                // 
-                // The remaining octets of the Path Attribute represent the
-                // attribute value and are interpreted according to the Attribute
-                // Flags and the Attribute Type Code.  The supported Attribute
-                // Type Codes, and their attribute values and uses are as follows:
-
-                let len = pa_bytes.len();
-
-                let (optional, transitive, partial) = match r#type {
-                    PathAttributeType::AsPath
-                    | PathAttributeType::NextHop
-                    | PathAttributeType::Origin => (false, true, false),
-                    PathAttributeType::Communities
-                    | PathAttributeType::ExtendedCommunities
-                    | PathAttributeType::LargeCommunities => (true, true, false),
-                    PathAttributeType::MpReachNlri => (true, false, false),
-                    _ => todo!(),
-                };
-
-                let mut flags = 0u8;
-                if optional {
-                    flags |= 0b1000_0000;
-                }
-                if !optional || transitive {
-                    flags |= 0b0100_0000;
-                }
-                if !optional || !transitive {
-                    flags &= 0b1101_1111;
-                } else if partial {
-                    flags |= 0b0010_0000;
-                }
-                if len > 255 {
-                    flags |= 0b0001_0000;
-                }
-
-                out_bytes.put_u8(flags); // attr. flags
-                out_bytes.put_u8(u8::from(r#type)); // attr. type
-                if len <= 255 {
-                    out_bytes.put_u8(u8::try_from(len).unwrap()); // attr. octet length
-                } else {
-                    out_bytes.put_u16(u16::try_from(len).unwrap()); // attr. octet length
-                };
-
-                out_bytes.extend_from_slice(pa_bytes);
-            }
-
-            let mut path_attributes = Vec::<u8>::new();
-
             // -------------------------------------------------------------------
             // "ORIGIN (Type Code 1):
             //
@@ -764,7 +1093,7 @@ pub fn mk_bgp_update(
                 &mut path_attributes,
                 PathAttributeType::Origin,
                 &[origin.into()],
-            );
+            )?;
 
             // -------------------------------------------------------------------
             // "AS_PATH (Type Code 2):
@@ -775,30 +1104,43 @@ pub fn mk_bgp_update(
             //  value>."
             //
             // From: https://datatracker.ietf.org/doc/html/rfc4271#section-4.3
-            let mut as_path_attr_value_bytes = Vec::<u8>::new();
-
             let as_path = if per_peer_header.is_legacy_two_byte_as_path_format() {
                 match as_path.try_to_asn16_path::<Vec<u8>>() {
                     Ok(as_path) => as_path,
-                    Err(err) => {
-                        warnings.push(format!("RFC 7854 section 4.2 Per-Peer Header violation: Peer Flags A-bit is SET but should be unset because the AS PATH being encoded contains ASNs that cannot be encoded in 16-bits: {err}"));
-                        as_path.to_as_path::<Vec<u8>>().unwrap()
+                    Err(_) => {
+                        let large_asn = as_path
+                            .iter()
+                            .flat_map(|hop| match hop {
+                                Hop::Asn(asn) => vec![*asn],
+                                Hop::Segment(seg) => seg.asns().collect(),
+                            })
+                            .find(|asn| asn.into_u32() > u32::from(u16::MAX));
+                        if let Some(asn) = large_asn {
+                            warnings.push(Warning::PeerFlagsABitSet {
+                                peer_flags: per_peer_header.peer_flags,
+                                asn,
+                            });
+                        }
+                        let Ok(as_path) = as_path.to_as_path::<Vec<u8>>();
+                        as_path
                     }
                 }
             } else {
-                as_path.to_as_path::<Vec<u8>>().unwrap()
+                let Ok(as_path) = as_path.to_as_path::<Vec<u8>>();
+                as_path
             };
 
             // sequence of AS path segments [(seg. type, seg. len, seg. val), ...]
-            for segment in as_path.segments() {
-                segment.compose(&mut as_path_attr_value_bytes).unwrap();
-            }
+            //
+            // Note: the octets are used as is, as re-composing the segments
+            // would widen a 2-byte path to 4-byte ASNs.
+            let as_path_attr_value_bytes = as_path.into_inner();
 
             push_attributes(
                 &mut path_attributes,
                 PathAttributeType::AsPath,
                 &as_path_attr_value_bytes,
-            );
+            )?;
 
             // -------------------------------------------------------------------
             // "NEXT_HOP (Type Code 3):
@@ -814,7 +1156,7 @@ pub fn mk_bgp_update(
                     &mut path_attributes,
                     PathAttributeType::NextHop,
                     &addr.octets(),
-                );
+                )?;
             }
 
             // -------------------------------------------------------------------
@@ -857,7 +1199,11 @@ pub fn mk_bgp_update(
                             extended_communities_attribute_bytes
                                 .extend_from_slice(&c.to_raw())
                         }
-                        Community::Ipv6Extended(_) => todo!(),
+                        Community::Ipv6Extended(_) => {
+                            return Err(EncodeError::UnsupportedCommunity(
+                                *community,
+                            ))
+                        }
                         Community::Large(c) => {
                             large_communities_attribute_bytes
                                 .extend_from_slice(&c.to_raw())
@@ -870,7 +1216,7 @@ pub fn mk_bgp_update(
                         &mut path_attributes,
                         PathAttributeType::Communities,
                         &communities_attribute_bytes,
-                    );
+                    )?;
                 }
 
                 if !extended_communities_attribute_bytes.is_empty() {
@@ -878,7 +1224,7 @@ pub fn mk_bgp_update(
                         &mut path_attributes,
                         PathAttributeType::ExtendedCommunities,
                         &extended_communities_attribute_bytes,
-                    );
+                    )?;
                 }
 
                 if !large_communities_attribute_bytes.is_empty() {
@@ -886,87 +1232,307 @@ pub fn mk_bgp_update(
                         &mut path_attributes,
                         PathAttributeType::LargeCommunities,
                         &large_communities_attribute_bytes,
-                    );
+                    )?;
                 }
             }
 
-            // Now add the list of NLRI IP addresses
-            let mut announced_routes = Vec::<u8>::new();
-            let mut mp_reach_nlri = BytesMut::new();
+            let mut mp_reach_nlri = Vec::<u8>::new();
 
             for prefix in prefixes.iter() {
-                let (addr, len) = prefix.addr_and_len();
-                match addr {
-                    IpAddr::V4(addr) => {
-                        announced_routes.extend_from_slice(&[len]);
-                        if len > 0 {
-                            let min_bytes = div_ceil(len, 8) as usize;
-                            announced_routes.extend_from_slice(
-                                &addr.octets()[..min_bytes],
-                            );
-                        }
-                    }
-                    IpAddr::V6(addr) => {
-                        // https://datatracker.ietf.org/doc/html/rfc4760#section-3
-                        if mp_reach_nlri.is_empty() {
-                            mp_reach_nlri.put_u16(Afi::Ipv6.into());
-                            mp_reach_nlri.put_u8(u8::from(Safi::Unicast));
-                            if let NextHop::Unicast(IpAddr::V6(addr)) = next_hop.0 {
-                                mp_reach_nlri
-                                    .put_u8(addr.octets().len() as u8);
-                                mp_reach_nlri
-                                    .extend_from_slice(&addr.octets());
-                            } else {
-                                unreachable!();
-                            }
-                            mp_reach_nlri.put_u8(0u8); // reserved
-                        }
-                        mp_reach_nlri.extend_from_slice(&[len]);
-                        if len > 0 {
-                            let min_bytes = div_ceil(len, 8) as usize;
-                            mp_reach_nlri.extend_from_slice(
-                                &addr.octets()[..min_bytes],
-                            );
-                        }
-                    }
+                if prefix.is_v4() {
+                    push_prefix(&mut announced_routes, *prefix);
+                } else {
+                    push_prefix(&mut mp_reach_nlri, *prefix);
                 }
             }
 
             if !mp_reach_nlri.is_empty() {
-                push_attributes(
+                let NextHop::Unicast(IpAddr::V6(addr)) = next_hop.0 else {
+                    return Err(EncodeError::NextHopFamilyMismatch(
+                        next_hop.0,
+                    ));
+                };
+                push_mp_reach_nlri(
                     &mut path_attributes,
-                    PathAttributeType::MpReachNlri,
+                    &addr.octets(),
                     &mp_reach_nlri,
-                );
+                )?;
             }
 
-            let num_path_attribute_bytes = u16::try_from(
-                path_attributes.len() + extra_path_attributes.len(),
-            )
-            .unwrap();
-            buf.extend_from_slice(&num_path_attribute_bytes.to_be_bytes()); // N path attribute bytes
-            buf.extend_from_slice(&path_attributes);
-            buf.extend_from_slice(extra_path_attributes);
-
-            if !announced_routes.is_empty() {
-                buf.extend_from_slice(&announced_routes); // the announced routes
-            }
+            path_attributes.extend_from_slice(extra_path_attributes);
         }
     }
 
+    let buf = mk_raw_bgp_update(
+        &withdrawn_routes,
+        &path_attributes,
+        &announced_routes,
+    )?;
+    Ok((buf, warnings))
+}
+
+/// Returns a BGP UPDATE message with the given withdrawn routes, path
+/// attributes and NLRI, each already in its wire format.
+pub(crate) fn mk_raw_bgp_update(
+    withdrawn_routes: &[u8],
+    path_attributes: &[u8],
+    nlri: &[u8],
+) -> Result<Bytes, EncodeError> {
+    let mut buf = BytesMut::new();
+
+    // Fixed size BGP header
+    buf.resize(buf.len() + 16, 0xFFu8);
+    // marker
+    buf.resize(buf.len() + 2, 0);
+    // placeholder length, to be replaced later
+    buf.extend_from_slice(&2u8.to_be_bytes());
+    // 2 - UPDATE
+
+    // Other fields
+    let num_withdrawn_route_bytes = u16::try_from(withdrawn_routes.len())
+        .map_err(|_| {
+            EncodeError::WithdrawnRoutesTooLong(withdrawn_routes.len())
+        })?;
+    buf.extend_from_slice(&num_withdrawn_route_bytes.to_be_bytes());
+    buf.extend_from_slice(withdrawn_routes); // the withdrawn routes
+
+    let num_path_attribute_bytes = u16::try_from(path_attributes.len())
+        .map_err(|_| {
+            EncodeError::PathAttributesTooLong(path_attributes.len())
+        })?;
+    // N path attribute bytes
+    buf.extend_from_slice(&num_path_attribute_bytes.to_be_bytes());
+    buf.extend_from_slice(path_attributes);
+    buf.extend_from_slice(nlri); // the announced routes
+
     // Finalize BGP message
-    finalize_bgp_msg_len(&mut buf);
-    
-    (buf.freeze(), warnings)
+    finalize_bgp_msg_len(&mut buf)?;
+
+    Ok(buf.freeze())
+}
+
+/// Returns the End-of-RIB marker UPDATE for IPv4 or IPv6 unicast.
+///
+/// "An UPDATE message with no reachable Network Layer Reachability
+///  Information (NLRI) and empty withdrawn NLRI is specified as the
+///  End-of-RIB marker that can be used by a BGP speaker to indicate to its
+///  peer the completion of the initial routing update after the session is
+///  established. For the IPv4 unicast address family, the End-of-RIB marker
+///  is an UPDATE message with the minimum length [BGP-4]. For any other
+///  address family, it is an UPDATE message that contains only the
+///  MP_UNREACH_NLRI attribute [BGP-MP] with no withdrawn routes for that
+///  <AFI, SAFI>."
+///
+/// From: https://www.rfc-editor.org/rfc/rfc4724#section-2
+pub(crate) fn mk_end_of_rib(ipv6: bool) -> Result<Bytes, EncodeError> {
+    let mut path_attributes = vec![];
+    if ipv6 {
+        push_mp_unreach_nlri(&mut path_attributes, &[])?;
+    }
+    mk_raw_bgp_update(&[], &path_attributes, &[])
+}
+
+/// Appends a prefix in the NLRI encoding.
+///
+/// "The Prefix field contains an IP address prefix, followed by the
+///  minimum number of trailing bits needed to make the end of the field
+///  fall on an octet boundary."
+///
+/// From: https://datatracker.ietf.org/doc/html/rfc4271#section-4.3
+pub(crate) fn push_prefix(buf: &mut impl BufMut, prefix: Prefix) {
+    let (addr, len) = prefix.addr_and_len();
+    let min_bytes = div_ceil(len, 8) as usize;
+    buf.put_u8(len);
+    match addr {
+        IpAddr::V4(addr) => buf.put_slice(&addr.octets()[..min_bytes]),
+        IpAddr::V6(addr) => buf.put_slice(&addr.octets()[..min_bytes]),
+    }
+}
+
+/// Returns the length of a prefix in the NLRI encoding.
+pub(crate) fn prefix_len(prefix: Prefix) -> usize {
+    1 + div_ceil(prefix.len(), 8) as usize
+}
+
+/// Appends an MP_REACH_NLRI attribute for IPv6 unicast.
+///
+/// https://datatracker.ietf.org/doc/html/rfc4760#section-3
+pub(crate) fn push_mp_reach_nlri(
+    path_attributes: &mut Vec<u8>,
+    next_hop: &[u8],
+    nlri: &[u8],
+) -> Result<(), EncodeError> {
+    let nh_len = u8::try_from(next_hop.len()).map_err(|_| {
+        EncodeError::PathAttributeTooLong(
+            PathAttributeType::MpReachNlri,
+            next_hop.len(),
+        )
+    })?;
+    let mut mp_reach_nlri = BytesMut::new();
+    mp_reach_nlri.put_u16(Afi::Ipv6.into());
+    mp_reach_nlri.put_u8(u8::from(Safi::Unicast));
+    mp_reach_nlri.put_u8(nh_len);
+    mp_reach_nlri.extend_from_slice(next_hop);
+    mp_reach_nlri.put_u8(0u8); // reserved
+    mp_reach_nlri.extend_from_slice(nlri);
+    push_attributes(
+        path_attributes,
+        PathAttributeType::MpReachNlri,
+        &mp_reach_nlri,
+    )
+}
+
+/// Appends an MP_UNREACH_NLRI attribute for IPv6 unicast.
+///
+/// https://datatracker.ietf.org/doc/html/rfc4760#section-4
+fn push_mp_unreach_nlri(
+    path_attributes: &mut Vec<u8>,
+    withdrawn_routes: &[u8],
+) -> Result<(), EncodeError> {
+    let mut mp_unreach_nlri = BytesMut::new();
+    mp_unreach_nlri.put_u16(Afi::Ipv6.into());
+    mp_unreach_nlri.put_u8(u8::from(Safi::Unicast));
+    mp_unreach_nlri.extend_from_slice(withdrawn_routes);
+    push_attributes(
+        path_attributes,
+        PathAttributeType::MpUnreachNlri,
+        &mp_unreach_nlri,
+    )
+}
+
+fn push_attributes(
+    out_bytes: &mut Vec<u8>,
+    r#type: PathAttributeType,
+    pa_bytes: &[u8],
+) -> Result<(), EncodeError> {
+    // Path Attributes:
+    //
+    // A variable-length sequence of path attributes is present in
+    // every UPDATE message, except for an UPDATE message that carries
+    // only the withdrawn routes.  Each path attribute is a triple
+    // <attribute type, attribute length, attribute value> of variable
+    // length.
+    //
+    // Attribute Type is a two-octet field that consists of the
+    // Attribute Flags octet, followed by the Attribute Type Code
+    // octet.
+    //
+    //       0                   1
+    //       0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5
+    //       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    //       |  Attr. Flags  |Attr. Type Code|
+    //       +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+    //
+    // The high-order bit (bit 0) of the Attribute Flags octet is the
+    // Optional bit.  It defines whether the attribute is optional (if
+    // set to 1) or well-known (if set to 0).
+    //
+    // The second high-order bit (bit 1) of the Attribute Flags octet
+    // is the Transitive bit.  It defines whether an optional
+    // attribute is transitive (if set to 1) or non-transitive (if set
+    // to 0).
+    //
+    // For well-known attributes, the Transitive bit MUST be set to 1.
+    // (See Section 5 for a discussion of transitive attributes.)
+    //
+    // The third high-order bit (bit 2) of the Attribute Flags octet
+    // is the Partial bit.  It defines whether the information
+    // contained in the optional transitive attribute is partial (if
+    // set to 1) or complete (if set to 0).  For well-known attributes
+    // and for optional non-transitive attributes, the Partial bit
+    // MUST be set to 0.
+    //
+    // The fourth high-order bit (bit 3) of the Attribute Flags octet
+    // is the Extended Length bit.  It defines whether the Attribute
+    // Length is one octet (if set to 0) or two octets (if set to 1).
+    //
+    // The lower-order four bits of the Attribute Flags octet are
+    // unused.  They MUST be zero when sent and MUST be ignored when
+    // received.
+    //
+    // The Attribute Type Code octet contains the Attribute Type Code.
+    // Currently defined Attribute Type Codes are discussed in Section
+    // 5.
+    //
+    // If the Extended Length bit of the Attribute Flags octet is set
+    // to 0, the third octet of the Path Attribute contains the length
+    // of the attribute data in octets.
+    //
+    // If the Extended Length bit of the Attribute Flags octet is set
+    // to 1, the third and fourth octets of the path attribute contain
+    // the length of the attribute data in octets.
+    //
+    // The remaining octets of the Path Attribute represent the
+    // attribute value and are interpreted according to the Attribute
+    // Flags and the Attribute Type Code.  The supported Attribute
+    // Type Codes, and their attribute values and uses are as follows:
+
+    let len = pa_bytes.len();
+
+    let (optional, transitive, partial) = match r#type {
+        PathAttributeType::AsPath
+        | PathAttributeType::NextHop
+        | PathAttributeType::Origin => (false, true, false),
+        PathAttributeType::Communities
+        | PathAttributeType::ExtendedCommunities
+        | PathAttributeType::LargeCommunities => (true, true, false),
+        PathAttributeType::MpReachNlri
+        | PathAttributeType::MpUnreachNlri => (true, false, false),
+        _ => {
+            return Err(EncodeError::UnsupportedPathAttribute(
+                r#type,
+            ))
+        }
+    };
+
+    let mut flags = 0u8;
+    if optional {
+        flags |= 0b1000_0000;
+    }
+    if !optional || transitive {
+        flags |= 0b0100_0000;
+    }
+    if !optional || !transitive {
+        flags &= 0b1101_1111;
+    } else if partial {
+        flags |= 0b0010_0000;
+    }
+    if len > 255 {
+        flags |= 0b0001_0000;
+    }
+
+    out_bytes.put_u8(flags); // attr. flags
+    out_bytes.put_u8(u8::from(r#type)); // attr. type
+    if let Ok(len) = u8::try_from(len) {
+        out_bytes.put_u8(len); // attr. octet length
+    } else if let Ok(len) = u16::try_from(len) {
+        out_bytes.put_u16(len); // attr. octet length
+    } else {
+        return Err(EncodeError::PathAttributeTooLong(
+            r#type, len,
+        ));
+    };
+
+    out_bytes.extend_from_slice(pa_bytes);
+    Ok(())
 }
 
 // Returns the generated bytes and a, possibly empty, set of warning messages.
 pub fn mk_peer_down_notification_msg(
     per_peer_header: &PerPeerHeader,
-) -> (Bytes, Vec<String>) {
+) -> Result<(Bytes, Vec<Warning>), EncodeError> {
+    mk_peer_down_msg(per_peer_header, 5, &[])
+}
+
+// Returns the generated bytes and a, possibly empty, set of warning messages.
+pub(crate) fn mk_peer_down_msg(
+    per_peer_header: &PerPeerHeader,
+    reason: u8,
+    data: &[u8],
+) -> Result<(Bytes, Vec<Warning>), EncodeError> {
     let mut buf = BytesMut::new();
     push_bmp_common_header(&mut buf, MessageType::PeerDownNotification);
-    let warnings = push_bmp_per_peer_header(&mut buf, per_peer_header);
+    let warnings = push_bmp_per_peer_header(&mut buf, per_peer_header)?;
 
     // 4.9.  Peer Down Notification
     //
@@ -981,15 +1547,18 @@ pub fn mk_peer_down_notification_msg(
     //
     // From: https://www.rfc-editor.org/rfc/rfc7854.html#section-4.9
 
-    buf.extend_from_slice(&5u8.to_be_bytes()); // reason code 5
+    buf.extend_from_slice(&reason.to_be_bytes());
+    buf.extend_from_slice(data);
+
+    finalize_bmp_msg_len(&mut buf)?;
 
-    finalize_bmp_msg_len(&mut buf);
-    
-    (buf.freeze(), warnings)
+    Ok((buf.freeze(), warnings))
 }
 
 // Returns the generated bytes and a, possibly empty, set of warning messages.
-pub fn mk_statistics_report_msg(per_peer_header: &PerPeerHeader) -> (Bytes, Vec<String>) {
+pub fn mk_statistics_report_msg(
+    per_peer_header: &PerPeerHeader,
+) -> Result<(Bytes, Vec<Warning>), EncodeError> {
     // 4.8.  Stats Reports
     //
     // "Following the common BMP header and per-peer header is a 4-byte field
@@ -1022,16 +1591,16 @@ pub fn mk_statistics_report_msg(per_peer_header: &PerPeerHeader) -> (Bytes, Vec<
 
     let mut buf = BytesMut::new();
     push_bmp_common_header(&mut buf, MessageType::StatisticsReport);
-    let warnings = push_bmp_per_peer_header(&mut buf, per_peer_header);
+    let warnings = push_bmp_per_peer_header(&mut buf, per_peer_header)?;
 
     buf.extend_from_slice(&0u32.to_be_bytes()); // zero stats
 
-    finalize_bmp_msg_len(&mut buf);
-    
-    (buf.freeze(), warnings)
+    finalize_bmp_msg_len(&mut buf)?;
+
+    Ok((buf.freeze(), warnings))
 }
 
-pub fn mk_termination_msg() -> Bytes {
+pub fn mk_termination_msg() -> Result<Bytes, EncodeError> {
     let mut buf = BytesMut::new();
     push_bmp_common_header(&mut buf, MessageType::TerminationMessage);
 
@@ -1060,27 +1629,31 @@ pub fn mk_termination_msg() -> Bytes {
         &mut buf,
         TerminationInformation::AdminClose,
         &[0u8, 0u8],
-    );
+    )?;
 
-    finalize_bmp_msg_len(&mut buf);
-    buf.freeze()
+    finalize_bmp_msg_len(&mut buf)?;
+    Ok(buf.freeze())
 }
 
-fn finalize_bmp_msg_len(buf: &mut BytesMut) {
-    let len_bytes: [u8; 4] = (buf.len() as u32).to_be_bytes();
-    buf[1] = len_bytes[0];
-    buf[2] = len_bytes[1];
-    buf[3] = len_bytes[2];
-    buf[4] = len_bytes[3];
+fn finalize_bmp_msg_len(buf: &mut BytesMut) -> Result<(), EncodeError> {
+    let len = u32::try_from(buf.len())
+        .map_err(|_| EncodeError::BmpMessageTooLong(buf.len()))?;
+    buf[1..5].copy_from_slice(&len.to_be_bytes());
+    Ok(())
 }
 
-fn finalize_bgp_msg_len(buf: &mut BytesMut) {
-    assert!(buf.len() >= 19);
-    assert!(buf.len() <= 4096);
+fn finalize_bgp_msg_len(buf: &mut BytesMut) -> Result<(), EncodeError> {
+    if buf.len() < 19 {
+        return Err(EncodeError::BgpMessageTooShort(buf.len()));
+    }
+    if buf.len() > MAX_BGP_MSG_LEN {
+        return Err(EncodeError::BgpMessageTooLong(buf.len()));
+    }
 
     let len_bytes: [u8; 2] = (buf.len() as u16).to_be_bytes();
     buf[16] = len_bytes[0];
     buf[17] = len_bytes[1];
+    Ok(())
 }
 
 fn push_bmp_common_header(buf: &mut BytesMut, msg_type: MessageType) {
@@ -1100,7 +1673,7 @@ fn push_bmp_common_header(buf: &mut BytesMut, msg_type: MessageType) {
     buf.extend_from_slice(&u8::from(msg_type).to_be_bytes());
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct PerPeerHeader {
     pub peer_type: MyPeerType,
     pub peer_flags: u8,
@@ -1108,9 +1681,82 @@ pub struct PerPeerHeader {
     pub peer_address: IpAddr,
     pub peer_as: Asn,
     pub peer_bgp_id: [u8; 4],
+
+    /// The timestamp to use instead of the current system time.
+    pub timestamp: Option<Timestamp>,
 }
 
 impl PerPeerHeader {
+    /// Creates a Per-Peer Header for a global instance peer.
+    ///
+    /// The V flag is set according to the address family of the peer
+    /// address and the BGP ID is derived from the last four octets of the
+    /// peer address. Use the other methods to override these defaults.
+    pub fn new(peer_address: IpAddr, peer_as: Asn) -> Self {
+        let (peer_flags, peer_bgp_id) = match peer_address {
+            IpAddr::V4(addr) => (0, addr.octets()),
+            IpAddr::V6(addr) => {
+                let octets = addr.octets();
+                (0x80, [octets[12], octets[13], octets[14], octets[15]])
+            }
+        };
+        PerPeerHeader {
+            peer_type: PeerType::GlobalInstance.into(),
+            peer_flags,
+            peer_distinguisher: [0u8; 8],
+            peer_address,
+            peer_as,
+            peer_bgp_id,
+            timestamp: None,
+        }
+    }
+
+    pub fn peer_type(mut self, peer_type: MyPeerType) -> Self {
+        self.peer_type = peer_type;
+        self
+    }
+
+    pub fn flags(mut self, peer_flags: u8) -> Self {
+        self.peer_flags = peer_flags;
+        self
+    }
+
+    pub fn distinguisher(mut self, peer_distinguisher: [u8; 8]) -> Self {
+        self.peer_distinguisher = peer_distinguisher;
+        self
+    }
+
+    pub fn bgp_id(mut self, peer_bgp_id: [u8; 4]) -> Self {
+        self.peer_bgp_id = peer_bgp_id;
+        self
+    }
+
+    /// Uses the given timestamp instead of the current system time.
+    pub fn timestamp(mut self, timestamp: Timestamp) -> Self {
+        self.timestamp = Some(timestamp);
+        self
+    }
+
+    /// Sets or clears the L flag, i.e. post-policy Adj-RIB-In.
+    pub fn post_policy(mut self, post_policy: bool) -> Self {
+        if post_policy {
+            self.peer_flags |= 0x40;
+        } else {
+            self.peer_flags &= !0x40;
+        }
+        self
+    }
+
+    /// Sets or clears the A flag, i.e. the legacy 2-byte AS_PATH format.
+    pub fn legacy_as_path(mut self, legacy: bool) -> Self {
+        if legacy {
+            self.peer_flags |= 0x20;
+        } else {
+            self.peer_flags &= !0x20;
+        }
+        self
+    }
+
     fn is_ipv4(&self) -> bool {
         self.peer_flags & 0x80 == 0
     }
@@ -1124,19 +1770,63 @@ impl PerPeerHeader {
     }
 }
 
-pub fn mk_per_peer_header(peer_ip: &str, peer_as: u32) -> PerPeerHeader {
-    PerPeerHeader {
+pub fn mk_per_peer_header(
+    peer_ip: &str,
+    peer_as: u32,
+) -> Result<PerPeerHeader, EncodeError> {
+    let peer_address = peer_ip
+        .parse()
+        .map_err(|_| EncodeError::InvalidPeerAddress(peer_ip.to_string()))?;
+    Ok(PerPeerHeader {
         peer_type: PeerType::GlobalInstance.into(),
         peer_flags: 0,
         peer_distinguisher: [0u8; 8],
-        peer_address: peer_ip.parse().unwrap(),
+        peer_address,
         peer_as: Asn::from_u32(peer_as),
         peer_bgp_id: [1u8, 2u8, 3u8, 4u8],
+        timestamp: None,
+    })
+}
+
+/// The content of a BGP OPEN message included in a Peer Up Notification.
+///
+/// The hold time defaults to zero, which disables keep alives.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Open {
+    pub asn: u16,
+    pub hold_time: u16,
+    pub bgp_id: u32,
+    pub eor_capable: bool,
+}
+
+impl Open {
+    pub fn new(asn: u16, bgp_id: u32) -> Self {
+        Open {
+            asn,
+            hold_time: 0,
+            bgp_id,
+            eor_capable: false,
+        }
+    }
+
+    pub fn hold_time(mut self, hold_time: u16) -> Self {
+        self.hold_time = hold_time;
+        self
+    }
+
+    /// Whether to advertise the Graceful Restart capability, and thus
+    /// the ability to send End-of-RIB markers.
+    pub fn eor_capable(mut self, eor_capable: bool) -> Self {
+        self.eor_capable = eor_capable;
+        self
     }
 }
 
 // Returns warnings about incorrect Per-Peer Header flags, if any.
-fn push_bmp_per_peer_header(buf: &mut BytesMut, pph: &PerPeerHeader) -> Vec<String> {
+fn push_bmp_per_peer_header(
+    buf: &mut BytesMut,
+    pph: &PerPeerHeader,
+) -> Result<Vec<Warning>, EncodeError> {
     let mut warnings = vec![];
 
     //  0                   1                   2                   3
@@ -1201,9 +1891,10 @@ fn push_bmp_per_peer_header(buf: &mut BytesMut, pph: &PerPeerHeader) -> Vec<Stri
     //  unavailable.  Precision of the timestamp is implementation-dependent."
     //
     // From: https://www.rfc-editor.org/rfc/rfc7854.html#section-4.2
-    let now = Utc::now();
-    let epoch_seconds = u32::try_from(now.timestamp()).unwrap();
-    let epoch_micros = now.timestamp_subsec_micros();
+    let timestamp = match pph.timestamp {
+        Some(timestamp) => timestamp,
+        None => SystemClock::new().now()?,
+    };
 
     buf.put_u8(u8::from(*pph.peer_type));
     buf.put_u8(pph.peer_flags);
@@ -1221,30 +1912,36 @@ fn push_bmp_per_peer_header(buf: &mut BytesMut, pph: &PerPeerHeader) -> Vec<Stri
             buf.resize(buf.len() + 12, 0u8);
             buf.extend_from_slice(&addr.octets());
             if pph.is_ipv6() {
-                warnings.push(format!("RFC 7854 section 4.2 Per-Peer Header violation: Peer Flags V-bit is SET but should be unset because peer address {addr} is an IPv4 address, not IPv6."));
+                warnings.push(Warning::PeerFlagsVBitSet {
+                    peer_flags: pph.peer_flags,
+                    peer_address: addr,
+                });
             }
         }
         IpAddr::V6(addr) => {
             buf.extend_from_slice(&addr.octets());
             if pph.is_ipv4() {
-                warnings.push(format!("RFC 7854 section 4.2 Per-Peer Header violation: Peer Flags V-bit is NOT set but should be set because peer address {addr} is an IPv6 address, not IPv4."));
+                warnings.push(Warning::PeerFlagsVBitUnset {
+                    peer_flags: pph.peer_flags,
+                    peer_address: addr,
+                });
             }
         }
     }
 
     buf.extend_from_slice(&pph.peer_as.into_u32().to_be_bytes()); // assumes 32-bit ASN
     buf.extend_from_slice(&pph.peer_bgp_id);
-    buf.extend_from_slice(&epoch_seconds.to_be_bytes());
-    buf.extend_from_slice(&epoch_micros.to_be_bytes());
+    buf.extend_from_slice(&timestamp.secs.to_be_bytes());
+    buf.extend_from_slice(&timestamp.micros.to_be_bytes());
 
-    warnings
+    Ok(warnings)
 }
 
 fn push_bmp_information_tlv(
     buf: &mut BytesMut,
     tlv_type: InformationTlvType,
     tlv_value: &[u8],
-) {
+) -> Result<(), EncodeError> {
     //  0                   1                   2                   3
     //  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
     // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
@@ -1255,16 +1952,19 @@ fn push_bmp_information_tlv(
     // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
     //
     // From: https://www.rfc-editor.org/rfc/rfc7854.html#section-4.4
-    buf.extend_from_slice(&information_tlv_type_to_be_bytes(tlv_type));
-    buf.extend_from_slice(&(tlv_value.len() as u16).to_be_bytes());
+    let len = u16::try_from(tlv_value.len())
+        .map_err(|_| EncodeError::InformationTlvTooLong(tlv_value.len()))?;
+    buf.extend_from_slice(&information_tlv_type_to_be_bytes(tlv_type)?);
+    buf.extend_from_slice(&len.to_be_bytes());
     buf.extend_from_slice(tlv_value);
+    Ok(())
 }
 
 fn push_bmp_termination_tlv(
     buf: &mut BytesMut,
     tlv_type: TerminationInformation,
     tlv_value: &[u8],
-) {
+) -> Result<(), EncodeError> {
     //  0                   1                   2                   3
     //  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
     // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
@@ -1275,33 +1975,42 @@ fn push_bmp_termination_tlv(
     // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
     //
     // From: https://www.rfc-editor.org/rfc/rfc7854.html#section-4.4
-    buf.extend_from_slice(&termination_tlv_type_to_be_bytes(tlv_type));
-    buf.extend_from_slice(&(tlv_value.len() as u16).to_be_bytes());
+    let len = u16::try_from(tlv_value.len())
+        .map_err(|_| EncodeError::InformationTlvTooLong(tlv_value.len()))?;
+    buf.extend_from_slice(&termination_tlv_type_to_be_bytes(tlv_type)?);
+    buf.extend_from_slice(&len.to_be_bytes());
     buf.extend_from_slice(tlv_value);
+    Ok(())
 }
 
-fn information_tlv_type_to_be_bytes(typ: InformationTlvType) -> [u8; 2] {
+fn information_tlv_type_to_be_bytes(
+    typ: InformationTlvType,
+) -> Result<[u8; 2], EncodeError> {
     match typ {
-        InformationTlvType::String => 0u16.to_be_bytes(),
-        InformationTlvType::SysDesc => 1u16.to_be_bytes(),
-        InformationTlvType::SysName => 2u16.to_be_bytes(),
-        _ => unreachable!(),
+        InformationTlvType::String => Ok(0u16.to_be_bytes()),
+        InformationTlvType::SysDesc => Ok(1u16.to_be_bytes()),
+        InformationTlvType::SysName => Ok(2u16.to_be_bytes()),
+        _ => Err(EncodeError::UnsupportedInformationTlv(typ)),
     }
 }
 
-fn termination_tlv_type_to_be_bytes(typ: TerminationInformation) -> [u8; 2] {
+fn termination_tlv_type_to_be_bytes(
+    typ: TerminationInformation,
+) -> Result<[u8; 2], EncodeError> {
     match typ {
-        TerminationInformation::CustomString(_) => 0u16.to_be_bytes(),
+        TerminationInformation::CustomString(_) => Ok(0u16.to_be_bytes()),
         TerminationInformation::AdminClose
         | TerminationInformation::Unspecified
         | TerminationInformation::OutOfResources
         | TerminationInformation::RedundantConnection
-        | TerminationInformation::PermAdminClose => 1u16.to_be_bytes(),
-        TerminationInformation::Undefined(_) => unreachable!(),
+        | TerminationInformation::PermAdminClose => Ok(1u16.to_be_bytes()),
+        TerminationInformation::Undefined(reason) => {
+            Err(EncodeError::UnsupportedTerminationReason(reason))
+        }
     }
 }
 
-#[derive(Debug, PartialEq, Eq)]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct MyPeerType(PeerType);
 
 impl From<PeerType> for MyPeerType {
@@ -1324,14 +2033,18 @@ impl FromStr for MyPeerType {
     fn from_str(s: &str) -> Result<Self, Self::Err> {
         let peer_type = match s {
             "global" => PeerType::GlobalInstance,
-            _ => todo!(),
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Unsupported peer type '{s}', expected: global"
+                ))
+            }
         };
 
         Ok(MyPeerType(peer_type))
     }
 }
 
-#[derive(Default)]
+#[derive(Clone, Debug, Default)]
 pub struct Prefixes(Vec<Prefix>);
 
 impl Prefixes {
@@ -1348,6 +2061,8 @@ impl Deref for Prefixes {
     }
 }
 
+/// Parses a comma separated list of prefixes and prefix ranges, see
+/// [`prefix_range`].
 impl FromStr for Prefixes {
     type Err = anyhow::Error;
 
@@ -1358,7 +2073,12 @@ impl FromStr for Prefixes {
             _ => {
                 let mut prefixes = Vec::new();
                 for prefix_str in s.split(',') {
-                    prefixes.push(prefix_str.parse()?);
+                    prefixes.extend(prefix_range(prefix_str)?);
+                    if prefixes.len() > MAX_PREFIX_RANGE {
+                        return Err(anyhow::anyhow!(
+                            "More than {MAX_PREFIX_RANGE} prefixes"
+                        ));
+                    }
                 }
                 Ok(Prefixes(prefixes))
             }
@@ -1366,6 +2086,127 @@ impl FromStr for Prefixes {
     }
 }
 
+/// The most prefixes a range expands into.
+pub const MAX_PREFIX_RANGE: usize = 1 << 20;
+
+/// Parses a prefix or a range of prefixes of the same length.
+///
+/// Besides a single prefix this accepts `<prefix> split /<len>` for all
+/// more specifics of a length, e.g. `10.0.0.0/16 split /24`, `<prefix>
+/// first <count> /<len>` for the first of these, e.g. `2001:db8::/32 first
+/// 1000 /48`, and `<prefix>..<prefix>` for the prefixes from one up to and
+/// including the other, e.g. `10.0.0.0/24..10.0.9.0/24`. A range expands
+/// into at most [`MAX_PREFIX_RANGE`] prefixes.
+pub fn prefix_range(s: &str) -> Result<Vec<Prefix>, anyhow::Error> {
+    let s = s.trim();
+    let parse_prefix = |s: &str| {
+        Prefix::from_str(s.trim())
+            .map_err(|err| anyhow::anyhow!("Invalid prefix '{s}': {err}"))
+    };
+    let parse_len = |s: &str| {
+        s.trim()
+            .strip_prefix('/')
+            .and_then(|len| len.parse::<u8>().ok())
+            .ok_or_else(|| anyhow::anyhow!("Expected /<len>, not '{s}'"))
+    };
+
+    let (first, len, count) = if let Some((prefix, len)) =
+        s.split_once(" split ")
+    {
+        let prefix = parse_prefix(prefix)?;
+        let len = parse_len(len)?;
+        let count = more_specifics(prefix, len)?;
+        (prefix, len, count)
+    } else if let Some((prefix, rest)) = s.split_once(" first ") {
+        let prefix = parse_prefix(prefix)?;
+        let (count, len) = rest.trim().split_once(' ').ok_or_else(|| {
+            anyhow::anyhow!("Expected first <count> /<len>")
+        })?;
+        let count = count
+            .parse::<u128>()
+            .map_err(|_| anyhow::anyhow!("Invalid count '{count}'"))?;
+        let len = parse_len(len)?;
+        let available = more_specifics(prefix, len)?;
+        if count > available {
+            return Err(anyhow::anyhow!(
+                "{prefix} has only {available} /{len} more specifics"
+            ));
+        }
+        (prefix, len, count)
+    } else if let Some((from, to)) = s.split_once("..") {
+        let from = parse_prefix(from)?;
+        let to = parse_prefix(to)?;
+        if from.is_v4() != to.is_v4() || from.len() != to.len() {
+            return Err(anyhow::anyhow!(
+                "A range needs prefixes of the same family and length"
+            ));
+        }
+        if address(to) < address(from) {
+            return Err(anyhow::anyhow!("{to} comes before {from}"));
+        }
+        let count = (address(to) - address(from))
+            .checked_shr(host_bits(from))
+            .unwrap_or(0);
+        (from, from.len(), count.saturating_add(1))
+    } else {
+        let prefix = parse_prefix(s)?;
+        (prefix, prefix.len(), 1)
+    };
+
+    if count > MAX_PREFIX_RANGE as u128 {
+        return Err(anyhow::anyhow!(
+            "'{s}' expands into more than {MAX_PREFIX_RANGE} prefixes"
+        ));
+    }
+    let v4 = first.is_v4();
+    let start = address(first);
+    let bits = if v4 { 32 } else { 128 };
+    // A step of 2^128 happens for ::/0, of which there is only one.
+    let step = 1u128.checked_shl(u32::from(bits - len)).unwrap_or(0);
+    (0..count)
+        .map(|i| {
+            let addr = start + i * step;
+            let addr = if v4 {
+                IpAddr::from(Ipv4Addr::from(addr as u32))
+            } else {
+                IpAddr::from(Ipv6Addr::from(addr))
+            };
+            Prefix::new(addr, len).map_err(|err| {
+                anyhow::anyhow!("Invalid prefix {addr}/{len}: {err}")
+            })
+        })
+        .collect()
+}
+
+/// Returns the number of more specifics of a length that make up a prefix,
+/// saturating at `u128::MAX`.
+fn more_specifics(prefix: Prefix, len: u8) -> Result<u128, anyhow::Error> {
+    let bits = if prefix.is_v4() { 32 } else { 128 };
+    if len < prefix.len() || len > bits {
+        return Err(anyhow::anyhow!(
+            "Cannot split /{} into /{len}",
+            prefix.len()
+        ));
+    }
+    Ok(1u128
+        .checked_shl(u32::from(len - prefix.len()))
+        .unwrap_or(u128::MAX))
+}
+
+/// Returns the address of a prefix as a number.
+fn address(prefix: Prefix) -> u128 {
+    match prefix.addr() {
+        IpAddr::V4(addr) => u128::from(u32::from(addr)),
+        IpAddr::V6(addr) => u128::from(addr),
+    }
+}
+
+/// Returns the number of bits after the prefix length.
+fn host_bits(prefix: Prefix) -> u32 {
+    let bits = if prefix.is_v4() { 32 } else { 128 };
+    u32::from(bits - prefix.len())
+}
+
 // Based on `div_ceil()` from Rust nightly.
 pub const fn div_ceil(lhs: u8, rhs: u8) -> u8 {
     let d = lhs / rhs;
@@ -1377,8 +2218,15 @@ pub const fn div_ceil(lhs: u8, rhs: u8) -> u8 {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct MyOriginType(OriginType);
 
+impl From<OriginType> for MyOriginType {
+    fn from(origin_type: OriginType) -> Self {
+        MyOriginType(origin_type)
+    }
+}
+
 impl Deref for MyOriginType {
     type Target = OriginType;
 
@@ -1423,8 +2271,15 @@ impl From<&MyOriginType> for u8 {
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct MyAsPath(HopPath);
 
+impl From<HopPath> for MyAsPath {
+    fn from(hop_path: HopPath) -> Self {
+        MyAsPath(hop_path)
+    }
+}
+
 impl Deref for MyAsPath {
     type Target = HopPath;
 
@@ -1451,8 +2306,15 @@ impl FromStr for MyAsPath {
     }
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct MyNextHop(NextHop);
 
+impl From<NextHop> for MyNextHop {
+    fn from(next_hop: NextHop) -> Self {
+        MyNextHop(next_hop)
+    }
+}
+
 impl Deref for MyNextHop {
     type Target = NextHop;
 
@@ -1473,8 +2335,15 @@ impl FromStr for MyNextHop {
     }
 }
 
+#[derive(Clone, Debug, PartialEq, Eq)]
 pub struct MyCommunities(Vec<Community>);
 
+impl From<Vec<Community>> for MyCommunities {
+    fn from(communities: Vec<Community>) -> Self {
+        MyCommunities(communities)
+    }
+}
+
 impl Deref for MyCommunities {
     type Target = Vec<Community>;
 
@@ -1493,7 +2362,7 @@ impl FromStr for MyCommunities {
             _ => {
                 let mut communities = Vec::new();
                 for community_str in s.split(',') {
-                    communities.push(community_str.parse().unwrap());
+                    communities.push(community_str.parse()?);
                 }
                 Ok(MyCommunities(communities))
             }
@@ -1501,7 +2370,7 @@ impl FromStr for MyCommunities {
     }
 }
 
-#[derive(Default)]
+#[derive(Clone, Debug, Default)]
 pub enum Announcements {
     #[default]
     None,
@@ -1514,6 +2383,66 @@ pub enum Announcements {
     },
 }
 
+impl Announcements {
+    /// Returns the announced prefixes.
+    pub fn prefixes(&self) -> &[Prefix] {
+        match self {
+            Announcements::None => &[],
+            Announcements::Some { prefixes, .. } => prefixes,
+        }
+    }
+
+    /// Returns whether both announce prefixes with the same path
+    /// attributes.
+    pub fn same_attributes(&self, other: &Announcements) -> bool {
+        match (self, other) {
+            (
+                Announcements::Some {
+                    origin,
+                    as_path,
+                    next_hop,
+                    communities,
+                    ..
+                },
+                Announcements::Some {
+                    origin: other_origin,
+                    as_path: other_as_path,
+                    next_hop: other_next_hop,
+                    communities: other_communities,
+                    ..
+                },
+            ) => {
+                origin == other_origin
+                    && as_path == other_as_path
+                    && next_hop == other_next_hop
+                    && communities == other_communities
+            }
+            (Announcements::None, Announcements::None) => true,
+            _ => false,
+        }
+    }
+
+    /// Returns the same path attributes for other prefixes.
+    pub fn with_prefixes(&self, prefixes: Vec<Prefix>) -> Self {
+        match self {
+            Announcements::None => Announcements::None,
+            Announcements::Some {
+                origin,
+                as_path,
+                next_hop,
+                communities,
+                ..
+            } => Announcements::Some {
+                origin: *origin,
+                as_path: as_path.clone(),
+                next_hop: *next_hop,
+                communities: communities.clone(),
+                prefixes: Prefixes(prefixes),
+            },
+        }
+    }
+}
+
 impl FromStr for Announcements {
     type Err = anyhow::Error;
 
@@ -1523,12 +2452,16 @@ impl FromStr for Announcements {
 
             _ => {
                 let parts: Vec<&str> = s.splitn(5, ' ').collect();
-                assert_eq!(parts.len(), 5);
-                let origin = parts[0].parse().unwrap();
-                let as_path = parts[1].parse().unwrap();
-                let next_hop = parts[2].parse().unwrap();
-                let communities = parts[3].parse().unwrap();
-                let prefixes = parts[4].parse().unwrap();
+                if parts.len() != 5 {
+                    return Err(anyhow::anyhow!(
+                        "Expected <origin> <as path> <next hop> <communities> <prefixes>"
+                    ));
+                }
+                let origin = parts[0].parse()?;
+                let as_path = parts[1].parse()?;
+                let next_hop = parts[2].parse()?;
+                let communities = parts[3].parse()?;
+                let prefixes = parts[4].parse()?;
                 Ok(Self::Some {
                     origin,
                     as_path,
@@ -1540,3 +2473,40 @@ impl FromStr for Announcements {
         }
     }
 }
+
+/// Announcements with possibly different path attributes, to be packed
+/// into as few BGP UPDATEs as possible by [`mk_route_monitoring_msgs`].
+#[derive(Clone, Debug, Default)]
+pub struct Routes(Vec<Announcements>);
+
+impl Routes {
+    pub fn new(routes: Vec<Announcements>) -> Self {
+        Self(routes)
+    }
+}
+
+impl Deref for Routes {
+    type Target = Vec<Announcements>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Parses a semicolon separated list of announcements, see
+/// [`Announcements`].
+impl FromStr for Routes {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_lowercase().as_str() {
+            "" | "none" => Ok(Routes(vec![])),
+
+            _ => s
+                .split(';')
+                .map(|announcements| announcements.trim().parse())
+                .collect::<Result<_, _>>()
+                .map(Routes),
+        }
+    }
+}
@@ -1 +1,14 @@
+#[cfg(feature = "arbitrary")]
+pub mod arbitrary;
+pub mod builder;
+pub mod capture;
+pub mod churn;
+pub mod clock;
+pub mod describe;
 pub mod encode;
+pub mod mutate;
+pub mod record;
+pub mod replay;
+pub mod session;
+pub mod table;
+mod update;
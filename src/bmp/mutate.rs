@@ -0,0 +1,478 @@
+//! Deliberately malformed BMP messages.
+//!
+//! The encoders in [`encode`] and [`builder`] only produce well-formed
+//! messages. To check how a monitoring station deals with broken input,
+//! e.g. whether it applies RFC 7606 treat-as-withdraw or resets the
+//! session, the [`Mutation`]s in this module can be applied to an encoded
+//! message with [`mutate`]. Conversely, [`fix_lengths`] corrects the
+//! length fields of a hand-crafted message.
+//!
+//! [`encode`]: crate::bmp::encode
+//! [`builder`]: crate::bmp::builder
+
+use std::fmt;
+use std::ops::Range;
+use std::str::FromStr;
+
+use bytes::Bytes;
+use routecore::bgp::types::PathAttributeType;
+use routecore::bmp::message::MessageType;
+
+use crate::hex;
+
+/// The length of the BMP Common Header.
+const COMMON_HEADER_LEN: usize = 6;
+
+/// The length of the BMP Per-Peer Header.
+const PER_PEER_HEADER_LEN: usize = 42;
+
+/// The length of the local address and ports in a Peer Up Notification.
+const PEER_UP_FIXED_LEN: usize = 20;
+
+/// The length of the BGP message header.
+const BGP_HEADER_LEN: usize = 19;
+
+/// The length of the BGP marker at the start of the BGP message header.
+const BGP_MARKER_LEN: usize = 16;
+
+//------------ MutateError ---------------------------------------------------
+
+/// The reason a mutation could not be applied to a message.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MutateError {
+    /// The message is too short to contain the fields the mutation needs.
+    Malformed(&'static str),
+
+    /// The message type does not carry a BGP message.
+    NoBgpMessage(MessageType),
+
+    /// The BGP message is not an UPDATE.
+    NotAnUpdate(u8),
+
+    /// The UPDATE does not contain a path attribute of this type.
+    AttributeNotFound(PathAttributeType),
+
+    /// The UPDATE does not contain any prefixes in the Withdrawn Routes or
+    /// NLRI fields.
+    NoPrefix,
+
+    /// The mutated message no longer fits its length fields.
+    TooLong(usize),
+}
+
+impl fmt::Display for MutateError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MutateError::Malformed(field) => {
+                write!(f, "Message is too short to contain the {field}")
+            }
+            MutateError::NoBgpMessage(typ) => {
+                write!(f, "BMP message type {typ} carries no BGP message")
+            }
+            MutateError::NotAnUpdate(typ) => {
+                write!(f, "BGP message type {typ} is not an UPDATE")
+            }
+            MutateError::AttributeNotFound(typ) => {
+                write!(f, "BGP UPDATE has no {typ} path attribute")
+            }
+            MutateError::NoPrefix => write!(
+                f,
+                "BGP UPDATE has no prefixes in the Withdrawn Routes or \
+                 NLRI fields"
+            ),
+            MutateError::TooLong(len) => write!(
+                f,
+                "Mutated message length {len} does not fit its length field"
+            ),
+        }
+    }
+}
+
+impl std::error::Error for MutateError {}
+
+//------------ Mutation ------------------------------------------------------
+
+/// A single way of breaking an encoded BMP message.
+///
+/// The mutations that change the structure of the message, i.e. adding a
+/// duplicate attribute or trailing garbage, update the length fields that
+/// enclose the change so that only the intended defect remains. The other
+/// mutations change exactly one field.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Mutation {
+    /// Sets the BMP Common Header message length.
+    BmpLength(u32),
+
+    /// Sets the BGP message header length.
+    BgpLength(u16),
+
+    /// Cuts the message off after this many bytes.
+    Truncate(usize),
+
+    /// Zeroes the BGP marker, which should be all ones.
+    CorruptMarker,
+
+    /// Sets the BMP version, which should be 3.
+    Version(u8),
+
+    /// Repeats the first path attribute of this type right after it.
+    DuplicateAttribute(PathAttributeType),
+
+    /// Sets the flags of the first path attribute of this type.
+    AttributeFlags(PathAttributeType, u8),
+
+    /// Sets the length of the first prefix in the NLRI field, or in the
+    /// Withdrawn Routes field if there is no NLRI.
+    PrefixLength(u8),
+
+    /// Appends bytes after the BGP message, inside the BMP message.
+    TrailingGarbage(Bytes),
+}
+
+impl Mutation {
+    /// Returns the order in which the mutation is applied.
+    ///
+    /// Structural mutations go first as they rely on, and update, the
+    /// length fields. Single field changes go next, and truncation last so
+    /// that it applies to the final message.
+    fn rank(&self) -> u8 {
+        match self {
+            Mutation::DuplicateAttribute(_)
+            | Mutation::AttributeFlags(..)
+            | Mutation::PrefixLength(_)
+            | Mutation::TrailingGarbage(_) => 0,
+            Mutation::BmpLength(_)
+            | Mutation::BgpLength(_)
+            | Mutation::CorruptMarker
+            | Mutation::Version(_) => 1,
+            Mutation::Truncate(_) => 2,
+        }
+    }
+
+    fn apply(&self, buf: &mut Vec<u8>) -> Result<(), MutateError> {
+        match self {
+            Mutation::BmpLength(len) => {
+                buf[1..5].copy_from_slice(&len.to_be_bytes());
+            }
+            Mutation::BgpLength(len) => {
+                let bgp = bgp_offset(buf)?;
+                set_bgp_length(buf, bgp, usize::from(*len))?;
+            }
+            Mutation::Truncate(len) => buf.truncate(*len),
+            Mutation::CorruptMarker => {
+                let bgp = bgp_offset(buf)?;
+                buf[bgp..bgp + BGP_MARKER_LEN].fill(0);
+            }
+            Mutation::Version(version) => buf[0] = *version,
+            Mutation::DuplicateAttribute(typ) => {
+                let update = UpdateLayout::new(buf)?;
+                let attr = update.find_attribute(buf, *typ)?;
+                let copy = buf[attr.clone()].to_vec();
+                let len = copy.len();
+                buf.splice(attr.end..attr.end, copy);
+                add_to_u16(buf, update.attributes.start - 2, len)?;
+                add_to_u16(buf, update.bgp + BGP_MARKER_LEN, len)?;
+                add_to_bmp_length(buf, len)?;
+            }
+            Mutation::AttributeFlags(typ, flags) => {
+                let update = UpdateLayout::new(buf)?;
+                let attr = update.find_attribute(buf, *typ)?;
+                buf[attr.start] = *flags;
+            }
+            Mutation::PrefixLength(len) => {
+                let update = UpdateLayout::new(buf)?;
+                let pos = if !update.nlri.is_empty() {
+                    update.nlri.start
+                } else if !update.withdrawn.is_empty() {
+                    update.withdrawn.start
+                } else {
+                    return Err(MutateError::NoPrefix);
+                };
+                buf[pos] = *len;
+            }
+            Mutation::TrailingGarbage(garbage) => {
+                buf.extend_from_slice(garbage);
+                add_to_bmp_length(buf, garbage.len())?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Mutation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Mutation::BmpLength(len) => write!(f, "bmp_length={len}"),
+            Mutation::BgpLength(len) => write!(f, "bgp_length={len}"),
+            Mutation::Truncate(len) => write!(f, "truncate={len}"),
+            Mutation::CorruptMarker => write!(f, "corrupt_marker"),
+            Mutation::Version(version) => write!(f, "version={version}"),
+            Mutation::DuplicateAttribute(typ) => {
+                write!(f, "duplicate_attribute={}", u8::from(*typ))
+            }
+            Mutation::AttributeFlags(typ, flags) => {
+                write!(f, "attribute_flags={}:0x{flags:02x}", u8::from(*typ))
+            }
+            Mutation::PrefixLength(len) => write!(f, "prefix_length={len}"),
+            Mutation::TrailingGarbage(garbage) => {
+                write!(f, "trailing_garbage=")?;
+                for b in garbage.iter() {
+                    write!(f, "{b:02x}")?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+impl FromStr for Mutation {
+    type Err = anyhow::Error;
+
+    /// Parses `<name>[=<value>]`, e.g. `bgp_length=5`, `corrupt_marker`,
+    /// `attribute_flags=2:0x80` or `trailing_garbage=deadbeef`.
+    ///
+    /// Path attributes are given by their type code.
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, value) = s.split_once('=').unwrap_or((s, ""));
+        let mutation = match name {
+            "bmp_length" => Mutation::BmpLength(value.parse()?),
+            "bgp_length" => Mutation::BgpLength(value.parse()?),
+            "truncate" => Mutation::Truncate(value.parse()?),
+            "corrupt_marker" => Mutation::CorruptMarker,
+            "version" => Mutation::Version(value.parse()?),
+            "duplicate_attribute" => Mutation::DuplicateAttribute(
+                PathAttributeType::from(value.parse::<u8>()?),
+            ),
+            "attribute_flags" => {
+                let (typ, flags) =
+                    value.split_once(':').ok_or_else(|| {
+                        anyhow::anyhow!(
+                            "Expected attribute_flags=<type>:<flags>"
+                        )
+                    })?;
+                Mutation::AttributeFlags(
+                    PathAttributeType::from(typ.parse::<u8>()?),
+                    parse_u8(flags)?,
+                )
+            }
+            "prefix_length" => Mutation::PrefixLength(value.parse()?),
+            "trailing_garbage" => {
+                Mutation::TrailingGarbage(hex::decode(value)?.into())
+            }
+            _ => {
+                return Err(anyhow::anyhow!(
+                    "Unknown mutation '{name}', expected one of: \
+                     bmp_length, bgp_length, truncate, corrupt_marker, \
+                     version, duplicate_attribute, attribute_flags, \
+                     prefix_length, trailing_garbage"
+                ))
+            }
+        };
+        Ok(mutation)
+    }
+}
+
+fn parse_u8(s: &str) -> Result<u8, anyhow::Error> {
+    match s.strip_prefix("0x") {
+        Some(hex) => Ok(u8::from_str_radix(hex, 16)?),
+        None => Ok(s.parse()?),
+    }
+}
+
+//------------ mutate --------------------------------------------------------
+
+/// Applies the mutations to an encoded BMP message.
+///
+/// Structural mutations are applied first, then the ones that change a
+/// single field and finally truncation, whatever the order in which they
+/// are given. E.g. a duplicate attribute combined with a wrong BGP length
+/// results in exactly that wrong length.
+pub fn mutate(
+    msg: &[u8],
+    mutations: &[Mutation],
+) -> Result<Bytes, MutateError> {
+    if msg.len() < COMMON_HEADER_LEN {
+        return Err(MutateError::Malformed("BMP Common Header"));
+    }
+    let mut mutations = mutations.iter().collect::<Vec<_>>();
+    mutations.sort_by_key(|mutation| mutation.rank());
+
+    let mut buf = msg.to_vec();
+    for mutation in mutations {
+        mutation.apply(&mut buf)?;
+    }
+    Ok(buf.into())
+}
+
+/// Sets the length fields of a BMP message, and of the BGP messages it
+/// carries, to match the actual lengths.
+///
+/// This allows a hand-crafted message to be given without counting bytes.
+/// Only the BMP and BGP message header lengths are updated, lengths inside
+/// a BGP message such as the total path attribute length are left alone.
+/// The BGP message of a Route Monitoring or Peer Down Notification is
+/// assumed to take up the rest of the message, the length of the OPENs in
+/// a Peer Up Notification follows from their optional parameters length.
+pub fn fix_lengths(msg: &[u8]) -> Result<Bytes, MutateError> {
+    if msg.len() < COMMON_HEADER_LEN {
+        return Err(MutateError::Malformed("BMP Common Header"));
+    }
+    let mut buf = msg.to_vec();
+    let len = u32::try_from(buf.len())
+        .map_err(|_| MutateError::TooLong(buf.len()))?;
+    buf[1..5].copy_from_slice(&len.to_be_bytes());
+
+    let bgp = match bgp_offset(&buf) {
+        Ok(bgp) => bgp,
+        Err(MutateError::NoBgpMessage(_)) => return Ok(buf.into()),
+        Err(err) => return Err(err),
+    };
+    if MessageType::from(buf[5]) == MessageType::PeerUpNotification {
+        // The optional parameters length is the last fixed field of an
+        // OPEN, after the BGP header, version, AS, hold time and BGP ID.
+        const OPEN_FIXED_LEN: usize = BGP_HEADER_LEN + 10;
+        let mut pos = bgp;
+        for _ in 0..2 {
+            let opt_parm_len = buf
+                .get(pos + OPEN_FIXED_LEN - 1)
+                .ok_or(MutateError::Malformed("BGP OPEN"))?;
+            let open_len = OPEN_FIXED_LEN + usize::from(*opt_parm_len);
+            set_bgp_length(&mut buf, pos, open_len)?;
+            pos += open_len;
+        }
+    } else {
+        let bgp_len = buf.len() - bgp;
+        set_bgp_length(&mut buf, bgp, bgp_len)?;
+    }
+    Ok(buf.into())
+}
+
+fn set_bgp_length(
+    buf: &mut [u8],
+    bgp: usize,
+    len: usize,
+) -> Result<(), MutateError> {
+    let len = u16::try_from(len).map_err(|_| MutateError::TooLong(len))?;
+    buf[bgp + BGP_MARKER_LEN..bgp + BGP_MARKER_LEN + 2]
+        .copy_from_slice(&len.to_be_bytes());
+    Ok(())
+}
+
+/// Returns the offset of the BGP message carried in the BMP message.
+fn bgp_offset(buf: &[u8]) -> Result<usize, MutateError> {
+    let after_pph = COMMON_HEADER_LEN + PER_PEER_HEADER_LEN;
+    let offset = match MessageType::from(buf[5]) {
+        MessageType::RouteMonitoring => after_pph,
+        MessageType::PeerUpNotification => after_pph + PEER_UP_FIXED_LEN,
+        MessageType::PeerDownNotification
+            if matches!(buf.get(after_pph), Some(1) | Some(3)) =>
+        {
+            after_pph + 1
+        }
+        typ => return Err(MutateError::NoBgpMessage(typ)),
+    };
+    if buf.len() < offset + BGP_HEADER_LEN {
+        return Err(MutateError::Malformed("BGP message header"));
+    }
+    Ok(offset)
+}
+
+fn read_u16(buf: &[u8], pos: usize) -> Result<usize, MutateError> {
+    buf.get(pos..pos + 2)
+        .map(|b| usize::from(u16::from_be_bytes([b[0], b[1]])))
+        .ok_or(MutateError::Malformed("BGP UPDATE"))
+}
+
+fn add_to_u16(
+    buf: &mut [u8],
+    pos: usize,
+    delta: usize,
+) -> Result<(), MutateError> {
+    let len = read_u16(buf, pos)? + delta;
+    let len = u16::try_from(len).map_err(|_| MutateError::TooLong(len))?;
+    buf[pos..pos + 2].copy_from_slice(&len.to_be_bytes());
+    Ok(())
+}
+
+fn add_to_bmp_length(
+    buf: &mut [u8],
+    delta: usize,
+) -> Result<(), MutateError> {
+    let len =
+        u32::from_be_bytes([buf[1], buf[2], buf[3], buf[4]]) as usize + delta;
+    let len = u32::try_from(len).map_err(|_| MutateError::TooLong(len))?;
+    buf[1..5].copy_from_slice(&len.to_be_bytes());
+    Ok(())
+}
+
+//------------ UpdateLayout --------------------------------------------------
+
+/// The positions of the variable length fields of a BGP UPDATE.
+struct UpdateLayout {
+    bgp: usize,
+    withdrawn: Range<usize>,
+    attributes: Range<usize>,
+    nlri: Range<usize>,
+}
+
+impl UpdateLayout {
+    fn new(buf: &[u8]) -> Result<Self, MutateError> {
+        let bgp = bgp_offset(buf)?;
+        let typ = buf[bgp + BGP_HEADER_LEN - 1];
+        if typ != 2 {
+            return Err(MutateError::NotAnUpdate(typ));
+        }
+        let end = bgp + read_u16(buf, bgp + BGP_MARKER_LEN)?;
+        if end > buf.len() {
+            return Err(MutateError::Malformed("BGP UPDATE"));
+        }
+
+        let pos = bgp + BGP_HEADER_LEN;
+        let withdrawn = pos + 2..pos + 2 + read_u16(buf, pos)?;
+        let pos = withdrawn.end;
+        let attributes = pos + 2..pos + 2 + read_u16(buf, pos)?;
+        let nlri = attributes.end..end;
+        if nlri.start > nlri.end {
+            return Err(MutateError::Malformed("BGP UPDATE"));
+        }
+        Ok(UpdateLayout {
+            bgp,
+            withdrawn,
+            attributes,
+            nlri,
+        })
+    }
+
+    /// Returns the range of the first path attribute of the given type,
+    /// including its flags, type and length.
+    fn find_attribute(
+        &self,
+        buf: &[u8],
+        typ: PathAttributeType,
+    ) -> Result<Range<usize>, MutateError> {
+        let mut pos = self.attributes.start;
+        while pos < self.attributes.end {
+            let (flags, attr_typ) = match buf.get(pos..pos + 2) {
+                Some(b) => (b[0], b[1]),
+                None => return Err(MutateError::Malformed("path attribute")),
+            };
+            let (header_len, value_len) = if flags & 0b0001_0000 != 0 {
+                (4, read_u16(buf, pos + 2)?)
+            } else {
+                let len = buf
+                    .get(pos + 2)
+                    .ok_or(MutateError::Malformed("path attribute"))?;
+                (3, usize::from(*len))
+            };
+            let end = pos + header_len + value_len;
+            if end > self.attributes.end {
+                return Err(MutateError::Malformed("path attribute"));
+            }
+            if PathAttributeType::from(attr_typ) == typ {
+                return Ok(pos..end);
+            }
+            pos = end;
+        }
+        Err(MutateError::AttributeNotFound(typ))
+    }
+}
@@ -0,0 +1,109 @@
+//! Recording of sent BMP messages to a capture file.
+//!
+//! A [`Recorder`] writes every message it is given to a file in one of the
+//! [`RecordFormat`]s, so that a session can be reproduced exactly later.
+
+use std::fmt;
+use std::io::{self, Write};
+use std::str::FromStr;
+
+use crate::bmp::clock::Timestamp;
+use crate::mrt::write_bmp_record;
+use crate::pcap::PcapWriter;
+
+//------------ RecordFormat --------------------------------------------------
+
+/// The format of a capture file.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum RecordFormat {
+    /// The BMP messages back to back, exactly as sent on the wire.
+    #[default]
+    Raw,
+
+    /// Each BMP message in an MRT record of type `BMP`, with the time it was
+    /// sent. See [`write_bmp_record`].
+    Mrt,
+
+    /// A pcap packet capture of a synthetic TCP session to port 11019 that
+    /// carries the messages. See [`PcapWriter`].
+    Pcap,
+}
+
+impl FromStr for RecordFormat {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "raw" => Ok(RecordFormat::Raw),
+            "mrt" => Ok(RecordFormat::Mrt),
+            "pcap" => Ok(RecordFormat::Pcap),
+            _ => Err(anyhow::anyhow!("Expected raw, mrt or pcap")),
+        }
+    }
+}
+
+impl fmt::Display for RecordFormat {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RecordFormat::Raw => write!(f, "raw"),
+            RecordFormat::Mrt => write!(f, "mrt"),
+            RecordFormat::Pcap => write!(f, "pcap"),
+        }
+    }
+}
+
+//------------ Recorder ------------------------------------------------------
+
+/// Writes BMP messages to a capture file.
+///
+/// Each message is flushed right away so that the capture is complete up
+/// to the last message even if the process is killed.
+pub struct Recorder<W> {
+    out: Out<W>,
+}
+
+enum Out<W> {
+    Raw(W),
+    Mrt(W),
+    Pcap(PcapWriter<W>),
+}
+
+impl<W: Write> Recorder<W> {
+    /// Creates a recorder, writing the file header of the format, if any.
+    pub fn new(out: W, format: RecordFormat) -> Result<Self, io::Error> {
+        let out = match format {
+            RecordFormat::Raw => Out::Raw(out),
+            RecordFormat::Mrt => Out::Mrt(out),
+            RecordFormat::Pcap => Out::Pcap(PcapWriter::new(out)?),
+        };
+        Ok(Recorder { out })
+    }
+
+    /// Records a message sent at the given time.
+    ///
+    /// The time is not stored in the [`RecordFormat::Raw`] format.
+    pub fn record(
+        &mut self,
+        msg: &[u8],
+        sent: Timestamp,
+    ) -> Result<(), io::Error> {
+        match &mut self.out {
+            Out::Raw(out) => {
+                out.write_all(msg)?;
+                out.flush()
+            }
+            Out::Mrt(out) => {
+                write_bmp_record(out, sent, msg)?;
+                out.flush()
+            }
+            Out::Pcap(out) => out.write_message(msg, sent),
+        }
+    }
+
+    pub fn into_inner(self) -> W {
+        match self.out {
+            Out::Raw(out) | Out::Mrt(out) => out,
+            Out::Pcap(out) => out.into_inner(),
+        }
+    }
+}
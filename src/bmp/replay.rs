@@ -0,0 +1,342 @@
+//! Conversion of recorded routing data into BMP messages.
+//!
+//! [`MrtToBmp`] turns the records of an MRT file, as read by
+//! [`MrtReader`], into the BMP messages a monitored router would have sent
+//! for the same routing information:
+//!
+//! - each peer in a `PEER_INDEX_TABLE` becomes a Peer Up Notification.
+//! - each route in a RIB record becomes a Route Monitoring message, and the
+//!   end of a RIB dump results in an End-of-RIB marker for every peer.
+//! - a `BGP4MP` state change to Established becomes a Peer Up
+//!   Notification, a state change away from it a Peer Down Notification.
+//! - a `BGP4MP` UPDATE message becomes a Route Monitoring message, preceded
+//!   by a Peer Up Notification if no state change for the peer was seen.
+//! - a `BMP` record is passed on unchanged.
+//!
+//! The Per-Peer Header of each converted message carries the timestamp of
+//! the MRT record it was made from.
+//!
+//! [`MrtReader`]: crate::mrt::MrtReader
+
+use std::collections::HashSet;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr};
+
+use bytes::Bytes;
+use routecore::addr::Prefix;
+use routecore::asn::Asn;
+use routecore::bgp::types::PathAttributeType;
+
+use crate::bmp::builder::{PeerDown, PeerUp};
+use crate::bmp::clock::Timestamp;
+use crate::bmp::encode::{
+    mk_end_of_rib, mk_raw_bgp_update, mk_raw_route_monitoring_msg,
+    push_mp_reach_nlri, push_prefix, EncodeError, Open, PerPeerHeader,
+    Warning,
+};
+use crate::bmp::update::{self, MpNlri};
+use crate::mrt::{Bgp4mpPeer, MrtBody, MrtRecord, PeerEntry, RibEntry};
+
+/// AS_TRANS, used in an OPEN for ASNs that do not fit in 16 bits.
+const AS_TRANS: u16 = 23456;
+
+/// Peer Down reason 4: the remote system closed the session without a
+/// notification message.
+///
+/// MRT state changes do not say why a session went down, this reason also
+/// covers any unexpected termination of the transport session.
+const PEER_DOWN_REMOTE_NO_NOTIFICATION: u8 = 4;
+
+//------------ ReplayError ---------------------------------------------------
+
+/// The reason a record could not be converted into BMP messages.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ReplayError {
+    Encode(EncodeError),
+
+    /// A RIB entry refers to a peer that is not in the peer index table.
+    UnknownPeerIndex(u16),
+
+    /// The path attributes of a RIB entry could not be parsed.
+    InvalidAttributes,
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ReplayError::Encode(err) => err.fmt(f),
+            ReplayError::UnknownPeerIndex(idx) => {
+                write!(f, "RIB entry refers to unknown peer index {idx}")
+            }
+            ReplayError::InvalidAttributes => {
+                write!(f, "RIB entry has invalid path attributes")
+            }
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<EncodeError> for ReplayError {
+    fn from(err: EncodeError) -> Self {
+        ReplayError::Encode(err)
+    }
+}
+
+//------------ MrtToBmp ------------------------------------------------------
+
+/// Converts MRT records into BMP messages.
+///
+/// The conversion is stateful: RIB records refer to the preceding peer
+/// index table and Peer Up and Peer Down Notifications are only sent when
+/// the state of a peer changes. Call [`finish`] after the last record to
+/// get the End-of-RIB markers of a RIB dump.
+///
+/// [`finish`]: MrtToBmp::finish
+#[derive(Default)]
+pub struct MrtToBmp {
+    /// The peers of the last peer index table.
+    peers: Vec<PeerEntry>,
+
+    /// The peers for which a Peer Up Notification was sent.
+    up: HashSet<(IpAddr, Asn)>,
+
+    /// The address families seen in the RIB dump that is in progress.
+    rib_afis: Option<RibAfis>,
+
+    /// The timestamp of the last record.
+    last_timestamp: Timestamp,
+
+    /// The number of records that could not be converted because their
+    /// type is not supported.
+    skipped: u64,
+}
+
+#[derive(Default)]
+struct RibAfis {
+    ipv4: bool,
+    ipv6: bool,
+}
+
+impl MrtToBmp {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the number of records skipped because their type, or the
+    /// type of BGP message they carry, is not supported.
+    pub fn skipped(&self) -> u64 {
+        self.skipped
+    }
+
+    /// Returns the BMP messages for a record.
+    pub fn convert(
+        &mut self,
+        record: &MrtRecord,
+    ) -> Result<Vec<(Bytes, Vec<Warning>)>, ReplayError> {
+        let ts = record.timestamp;
+        let mut out = vec![];
+        if !matches!(
+            record.body,
+            MrtBody::Rib { .. } | MrtBody::Unsupported { .. }
+        ) {
+            self.end_of_rib(&mut out)?;
+        }
+        self.last_timestamp = ts;
+
+        match &record.body {
+            MrtBody::PeerIndexTable { peers, .. } => {
+                self.peers = peers.clone();
+                for peer in peers {
+                    if self.up.insert((peer.address, peer.asn)) {
+                        let pph = table_dump_pph(peer, ts);
+                        out.push(PeerUp::new(pph).build()?);
+                    }
+                }
+                self.rib_afis = Some(RibAfis::default());
+            }
+            MrtBody::Rib {
+                prefix, entries, ..
+            } => {
+                let afis = self.rib_afis.get_or_insert_with(Default::default);
+                if prefix.is_v4() {
+                    afis.ipv4 = true;
+                } else {
+                    afis.ipv6 = true;
+                }
+                for entry in entries {
+                    out.push(self.rib_entry(*prefix, entry, ts)?);
+                }
+            }
+            MrtBody::StateChange {
+                peer,
+                old_state,
+                new_state,
+            } => {
+                let key = (peer.peer_address, peer.peer_as);
+                if new_state.is_established() {
+                    if self.up.insert(key) {
+                        out.push(bgp4mp_peer_up(peer, ts)?);
+                    }
+                } else if old_state.is_established() || self.up.contains(&key)
+                {
+                    self.up.remove(&key);
+                    let pph = bgp4mp_pph(peer, ts);
+                    out.push(
+                        PeerDown::new(pph)
+                            .reason(
+                                PEER_DOWN_REMOTE_NO_NOTIFICATION,
+                                Bytes::new(),
+                            )
+                            .build()?,
+                    );
+                }
+            }
+            MrtBody::Message { peer, as4, message } => {
+                if message.get(18) != Some(&update::BGP_UPDATE) {
+                    self.skipped += 1;
+                    return Ok(out);
+                }
+                if self.up.insert((peer.peer_address, peer.peer_as)) {
+                    out.push(bgp4mp_peer_up(peer, ts)?);
+                }
+                let pph = bgp4mp_pph(peer, ts).legacy_as_path(!as4);
+                out.push(mk_raw_route_monitoring_msg(&pph, message.clone())?);
+            }
+            MrtBody::Bmp { message } => out.push((message.clone(), vec![])),
+            MrtBody::Unsupported { .. } => self.skipped += 1,
+        }
+        Ok(out)
+    }
+
+    /// Returns the messages that complete the conversion.
+    ///
+    /// These are the End-of-RIB markers if the input ended with a RIB dump.
+    pub fn finish(
+        &mut self,
+    ) -> Result<Vec<(Bytes, Vec<Warning>)>, ReplayError> {
+        let mut out = vec![];
+        self.end_of_rib(&mut out)?;
+        Ok(out)
+    }
+
+    /// Adds an End-of-RIB marker per peer and address family if a RIB dump
+    /// is in progress.
+    fn end_of_rib(
+        &mut self,
+        out: &mut Vec<(Bytes, Vec<Warning>)>,
+    ) -> Result<(), ReplayError> {
+        let Some(afis) = self.rib_afis.take() else {
+            return Ok(());
+        };
+        for peer in &self.peers {
+            let pph = table_dump_pph(peer, self.last_timestamp);
+            for (present, ipv6) in [(afis.ipv4, false), (afis.ipv6, true)] {
+                if present {
+                    out.push(mk_raw_route_monitoring_msg(
+                        &pph,
+                        mk_end_of_rib(ipv6)?,
+                    )?);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    fn rib_entry(
+        &self,
+        prefix: Prefix,
+        entry: &RibEntry,
+        ts: Timestamp,
+    ) -> Result<(Bytes, Vec<Warning>), ReplayError> {
+        let peer = self
+            .peers
+            .get(usize::from(entry.peer_index))
+            .ok_or(ReplayError::UnknownPeerIndex(entry.peer_index))?;
+        let pph = table_dump_pph(peer, ts);
+
+        let mut nlri = vec![];
+        push_prefix(&mut nlri, prefix);
+        let update = if prefix.is_v4() {
+            mk_raw_bgp_update(&[], &entry.attributes, &nlri)?
+        } else {
+            let attributes = expand_mp_reach_nlri(&entry.attributes, &nlri)?;
+            mk_raw_bgp_update(&[], &attributes, &[])?
+        };
+        Ok(mk_raw_route_monitoring_msg(&pph, update)?)
+    }
+}
+
+//------------ Helpers -------------------------------------------------------
+
+fn table_dump_pph(peer: &PeerEntry, ts: Timestamp) -> PerPeerHeader {
+    PerPeerHeader::new(peer.address, peer.asn)
+        .bgp_id(peer.bgp_id)
+        .timestamp(ts)
+}
+
+fn bgp4mp_pph(peer: &Bgp4mpPeer, ts: Timestamp) -> PerPeerHeader {
+    PerPeerHeader::new(peer.peer_address, peer.peer_as).timestamp(ts)
+}
+
+/// Returns a Peer Up Notification for a `BGP4MP` peer.
+///
+/// The local address and AS are known from the record, the ports and the
+/// BGP IDs are not.
+fn bgp4mp_peer_up(
+    peer: &Bgp4mpPeer,
+    ts: Timestamp,
+) -> Result<(Bytes, Vec<Warning>), ReplayError> {
+    let local_asn =
+        u16::try_from(peer.local_as.into_u32()).unwrap_or(AS_TRANS);
+    Ok(PeerUp::new(bgp4mp_pph(peer, ts))
+        .local(peer.local_address, 179)
+        .sent_open(Open::new(local_asn, u32::from(Ipv4Addr::LOCALHOST)))
+        .build()?)
+}
+
+/// Returns the attributes of an IPv6 RIB entry with a complete
+/// MP_REACH_NLRI attribute for the given NLRI.
+///
+/// "There is one exception to the encoding of BGP attributes for the BGP
+///  MP_REACH_NLRI attribute (BGP Type Code 14) [RFC4760]. Since the AFI,
+///  SAFI, and NLRI information is already encoded in the RIB Entry Header
+///  or RIB_GENERIC Entry Header, only the Next Hop Address Length and Next
+///  Hop Address fields are included."
+///
+/// From: https://www.rfc-editor.org/rfc/rfc6396#section-4.3.4
+///
+/// Some implementations write the complete attribute anyway, in which case
+/// only the NLRI is replaced.
+fn expand_mp_reach_nlri(
+    attributes: &[u8],
+    nlri: &[u8],
+) -> Result<Vec<u8>, ReplayError> {
+    let mut out = Vec::with_capacity(attributes.len() + nlri.len() + 8);
+    for attr in update::attributes(attributes)
+        .ok_or(ReplayError::InvalidAttributes)?
+    {
+        if attr.typ != PathAttributeType::MpReachNlri {
+            out.extend_from_slice(attr.raw);
+            continue;
+        }
+        let value = attr.value;
+        let next_hop = match value.split_first() {
+            Some((&nh_len, next_hop))
+                if usize::from(nh_len) == next_hop.len() =>
+            {
+                next_hop
+            }
+            _ => {
+                MpNlri::reach(value)
+                    .ok_or(ReplayError::InvalidAttributes)?
+                    .next_hop
+            }
+        };
+        if next_hop.len() > usize::from(u8::MAX) {
+            return Err(ReplayError::InvalidAttributes);
+        }
+        push_mp_reach_nlri(&mut out, next_hop, nlri)?;
+    }
+    Ok(out)
+}
@@ -0,0 +1,827 @@
+//! Tracking of the state a monitoring station builds from a BMP session.
+//!
+//! [`Session`] follows the messages sent to a station and keeps what the
+//! station would know at this point: whether the session was initiated,
+//! which peers are up with their Peer Up Notification, and the routes in
+//! the pre- and post-policy Adj-RIB-In of each peer. [`Session::check`]
+//! tells whether a message fits this state, and [`Session::replay`] turns
+//! the state back into messages, which is what a router sends after it
+//! reconnects to a station that lost the session.
+//!
+//! Messages are tracked by their raw bytes, so path attributes and message
+//! content the encoder does not know about are replayed as they were sent.
+//! Messages that cannot be parsed leave the state unchanged.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use routecore::addr::Prefix;
+use routecore::asn::Asn;
+use routecore::bgp::types::PathAttributeType;
+use routecore::bmp::message::{MessageType, PeerType};
+
+use crate::bmp::clock::Timestamp;
+use crate::bmp::encode::{
+    mk_end_of_rib, mk_raw_bgp_update, prefix_len, push_mp_reach_nlri,
+    push_prefix, EncodeError, MAX_BGP_MSG_LEN,
+};
+use crate::bmp::update::{self, MpNlri, Update, BGP_HEADER_LEN};
+
+/// The length of the BMP common header.
+const COMMON_HEADER_LEN: usize = 6;
+
+/// The length of the Per-Peer Header.
+const PER_PEER_HEADER_LEN: usize = 42;
+
+/// The part of the Per-Peer Header that identifies a RIB: the peer type,
+/// flags, distinguisher, address, AS and BGP ID, but not the timestamp.
+const RIB_KEY_LEN: usize = 34;
+
+/// The Per-Peer Header flag for an IPv6 peer address.
+const PEER_FLAG_V: u8 = 0x80;
+
+/// The Per-Peer Header flag for post-policy Adj-RIB-In routes.
+const PEER_FLAG_L: u8 = 0x40;
+
+/// The Per-Peer Header flag for AS_PATHs with 2-byte ASNs.
+const PEER_FLAG_A: u8 = 0x20;
+
+/// The BGP capability code for 4-octet AS numbers, RFC 6793.
+const CAPABILITY_FOUR_OCTET_AS: u8 = 65;
+
+/// The part of the Per-Peer Header that identifies a peer: the peer type,
+/// distinguisher and address, skipping the flags in between.
+fn peer_key(pph: &[u8]) -> [u8; 25] {
+    let mut key = [0u8; 25];
+    key[0] = pph[0];
+    key[1..].copy_from_slice(&pph[2..26]);
+    key
+}
+
+//------------ Session -------------------------------------------------------
+
+/// The state of a BMP session as seen by the monitoring station.
+#[derive(Clone, Debug, Default)]
+pub struct Session {
+    /// The last Initiation message sent.
+    initiation: Option<Bytes>,
+
+    /// The peers in the order they were first seen.
+    peers: Vec<Peer>,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns whether nothing was sent since the session started or
+    /// was terminated.
+    pub fn is_empty(&self) -> bool {
+        self.initiation.is_none() && self.peers.is_empty()
+    }
+
+    /// Returns whether an Initiation message was sent.
+    pub fn is_initiated(&self) -> bool {
+        self.initiation.is_some()
+    }
+
+    /// Returns the peers that are up or have routes, in the order they were
+    /// first seen.
+    pub fn peers(&self) -> &[Peer] {
+        &self.peers
+    }
+
+    /// Returns the number of routes in all RIBs of all peers.
+    pub fn route_count(&self) -> usize {
+        self.peers.iter().map(Peer::route_count).sum()
+    }
+
+    /// Returns what is out of order about sending a message now.
+    ///
+    /// The message is not applied to the state, use [`observe`] for that
+    /// once it was sent.
+    ///
+    /// [`observe`]: Session::observe
+    pub fn check(&self, msg: &[u8]) -> Vec<SequenceWarning> {
+        let mut warnings = vec![];
+        let Some(&typ) = msg.get(5) else {
+            return warnings;
+        };
+        let typ = MessageType::from(typ);
+        match typ {
+            MessageType::InitiationMessage => {
+                if self.is_initiated() {
+                    warnings.push(SequenceWarning::AlreadyInitiated);
+                }
+                return warnings;
+            }
+            _ if !self.is_initiated() => {
+                warnings.push(SequenceWarning::NotInitiated(typ));
+            }
+            _ => {}
+        }
+        let Some(pph) = per_peer_header(msg) else {
+            return warnings;
+        };
+        let up = self.peer(pph).is_some_and(Peer::is_up);
+        match typ {
+            MessageType::PeerUpNotification if up => {
+                warnings
+                    .push(SequenceWarning::PeerAlreadyUp(peer_address(pph)));
+            }
+            MessageType::RouteMonitoring
+            | MessageType::StatisticsReport
+            | MessageType::PeerDownNotification
+            | MessageType::RouteMirroring
+                if !up =>
+            {
+                warnings
+                    .push(SequenceWarning::PeerNotUp(typ, peer_address(pph)));
+            }
+            _ => {}
+        }
+        warnings
+    }
+
+    /// Updates the state with a message sent to the station.
+    pub fn observe(&mut self, msg: &[u8]) {
+        let Some(&typ) = msg.get(5) else {
+            return;
+        };
+        match MessageType::from(typ) {
+            MessageType::InitiationMessage => {
+                self.initiation = Some(Bytes::copy_from_slice(msg));
+            }
+            MessageType::TerminationMessage => *self = Self::default(),
+            MessageType::PeerUpNotification => {
+                if let Some(pph) = per_peer_header(msg) {
+                    let peer = self.peer_mut(pph);
+                    peer.header.copy_from_slice(&pph[..RIB_KEY_LEN]);
+                    peer.peer_up = Some(Bytes::copy_from_slice(msg));
+                    peer.ribs.clear();
+                }
+            }
+            MessageType::PeerDownNotification => {
+                if let Some(pph) = per_peer_header(msg) {
+                    let key = peer_key(pph);
+                    self.peers.retain(|peer| peer.key != key);
+                }
+            }
+            MessageType::RouteMonitoring => {
+                if let Some(pph) = per_peer_header(msg) {
+                    let bgp_msg =
+                        &msg[COMMON_HEADER_LEN + PER_PEER_HEADER_LEN..];
+                    self.peer_mut(pph).rib_mut(pph).update(bgp_msg);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Returns the messages that bring a new station up to date.
+    ///
+    /// These are the Initiation message, then for each peer its Peer Up
+    /// Notification followed by its routes, packed into as few Route
+    /// Monitoring messages as fit in BGP messages of at most 4096 bytes,
+    /// and an End-of-RIB marker for each address family with routes. The
+    /// Route Monitoring messages carry the given timestamp, the other
+    /// messages are replayed as they were sent.
+    pub fn replay(&self, ts: Timestamp) -> Result<Vec<Bytes>, EncodeError> {
+        let mut out = vec![];
+        out.extend(self.initiation.clone());
+        for peer in &self.peers {
+            out.extend(peer.peer_up.clone());
+            for rib in &peer.ribs {
+                let mut pph = [0u8; PER_PEER_HEADER_LEN];
+                pph[..RIB_KEY_LEN].copy_from_slice(&rib.header);
+                pph[RIB_KEY_LEN..RIB_KEY_LEN + 4]
+                    .copy_from_slice(&ts.secs.to_be_bytes());
+                pph[RIB_KEY_LEN + 4..]
+                    .copy_from_slice(&ts.micros.to_be_bytes());
+                for update in rib.updates()? {
+                    out.push(mk_route_monitoring(&pph, &update)?);
+                }
+            }
+        }
+        Ok(out)
+    }
+
+    fn peer(&self, pph: &[u8]) -> Option<&Peer> {
+        let key = peer_key(pph);
+        self.peers.iter().find(|peer| peer.key == key)
+    }
+
+    fn peer_mut(&mut self, pph: &[u8]) -> &mut Peer {
+        let key = peer_key(pph);
+        match self.peers.iter().position(|peer| peer.key == key) {
+            Some(idx) => &mut self.peers[idx],
+            None => {
+                self.peers.push(Peer {
+                    key,
+                    header: pph[..RIB_KEY_LEN].try_into().unwrap(),
+                    peer_up: None,
+                    ribs: vec![],
+                });
+                self.peers.last_mut().unwrap()
+            }
+        }
+    }
+}
+
+//------------ SequenceWarning -----------------------------------------------
+
+/// A message sent when the station does not expect it.
+///
+/// "Once the session is up, the router begins to send BMP messages. It MUST
+///  begin by sending an Initiation message. It subsequently sends a Peer Up
+///  message over the BMP session for each of its monitored BGP peers that
+///  is in the Established state. It follows by sending the contents of its
+///  Adj-RIBs-In (pre-policy, post-policy, or both, see Section 5)
+///  encapsulated in Route Monitoring messages."
+///
+/// From: https://www.rfc-editor.org/rfc/rfc7854#section-3.3
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SequenceWarning {
+    /// A message other than an Initiation message before the session was
+    /// initiated.
+    NotInitiated(MessageType),
+
+    /// A second Initiation message.
+    AlreadyInitiated,
+
+    /// A Peer Up Notification for a peer that is up.
+    PeerAlreadyUp(IpAddr),
+
+    /// A message about a peer that is not up.
+    PeerNotUp(MessageType, IpAddr),
+}
+
+impl fmt::Display for SequenceWarning {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "RFC 7854 section 3.3 sequence violation: ")?;
+        match self {
+            SequenceWarning::NotInitiated(typ) => {
+                write!(f, "{typ} sent before an Initiation message")
+            }
+            SequenceWarning::AlreadyInitiated => {
+                write!(f, "the session was already initiated")
+            }
+            SequenceWarning::PeerAlreadyUp(addr) => {
+                write!(f, "peer {addr} is already up")
+            }
+            SequenceWarning::PeerNotUp(typ, addr) => {
+                write!(f, "{typ} for peer {addr} which is not up")
+            }
+        }
+    }
+}
+
+//------------ Peer ----------------------------------------------------------
+
+/// A monitored peer that is up or has routes.
+#[derive(Clone, Debug)]
+pub struct Peer {
+    key: [u8; 25],
+
+    /// The Per-Peer Header without the timestamp of the Peer Up
+    /// Notification, or of the first Route Monitoring message.
+    header: [u8; RIB_KEY_LEN],
+
+    /// The Peer Up Notification, if the peer is up.
+    peer_up: Option<Bytes>,
+
+    /// The RIBs of the peer, e.g. pre- and post-policy, in the order they
+    /// were first seen.
+    ribs: Vec<Rib>,
+}
+
+impl Peer {
+    pub fn peer_type(&self) -> PeerType {
+        PeerType::from(self.header[0])
+    }
+
+    pub fn distinguisher(&self) -> [u8; 8] {
+        self.header[2..10].try_into().unwrap()
+    }
+
+    pub fn address(&self) -> IpAddr {
+        peer_address(&self.header)
+    }
+
+    pub fn asn(&self) -> Asn {
+        Asn::from_u32(u32::from_be_bytes(
+            self.header[26..30].try_into().unwrap(),
+        ))
+    }
+
+    pub fn bgp_id(&self) -> Ipv4Addr {
+        Ipv4Addr::from(<[u8; 4]>::try_from(&self.header[30..34]).unwrap())
+    }
+
+    /// Returns whether a Peer Up Notification was sent for the peer.
+    pub fn is_up(&self) -> bool {
+        self.peer_up.is_some()
+    }
+
+    /// Returns the Per-Peer Header timestamp of the Peer Up Notification.
+    pub fn up_since(&self) -> Option<Timestamp> {
+        let pph = per_peer_header(self.peer_up.as_ref()?)?;
+        let field = |pos: usize| {
+            u32::from_be_bytes(pph[pos..pos + 4].try_into().unwrap())
+        };
+        Some(Timestamp {
+            secs: field(RIB_KEY_LEN),
+            micros: field(RIB_KEY_LEN + 4),
+        })
+    }
+
+    /// Returns the local address and port and the remote port of the
+    /// session from the Peer Up Notification.
+    pub fn local(&self) -> Option<(IpAddr, u16, u16)> {
+        let body = self.peer_up_body()?;
+        let addr = body.get(..16)?;
+        let addr = if self.header[1] & PEER_FLAG_V != 0 {
+            IpAddr::from(<[u8; 16]>::try_from(addr).unwrap())
+        } else {
+            IpAddr::from(<[u8; 4]>::try_from(&addr[12..]).unwrap())
+        };
+        let port = |pos: usize| {
+            body.get(pos..pos + 2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        };
+        Some((addr, port(16)?, port(18)?))
+    }
+
+    /// Returns the OPEN message sent by the monitored router.
+    pub fn sent_open(&self) -> Option<OpenInfo> {
+        let body = self.peer_up_body()?.get(20..)?;
+        OpenInfo::parse(body)
+    }
+
+    /// Returns the OPEN message received from the peer.
+    pub fn received_open(&self) -> Option<OpenInfo> {
+        let body = self.peer_up_body()?.get(20..)?;
+        let sent_len = usize::from(u16::from_be_bytes(
+            body.get(16..18)?.try_into().unwrap(),
+        ));
+        OpenInfo::parse(body.get(sent_len..)?)
+    }
+
+    pub fn ribs(&self) -> &[Rib] {
+        &self.ribs
+    }
+
+    /// Returns the number of routes in all RIBs of the peer.
+    pub fn route_count(&self) -> usize {
+        self.ribs.iter().map(Rib::len).sum()
+    }
+
+    /// Returns the Peer Up Notification after the Per-Peer Header.
+    fn peer_up_body(&self) -> Option<&[u8]> {
+        self.peer_up
+            .as_ref()?
+            .get(COMMON_HEADER_LEN + PER_PEER_HEADER_LEN..)
+    }
+
+    fn rib_mut(&mut self, pph: &[u8]) -> &mut Rib {
+        let header = &pph[..RIB_KEY_LEN];
+        match self.ribs.iter().position(|rib| rib.header == header) {
+            Some(idx) => &mut self.ribs[idx],
+            None => {
+                self.ribs.push(Rib {
+                    header: header.try_into().unwrap(),
+                    routes: BTreeMap::new(),
+                });
+                self.ribs.last_mut().unwrap()
+            }
+        }
+    }
+}
+
+/// Shows the peer on a single line, e.g. for `show peers`.
+impl fmt::Display for Peer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{} AS{}", self.address(), self.asn().into_u32())?;
+        if self.peer_type() != PeerType::GlobalInstance {
+            write!(f, " {}", self.peer_type())?;
+            write!(f, " distinguisher ")?;
+            for b in self.distinguisher() {
+                write!(f, "{b:02x}")?;
+            }
+        }
+        write!(f, " BGP ID {}", self.bgp_id())?;
+        match self.up_since() {
+            Some(ts) => write!(f, ", up since {ts}")?,
+            None => write!(f, ", not up")?,
+        }
+        if let Some((addr, local_port, remote_port)) = self.local() {
+            write!(
+                f,
+                ", local {addr} port {local_port}, remote port {remote_port}"
+            )?;
+        }
+        if let Some(open) = self.sent_open() {
+            write!(f, ", sent OPEN {open}")?;
+        }
+        if let Some(open) = self.received_open() {
+            write!(f, ", received OPEN {open}")?;
+        }
+        for rib in &self.ribs {
+            let policy = if rib.is_post_policy() { "post" } else { "pre" };
+            write!(f, ", {policy}-policy routes: {}", rib.len())?;
+        }
+        Ok(())
+    }
+}
+
+//------------ OpenInfo ------------------------------------------------------
+
+/// The fields of a BGP OPEN message.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct OpenInfo {
+    /// The ASN, from the 4-octet AS number capability if present.
+    pub asn: Asn,
+    pub hold_time: u16,
+    pub bgp_id: Ipv4Addr,
+}
+
+impl OpenInfo {
+    /// Parses a BGP OPEN message including its header.
+    fn parse(msg: &[u8]) -> Option<Self> {
+        let body = msg.get(BGP_HEADER_LEN..)?;
+        let field = |pos: usize| {
+            body.get(pos..pos + 2)
+                .map(|b| u16::from_be_bytes([b[0], b[1]]))
+        };
+        let mut asn = Asn::from_u32(u32::from(field(1)?));
+        let hold_time = field(3)?;
+        let bgp_id =
+            Ipv4Addr::from(<[u8; 4]>::try_from(body.get(5..9)?).unwrap());
+
+        // Look for the 4-octet AS number in the capabilities optional
+        // parameters.
+        let params_len = usize::from(*body.get(9)?);
+        let mut params = body.get(10..10 + params_len)?;
+        while let [typ, len, rest @ ..] = params {
+            let value = rest.get(..usize::from(*len))?;
+            params = &rest[value.len()..];
+            if *typ != 2 {
+                continue;
+            }
+            let mut caps = value;
+            while let [code, len, rest @ ..] = caps {
+                let value = rest.get(..usize::from(*len))?;
+                caps = &rest[value.len()..];
+                if *code == CAPABILITY_FOUR_OCTET_AS && value.len() == 4 {
+                    asn = Asn::from_u32(u32::from_be_bytes(
+                        value.try_into().unwrap(),
+                    ));
+                }
+            }
+        }
+        Some(OpenInfo {
+            asn,
+            hold_time,
+            bgp_id,
+        })
+    }
+}
+
+impl fmt::Display for OpenInfo {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "AS{} hold time {} BGP ID {}",
+            self.asn.into_u32(),
+            self.hold_time,
+            self.bgp_id
+        )
+    }
+}
+
+//------------ Rib -----------------------------------------------------------
+
+/// The routes of a peer in one Adj-RIB-In, pre- or post-policy.
+#[derive(Clone, Debug)]
+pub struct Rib {
+    header: [u8; RIB_KEY_LEN],
+    routes: BTreeMap<Prefix, Route>,
+}
+
+impl Rib {
+    /// Returns whether this is the post-policy Adj-RIB-In, as indicated by
+    /// the L flag of the Per-Peer Header.
+    pub fn is_post_policy(&self) -> bool {
+        self.header[1] & PEER_FLAG_L != 0
+    }
+
+    pub fn len(&self) -> usize {
+        self.routes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// Returns the routes in prefix order.
+    pub fn routes(&self) -> impl Iterator<Item = (&Prefix, &Route)> + '_ {
+        self.routes.iter()
+    }
+
+    /// Applies the withdrawals and announcements of a BGP UPDATE message.
+    ///
+    /// Only IPv4 and IPv6 unicast routes are tracked.
+    fn update(&mut self, bgp_msg: &[u8]) {
+        let Some(msg) = Update::parse(bgp_msg) else {
+            return;
+        };
+        let Some(attrs) = update::attributes(msg.attributes) else {
+            return;
+        };
+        let mut withdrawn =
+            update::prefixes(msg.withdrawn, false).unwrap_or_default();
+        let mut announced = vec![];
+        let mut attributes = BytesMut::new();
+        let mut next_hop = Bytes::new();
+        if let Some(nlri) = update::prefixes(msg.nlri, false) {
+            announced.extend(nlri);
+        }
+        for attr in attrs {
+            match attr.typ {
+                PathAttributeType::MpReachNlri => {
+                    let Some(mp) = MpNlri::reach(attr.value) else {
+                        continue;
+                    };
+                    if mp.ipv6 {
+                        next_hop = Bytes::copy_from_slice(mp.next_hop);
+                        announced.extend(
+                            update::prefixes(mp.nlri, true)
+                                .unwrap_or_default(),
+                        );
+                    }
+                }
+                PathAttributeType::MpUnreachNlri => {
+                    if let Some(mp) = MpNlri::unreach(attr.value) {
+                        withdrawn.extend(
+                            update::prefixes(mp.nlri, mp.ipv6)
+                                .unwrap_or_default(),
+                        );
+                    }
+                }
+                _ => attributes.put_slice(attr.raw),
+            }
+        }
+
+        for prefix in withdrawn {
+            self.routes.remove(&prefix);
+        }
+        let attributes = attributes.freeze();
+        let legacy_as_path = self.header[1] & PEER_FLAG_A != 0;
+        for prefix in announced {
+            let next_hop = if prefix.is_v4() {
+                Bytes::new()
+            } else {
+                next_hop.clone()
+            };
+            self.routes.insert(
+                prefix,
+                Route {
+                    attributes: attributes.clone(),
+                    next_hop,
+                    legacy_as_path,
+                },
+            );
+        }
+    }
+
+    /// Returns UPDATE messages announcing all routes, followed by an
+    /// End-of-RIB marker for each address family.
+    fn updates(&self) -> Result<Vec<Bytes>, EncodeError> {
+        // Group the prefixes by their attributes, keeping the order in
+        // which the groups are first seen.
+        let mut groups: Vec<(&Route, Vec<Prefix>)> = vec![];
+        let mut index = HashMap::new();
+        for (prefix, route) in &self.routes {
+            let idx =
+                *index.entry((route, prefix.is_v4())).or_insert_with(|| {
+                    groups.push((route, vec![]));
+                    groups.len() - 1
+                });
+            groups[idx].1.push(*prefix);
+        }
+
+        let mut out = vec![];
+        for (route, prefixes) in &groups {
+            let ipv6 = !prefixes[0].is_v4();
+            // The room left for NLRI next to the header and attributes,
+            // including an MP_REACH_NLRI attribute with its AFI, SAFI,
+            // next hop and reserved octet for IPv6.
+            let mut fixed = BGP_HEADER_LEN + 2 + 2 + route.attributes.len();
+            if ipv6 {
+                fixed += 4 + 5 + route.next_hop.len();
+            }
+            let room = MAX_BGP_MSG_LEN.saturating_sub(fixed);
+
+            let mut nlri = vec![];
+            for prefix in prefixes {
+                if !nlri.is_empty() && nlri.len() + prefix_len(*prefix) > room
+                {
+                    out.push(route.update(ipv6, &nlri)?);
+                    nlri.clear();
+                }
+                push_prefix(&mut nlri, *prefix);
+            }
+            out.push(route.update(ipv6, &nlri)?);
+        }
+
+        for ipv6 in [false, true] {
+            if self.routes.keys().any(|prefix| prefix.is_v4() != ipv6) {
+                out.push(mk_end_of_rib(ipv6)?);
+            }
+        }
+        Ok(out)
+    }
+}
+
+//------------ Route ---------------------------------------------------------
+
+/// The path attributes of a route.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Route {
+    /// The attributes as sent, without MP_REACH_NLRI and MP_UNREACH_NLRI.
+    attributes: Bytes,
+
+    /// The MP_REACH_NLRI next hop of an IPv6 route.
+    next_hop: Bytes,
+
+    /// Whether the AS_PATH has 2-byte ASNs.
+    legacy_as_path: bool,
+}
+
+impl Route {
+    /// Returns the path attributes as sent, without MP_REACH_NLRI and
+    /// MP_UNREACH_NLRI.
+    pub fn attributes(&self) -> &[u8] {
+        &self.attributes
+    }
+
+    fn update(&self, ipv6: bool, nlri: &[u8]) -> Result<Bytes, EncodeError> {
+        if !ipv6 {
+            return mk_raw_bgp_update(&[], &self.attributes, nlri);
+        }
+        let mut attributes = self.attributes.to_vec();
+        push_mp_reach_nlri(&mut attributes, &self.next_hop, nlri)?;
+        mk_raw_bgp_update(&[], &attributes, &[])
+    }
+}
+
+/// Shows the route like the announcements given to the `route_monitoring`
+/// REPL command: origin, AS path, next hop and communities, followed by any
+/// other attributes.
+impl fmt::Display for Route {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let attrs = update::attributes(&self.attributes).unwrap_or_default();
+        let find = |typ| attrs.iter().find(|attr| attr.typ == typ);
+
+        match find(PathAttributeType::Origin).map(|attr| attr.value) {
+            Some([0]) => write!(f, "i")?,
+            Some([1]) => write!(f, "e")?,
+            Some([2]) => write!(f, "?")?,
+            _ => write!(f, "-")?,
+        }
+
+        write!(f, " [")?;
+        if let Some(attr) = find(PathAttributeType::AsPath) {
+            let asn_len = if self.legacy_as_path { 2 } else { 4 };
+            let mut segments = attr.value;
+            let mut first = true;
+            while let [typ, count, rest @ ..] = segments {
+                let len = usize::from(*count) * asn_len;
+                let Some(asns) = rest.get(..len) else {
+                    break;
+                };
+                segments = &rest[len..];
+                if *typ == 1 {
+                    write!(f, "{}{{", if first { "" } else { "," })?;
+                    first = true;
+                }
+                for asn in asns.chunks(asn_len) {
+                    let asn = asn
+                        .iter()
+                        .fold(0u32, |acc, b| (acc << 8) | u32::from(*b));
+                    write!(f, "{}{asn}", if first { "" } else { "," })?;
+                    first = false;
+                }
+                if *typ == 1 {
+                    write!(f, "}}")?;
+                }
+            }
+        }
+        write!(f, "]")?;
+
+        match find(PathAttributeType::NextHop).map(|attr| attr.value) {
+            Some(&[a, b, c, d]) => {
+                write!(f, " {}", Ipv4Addr::new(a, b, c, d))?
+            }
+            _ => match self.next_hop.get(..16) {
+                Some(addr) => write!(
+                    f,
+                    " {}",
+                    Ipv6Addr::from(<[u8; 16]>::try_from(addr).unwrap())
+                )?,
+                None => write!(f, " -")?,
+            },
+        }
+
+        let mut communities = vec![];
+        if let Some(attr) = find(PathAttributeType::Communities) {
+            for c in attr.value.chunks_exact(4) {
+                communities.push(format!(
+                    "{}:{}",
+                    u16::from_be_bytes([c[0], c[1]]),
+                    u16::from_be_bytes([c[2], c[3]])
+                ));
+            }
+        }
+        if let Some(attr) = find(PathAttributeType::LargeCommunities) {
+            for c in attr.value.chunks_exact(12) {
+                let part = |pos: usize| {
+                    u32::from_be_bytes(c[pos..pos + 4].try_into().unwrap())
+                };
+                communities.push(format!(
+                    "{}:{}:{}",
+                    part(0),
+                    part(4),
+                    part(8)
+                ));
+            }
+        }
+        if communities.is_empty() {
+            write!(f, " none")?;
+        } else {
+            write!(f, " {}", communities.join(","))?;
+        }
+
+        for attr in &attrs {
+            match attr.typ {
+                PathAttributeType::Origin
+                | PathAttributeType::AsPath
+                | PathAttributeType::NextHop
+                | PathAttributeType::Communities
+                | PathAttributeType::LargeCommunities => {}
+                PathAttributeType::MultiExitDisc
+                | PathAttributeType::LocalPref
+                    if attr.value.len() == 4 =>
+                {
+                    let name = if attr.typ == PathAttributeType::LocalPref {
+                        "local_pref"
+                    } else {
+                        "med"
+                    };
+                    let value =
+                        u32::from_be_bytes(attr.value.try_into().unwrap());
+                    write!(f, " {name}={value}")?;
+                }
+                _ => write!(f, " attr{}", u8::from(attr.typ))?,
+            }
+        }
+        Ok(())
+    }
+}
+
+//------------ Helpers -------------------------------------------------------
+
+/// Returns the Per-Peer Header of a message that has one.
+fn per_peer_header(msg: &[u8]) -> Option<&[u8]> {
+    msg.get(COMMON_HEADER_LEN..COMMON_HEADER_LEN + PER_PEER_HEADER_LEN)
+}
+
+/// Returns the peer address of a Per-Peer Header.
+fn peer_address(pph: &[u8]) -> IpAddr {
+    if pph[1] & PEER_FLAG_V != 0 {
+        IpAddr::from(<[u8; 16]>::try_from(&pph[10..26]).unwrap())
+    } else {
+        IpAddr::from(<[u8; 4]>::try_from(&pph[22..26]).unwrap())
+    }
+}
+
+/// Returns a Route Monitoring message with the given Per-Peer Header and
+/// BGP UPDATE message.
+fn mk_route_monitoring(
+    pph: &[u8],
+    bgp_msg: &[u8],
+) -> Result<Bytes, EncodeError> {
+    let len = COMMON_HEADER_LEN + pph.len() + bgp_msg.len();
+    let len = u32::try_from(len)
+        .map_err(|_| EncodeError::BmpMessageTooLong(len))?;
+    let mut buf = BytesMut::with_capacity(len as usize);
+    buf.put_u8(3);
+    buf.put_u32(len);
+    buf.put_u8(MessageType::RouteMonitoring.into());
+    buf.put_slice(pph);
+    buf.put_slice(bgp_msg);
+    Ok(buf.freeze())
+}
@@ -0,0 +1,538 @@
+//! Synthetic routing tables that look like the Internet table of a router
+//! with full transit.
+//!
+//! [`Table::generate`] produces a set of unique IPv4 and IPv6 prefixes with
+//! path attributes that follow the shape of the real table: most IPv4
+//! prefixes are /24s and most IPv6 prefixes /48s, AS paths are mostly three
+//! to five hops long and start at a handful of large transit networks, a
+//! few origin ASNs announce thousands of prefixes while most announce a
+//! few, and about half of the paths carry communities. The same seed always
+//! gives the same table.
+//!
+//! The routes feed into the encoder for any number of simulated peers, each
+//! adding its own ASN to the AS paths:
+//!
+//! ```no_run
+//! use routecore::asn::Asn;
+//! use routes::bmp::encode::{mk_route_monitoring_msg, PerPeerHeader, Prefixes};
+//! use routes::bmp::table::{Table, TableConfig};
+//!
+//! let table = Table::generate(&TableConfig::default());
+//! for idx in 1..=4u8 {
+//!     let peer_as = Asn::from_u32(64500 + u32::from(idx));
+//!     let v4_next_hop = [10, 0, 0, idx].into();
+//!     let v6_next_hop = [0xfd00, 0, 0, 0, 0, 0, 0, idx.into()].into();
+//!     let pph = PerPeerHeader::new(v4_next_hop, peer_as);
+//!     for route in table.routes() {
+//!         let next_hop =
+//!             if route.prefix.is_v4() { v4_next_hop } else { v6_next_hop };
+//!         let announcements = route.announcements(peer_as, next_hop);
+//!         let (bytes, _warnings) = mk_route_monitoring_msg(
+//!             &pph,
+//!             &Prefixes::default(),
+//!             &announcements,
+//!             &[],
+//!         )
+//!         .unwrap();
+//!         // send bytes to the station under test ...
+//!     }
+//! }
+//! ```
+
+use std::collections::{HashMap, HashSet};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use routecore::addr::Prefix;
+use routecore::asn::Asn;
+use routecore::bgp::aspath::HopPath;
+use routecore::bgp::communities::{
+    Community, LargeCommunity, StandardCommunity,
+};
+use routecore::bgp::types::{NextHop, OriginType};
+
+use crate::bmp::encode::{Announcements, Prefixes};
+
+/// The share of IPv4 prefixes per prefix length, in units of 0.001%,
+/// roughly as seen in the global table.
+const IPV4_LENGTHS: &[(u8, u32)] = &[
+    (8, 1),
+    (9, 1),
+    (10, 3),
+    (11, 8),
+    (12, 25),
+    (13, 50),
+    (14, 90),
+    (15, 160),
+    (16, 1400),
+    (17, 850),
+    (18, 1300),
+    (19, 2600),
+    (20, 4300),
+    (21, 5200),
+    (22, 11500),
+    (23, 10000),
+    (24, 62500),
+];
+
+/// The share of IPv6 prefixes per prefix length, in units of 0.01%, roughly
+/// as seen in the global table.
+const IPV6_LENGTHS: &[(u8, u32)] = &[
+    (16, 1),
+    (19, 1),
+    (20, 5),
+    (24, 10),
+    (28, 50),
+    (29, 500),
+    (30, 50),
+    (31, 60),
+    (32, 1200),
+    (33, 150),
+    (34, 200),
+    (35, 80),
+    (36, 400),
+    (37, 60),
+    (38, 150),
+    (39, 60),
+    (40, 700),
+    (41, 40),
+    (42, 150),
+    (43, 30),
+    (44, 800),
+    (45, 60),
+    (46, 250),
+    (47, 100),
+    (48, 4900),
+];
+
+/// The IPv6 blocks prefixes are taken from, with their share in percent:
+/// the RIR allocations from 2000::/3.
+const IPV6_BLOCKS: &[((u16, u8), u32)] = &[
+    ((0x2001, 16), 8),
+    ((0x2003, 18), 2),
+    ((0x2400, 12), 22),
+    ((0x2600, 12), 24),
+    ((0x2800, 12), 10),
+    ((0x2a00, 12), 32),
+    ((0x2c00, 12), 2),
+];
+
+/// The share of AS paths per number of hops after the peer, in percent.
+const PATH_LENGTHS: &[(usize, u32)] = &[
+    (1, 2),
+    (2, 18),
+    (3, 33),
+    (4, 25),
+    (5, 12),
+    (6, 6),
+    (7, 3),
+    (8, 1),
+];
+
+/// Transit-free networks, the first hop of most paths.
+const TIER1_ASNS: &[u32] = &[
+    174, 701, 1299, 2914, 3257, 3320, 3356, 3491, 5511, 6453, 6461, 6762,
+    6830, 7018, 12956,
+];
+
+/// The number of other transit networks making up the middle of paths.
+const TRANSIT_ASNS: usize = 2000;
+
+/// The average number of prefixes per origin ASN.
+const PREFIXES_PER_ORIGIN: usize = 12;
+
+/// IPv4 space that does not appear in the global table.
+const IPV4_BOGONS: &[(Ipv4Addr, u8)] = &[
+    (Ipv4Addr::new(0, 0, 0, 0), 8),
+    (Ipv4Addr::new(10, 0, 0, 0), 8),
+    (Ipv4Addr::new(100, 64, 0, 0), 10),
+    (Ipv4Addr::new(127, 0, 0, 0), 8),
+    (Ipv4Addr::new(169, 254, 0, 0), 16),
+    (Ipv4Addr::new(172, 16, 0, 0), 12),
+    (Ipv4Addr::new(192, 0, 2, 0), 24),
+    (Ipv4Addr::new(192, 168, 0, 0), 16),
+    (Ipv4Addr::new(198, 18, 0, 0), 15),
+    (Ipv4Addr::new(198, 51, 100, 0), 24),
+    (Ipv4Addr::new(203, 0, 113, 0), 24),
+];
+
+//------------ TableConfig ---------------------------------------------------
+
+/// The size of a table to generate and the seed to generate it from.
+#[derive(Clone, Copy, Debug)]
+pub struct TableConfig {
+    pub ipv4_prefixes: usize,
+    pub ipv6_prefixes: usize,
+    pub seed: u64,
+}
+
+/// A full table of today's size.
+impl Default for TableConfig {
+    fn default() -> Self {
+        TableConfig {
+            ipv4_prefixes: 1_000_000,
+            ipv6_prefixes: 220_000,
+            seed: 0,
+        }
+    }
+}
+
+//------------ Table ---------------------------------------------------------
+
+/// A generated routing table.
+#[derive(Clone, Debug)]
+pub struct Table {
+    /// The routes in prefix order.
+    routes: Vec<Route>,
+}
+
+impl Table {
+    pub fn generate(config: &TableConfig) -> Self {
+        let mut rng = Rng::new(config.seed);
+        let total = config.ipv4_prefixes + config.ipv6_prefixes;
+
+        let transit: Vec<Asn> =
+            (0..TRANSIT_ASNS).map(|_| random_asn(&mut rng)).collect();
+        let origins: Vec<Origin> = (0..(total / PREFIXES_PER_ORIGIN).max(1))
+            .map(|_| Origin::new(&mut rng, &transit))
+            .collect();
+
+        let mut seen = HashSet::with_capacity(total);
+        let mut routes = Vec::with_capacity(total);
+        for ipv6 in [false, true] {
+            let count = if ipv6 {
+                config.ipv6_prefixes
+            } else {
+                config.ipv4_prefixes
+            };
+            for _ in 0..count {
+                let prefix = loop {
+                    let prefix = if ipv6 {
+                        random_ipv6_prefix(&mut rng)
+                    } else {
+                        random_ipv4_prefix(&mut rng)
+                    };
+                    if seen.insert(prefix) {
+                        break prefix;
+                    }
+                };
+                // A few origins announce most of the prefixes.
+                let origin = &origins
+                    [(origins.len() as f64 * rng.unit().powi(3)) as usize];
+                let attributes = if rng.chance(0.15) {
+                    origin.paths[1].clone()
+                } else {
+                    origin.paths[0].clone()
+                };
+                routes.push(Route { prefix, attributes });
+            }
+        }
+        routes.sort_by_key(|route| route.prefix);
+
+        Table { routes }
+    }
+
+    /// Returns the routes in prefix order.
+    pub fn routes(&self) -> &[Route] {
+        &self.routes
+    }
+
+    pub fn len(&self) -> usize {
+        self.routes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.routes.is_empty()
+    }
+
+    /// Returns the number of distinct sets of path attributes.
+    pub fn attribute_set_count(&self) -> usize {
+        self.routes
+            .iter()
+            .map(|route| Arc::as_ptr(&route.attributes))
+            .collect::<HashSet<_>>()
+            .len()
+    }
+
+    /// Returns the prefixes of each address family for each set of path
+    /// attributes in the order they first appear, which is where a
+    /// router's update packing starts from.
+    pub fn by_attributes(&self) -> Vec<(Arc<PathAttributes>, Vec<Prefix>)> {
+        group_by_attributes(
+            self.routes
+                .iter()
+                .map(|route| (route.prefix, &route.attributes)),
+        )
+    }
+}
+
+/// Groups routes by address family and path attributes, in the order the
+/// groups first appear.
+pub(crate) fn group_by_attributes<'a>(
+    routes: impl IntoIterator<Item = (Prefix, &'a Arc<PathAttributes>)>,
+) -> Vec<(Arc<PathAttributes>, Vec<Prefix>)> {
+    let mut index = HashMap::new();
+    let mut out: Vec<(Arc<PathAttributes>, Vec<Prefix>)> = vec![];
+    for (prefix, attributes) in routes {
+        let key = (prefix.is_v4(), Arc::as_ptr(attributes));
+        let idx = *index.entry(key).or_insert_with(|| {
+            out.push((attributes.clone(), vec![]));
+            out.len() - 1
+        });
+        out[idx].1.push(prefix);
+    }
+    out
+}
+
+//------------ Route ---------------------------------------------------------
+
+/// A prefix with its path attributes.
+#[derive(Clone, Debug)]
+pub struct Route {
+    pub prefix: Prefix,
+
+    /// The path attributes, shared by the routes of the same origin and
+    /// path.
+    pub attributes: Arc<PathAttributes>,
+}
+
+impl Route {
+    /// Returns the announcement of the route by a peer.
+    pub fn announcements(
+        &self,
+        peer_as: Asn,
+        next_hop: IpAddr,
+    ) -> Announcements {
+        self.attributes
+            .announcements(peer_as, next_hop, vec![self.prefix])
+    }
+}
+
+//------------ PathAttributes ------------------------------------------------
+
+/// The path attributes of a route before a peer announces it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PathAttributes {
+    pub origin: OriginType,
+
+    /// The AS path from the first hop after the peer to the origin ASN,
+    /// which may be prepended.
+    pub as_path: Vec<Asn>,
+    pub communities: Vec<Community>,
+}
+
+impl PathAttributes {
+    /// Returns the origin ASN.
+    pub fn origin_asn(&self) -> Asn {
+        *self.as_path.last().unwrap()
+    }
+
+    /// Returns the announcement of prefixes by a peer, with the peer ASN
+    /// in front of the AS path.
+    pub fn announcements(
+        &self,
+        peer_as: Asn,
+        next_hop: IpAddr,
+        prefixes: Vec<Prefix>,
+    ) -> Announcements {
+        let mut as_path = HopPath::new();
+        as_path.append(peer_as);
+        for asn in &self.as_path {
+            as_path.append(*asn);
+        }
+        Announcements::Some {
+            origin: self.origin.into(),
+            as_path: as_path.into(),
+            next_hop: NextHop::Unicast(next_hop).into(),
+            communities: self.communities.clone().into(),
+            prefixes: Prefixes::new(prefixes),
+        }
+    }
+}
+
+//------------ Origin --------------------------------------------------------
+
+/// An origin ASN and the paths to it.
+struct Origin {
+    /// The usual path and an alternative, e.g. through another upstream.
+    paths: [Arc<PathAttributes>; 2],
+}
+
+impl Origin {
+    fn new(rng: &mut Rng, transit: &[Asn]) -> Self {
+        let asn = random_asn(rng);
+        let origin = match rng.below(100) {
+            0 => OriginType::Egp,
+            1..=11 => OriginType::Incomplete,
+            _ => OriginType::Igp,
+        };
+        let prepends = if rng.chance(0.08) {
+            rng.below(3) + 1
+        } else {
+            0
+        };
+        let mut path = || {
+            let hops = rng.weighted(PATH_LENGTHS);
+            let mut as_path = Vec::with_capacity(hops + 3);
+            if hops > 1 {
+                let tier1 =
+                    TIER1_ASNS[rng.below(TIER1_ASNS.len() as u64) as usize];
+                as_path.push(Asn::from_u32(tier1));
+            }
+            while as_path.len() + 1 < hops {
+                as_path
+                    .push(transit[rng.below(transit.len() as u64) as usize]);
+            }
+            as_path.push(asn);
+            for _ in 0..prepends {
+                as_path.push(asn);
+            }
+            Arc::new(PathAttributes {
+                origin,
+                communities: random_communities(rng, &as_path),
+                as_path,
+            })
+        };
+        let usual = path();
+        let alternative = path();
+        Origin {
+            paths: [usual, alternative],
+        }
+    }
+}
+
+//------------ Random values -------------------------------------------------
+
+/// Returns an ASN of the kind in use today, 16-bit or 32-bit.
+fn random_asn(rng: &mut Rng) -> Asn {
+    if rng.chance(0.55) {
+        // Skip AS_TRANS.
+        let asn = rng.below(64495) as u32 + 1;
+        Asn::from_u32(if asn == 23456 { asn + 1 } else { asn })
+    } else {
+        Asn::from_u32(131072 + rng.below(270_000) as u32)
+    }
+}
+
+/// Returns the communities for a path: none for about half of the paths,
+/// otherwise a few set by the networks in the path.
+fn random_communities(rng: &mut Rng, as_path: &[Asn]) -> Vec<Community> {
+    let mut out = vec![];
+    if rng.chance(0.45) {
+        return out;
+    }
+    let count = 1 + rng.below(4) + rng.below(4);
+    for _ in 0..count {
+        let asn =
+            as_path[rng.below(as_path.len() as u64) as usize].into_u32();
+        let value = 100 + rng.below(3900) as u32;
+        if let Ok(asn16) = u16::try_from(asn) {
+            out.push(
+                StandardCommunity::from_u32((u32::from(asn16) << 16) | value)
+                    .into(),
+            );
+        } else {
+            let mut raw = [0u8; 12];
+            raw[..4].copy_from_slice(&asn.to_be_bytes());
+            raw[4..8].copy_from_slice(&1u32.to_be_bytes());
+            raw[8..].copy_from_slice(&value.to_be_bytes());
+            out.push(LargeCommunity::from_raw(raw).into());
+        }
+    }
+    out.sort();
+    out.dedup();
+    out
+}
+
+fn random_ipv4_prefix(rng: &mut Rng) -> Prefix {
+    let len = rng.weighted(IPV4_LENGTHS);
+    loop {
+        // Unicast space only, i.e. up to 223.255.255.255.
+        let addr = rng.below(224 << 24) as u32;
+        let addr = addr & (u32::MAX << (32 - len));
+        let bogon = IPV4_BOGONS.iter().any(|(bogon, bogon_len)| {
+            let shorter = len.min(*bogon_len);
+            let mask = u32::MAX << (32 - shorter);
+            addr & mask == u32::from(*bogon) & mask
+        });
+        if !bogon {
+            return Prefix::new(Ipv4Addr::from(addr).into(), len).unwrap();
+        }
+    }
+}
+
+fn random_ipv6_prefix(rng: &mut Rng) -> Prefix {
+    let len = rng.weighted(IPV6_LENGTHS);
+    loop {
+        let (block, block_len) = rng.weighted(IPV6_BLOCKS);
+        let block = u128::from(block) << 112;
+        let random =
+            (u128::from(rng.next_u64()) << 64) | u128::from(rng.next_u64());
+        let addr = block | (random >> block_len);
+        let addr = addr & (u128::MAX << (128 - len));
+        // Skip the documentation prefix 2001:db8::/32.
+        if len >= 32 && addr >> 96 == 0x2001_0db8 {
+            continue;
+        }
+        return Prefix::new(Ipv6Addr::from(addr).into(), len).unwrap();
+    }
+}
+
+//------------ Rng -----------------------------------------------------------
+
+/// A small pseudo-random number generator, SplitMix64.
+///
+/// Implemented here rather than taken from a crate so that a seed gives
+/// the same table, or the same churn, with any version of the
+/// dependencies.
+pub(crate) struct Rng(u64);
+
+impl Rng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Rng(seed)
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    /// Returns a number from 0 up to but not including `n`.
+    pub(crate) fn below(&mut self, n: u64) -> u64 {
+        ((u128::from(self.next_u64()) * u128::from(n)) >> 64) as u64
+    }
+
+    /// Returns a number from 0 up to but not including 1.
+    pub(crate) fn unit(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    pub(crate) fn chance(&mut self, p: f64) -> bool {
+        self.unit() < p
+    }
+
+    /// Returns the time until the next event of a Poisson process with
+    /// the given mean time between events, at most `Duration::MAX`.
+    pub(crate) fn exponential(&mut self, mean: Duration) -> Duration {
+        let factor = -(1.0 - self.unit()).ln();
+        Duration::try_from_secs_f64(mean.as_secs_f64() * factor)
+            .unwrap_or(Duration::MAX)
+    }
+
+    /// Picks a value with a probability proportional to its weight.
+    pub(crate) fn weighted<T: Copy>(&mut self, choices: &[(T, u32)]) -> T {
+        let total: u32 = choices.iter().map(|(_, weight)| weight).sum();
+        let mut pick = self.below(total.into()) as u32;
+        for (value, weight) in choices {
+            if pick < *weight {
+                return *value;
+            }
+            pick -= weight;
+        }
+        unreachable!()
+    }
+}
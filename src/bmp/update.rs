@@ -0,0 +1,157 @@
+//! Taking apart raw BGP UPDATE messages.
+//!
+//! These helpers work on the wire format directly so that path attributes
+//! the encoder knows nothing about pass through unchanged. The
+//! [`encode`](crate::bmp::encode) module puts the parts together again.
+
+use routecore::addr::Prefix;
+use routecore::bgp::types::{Afi, PathAttributeType, Safi};
+
+use crate::bmp::encode::div_ceil;
+
+/// The BGP message type code of an UPDATE.
+pub(crate) const BGP_UPDATE: u8 = 2;
+
+/// The length of the BGP message header.
+pub(crate) const BGP_HEADER_LEN: usize = 19;
+
+//------------ Parsing -------------------------------------------------------
+
+/// The variable length fields of an UPDATE message.
+pub(crate) struct Update<'a> {
+    pub withdrawn: &'a [u8],
+    pub attributes: &'a [u8],
+    pub nlri: &'a [u8],
+}
+
+impl<'a> Update<'a> {
+    /// Splits a complete BGP message, including its header, into the fields
+    /// of an UPDATE.
+    ///
+    /// Returns `None` if the message is not an UPDATE or the lengths do not
+    /// add up.
+    pub fn parse(msg: &'a [u8]) -> Option<Self> {
+        if msg.get(18) != Some(&BGP_UPDATE) {
+            return None;
+        }
+        let len = usize::from(u16::from_be_bytes([msg[16], msg[17]]));
+        let body = msg.get(BGP_HEADER_LEN..len)?;
+        let withdrawn_len =
+            usize::from(u16::from_be_bytes(body.get(..2)?.try_into().ok()?));
+        let withdrawn = body.get(2..2 + withdrawn_len)?;
+        let rest = &body[2 + withdrawn_len..];
+        let attributes_len =
+            usize::from(u16::from_be_bytes(rest.get(..2)?.try_into().ok()?));
+        let attributes = rest.get(2..2 + attributes_len)?;
+        let nlri = &rest[2 + attributes_len..];
+        Some(Update {
+            withdrawn,
+            attributes,
+            nlri,
+        })
+    }
+}
+
+/// A single path attribute.
+pub(crate) struct Attribute<'a> {
+    pub typ: PathAttributeType,
+    pub value: &'a [u8],
+
+    /// The complete attribute, including flags, type and length.
+    pub raw: &'a [u8],
+}
+
+/// Splits the path attributes field of an UPDATE into its attributes.
+pub(crate) fn attributes(buf: &[u8]) -> Option<Vec<Attribute<'_>>> {
+    let mut out = vec![];
+    let mut pos = 0;
+    while pos < buf.len() {
+        let header = buf.get(pos..pos + 3)?;
+        let (flags, typ) = (header[0], header[1]);
+        let (header_len, len) = if flags & 0x10 != 0 {
+            let len = buf.get(pos + 2..pos + 4)?;
+            (4, usize::from(u16::from_be_bytes([len[0], len[1]])))
+        } else {
+            (3, usize::from(header[2]))
+        };
+        let end = pos + header_len + len;
+        out.push(Attribute {
+            typ: PathAttributeType::from(typ),
+            value: buf.get(pos + header_len..end)?,
+            raw: &buf[pos..end],
+        });
+        pos = end;
+    }
+    Some(out)
+}
+
+/// Parses a sequence of prefixes in the NLRI encoding.
+pub(crate) fn prefixes(mut buf: &[u8], ipv6: bool) -> Option<Vec<Prefix>> {
+    let mut out = vec![];
+    while let Some((&len, rest)) = buf.split_first() {
+        let octets = rest.get(..usize::from(div_ceil(len, 8)))?;
+        let prefix = if ipv6 {
+            let mut addr = [0u8; 16];
+            addr.get_mut(..octets.len())?.copy_from_slice(octets);
+            Prefix::new_v6_relaxed(addr.into(), len)
+        } else {
+            let mut addr = [0u8; 4];
+            addr.get_mut(..octets.len())?.copy_from_slice(octets);
+            Prefix::new_v4_relaxed(addr.into(), len)
+        };
+        out.push(prefix.ok()?);
+        buf = &rest[octets.len()..];
+    }
+    Some(out)
+}
+
+/// The content of an MP_REACH_NLRI or MP_UNREACH_NLRI attribute for
+/// unicast routes.
+pub(crate) struct MpNlri<'a> {
+    pub ipv6: bool,
+
+    /// The next hop, empty for MP_UNREACH_NLRI.
+    pub next_hop: &'a [u8],
+    pub nlri: &'a [u8],
+}
+
+impl<'a> MpNlri<'a> {
+    /// Parses the value of an MP_REACH_NLRI attribute.
+    ///
+    /// Returns `None` for anything but IPv4 or IPv6 unicast.
+    pub fn reach(value: &'a [u8]) -> Option<Self> {
+        let ipv6 = unicast_afi(value)?;
+        let nh_len = usize::from(*value.get(3)?);
+        let next_hop = value.get(4..4 + nh_len)?;
+        // Skip the reserved octet.
+        let nlri = value.get(5 + nh_len..)?;
+        Some(MpNlri {
+            ipv6,
+            next_hop,
+            nlri,
+        })
+    }
+
+    /// Parses the value of an MP_UNREACH_NLRI attribute.
+    pub fn unreach(value: &'a [u8]) -> Option<Self> {
+        Some(MpNlri {
+            ipv6: unicast_afi(value)?,
+            next_hop: &[],
+            nlri: value.get(3..)?,
+        })
+    }
+}
+
+/// Returns whether the AFI and SAFI at the start of the value are IPv6
+/// unicast, or `None` if they are neither IPv4 nor IPv6 unicast.
+fn unicast_afi(value: &[u8]) -> Option<bool> {
+    let afi = Afi::from(u16::from_be_bytes(value.get(..2)?.try_into().ok()?));
+    if Safi::from(*value.get(2)?) != Safi::Unicast {
+        return None;
+    }
+    match afi {
+        Afi::Ipv4 => Some(false),
+        Afi::Ipv6 => Some(true),
+        _ => None,
+    }
+}
@@ -0,0 +1,87 @@
+//! Parsing of hex encoded bytes.
+//!
+//! [`decode`] accepts the formats that bytes are usually copied around in:
+//!
+//! - contiguous hex digits, e.g. `ffff0013` as copied from Wireshark with
+//!   "Copy as a Hex Stream".
+//! - bytes separated by spaces and/or commas, with or without `0x`
+//!   prefix, e.g. `ff ff 0 13` or `0xff,0xff,0x00,0x13`.
+//! - `xxd` output, e.g. `00000000: ffff 0013  ....`.
+//! - `tcpdump -X` output, e.g. `0x0000:  ffff 0013  ....`.
+//! - Wireshark hex dumps, e.g. `0000   ff ff 00 13   ....`.
+//!
+//! Hex dumps may span multiple lines, the offsets at the start and the
+//! ASCII rendering at the end of each line are ignored.
+
+use anyhow::anyhow;
+
+/// Decodes hex encoded bytes in any of the supported formats.
+pub fn decode(s: &str) -> Result<Vec<u8>, anyhow::Error> {
+    let mut out = Vec::new();
+    for (idx, line) in s.lines().enumerate() {
+        decode_line(strip_dump_columns(line), &mut out)
+            .map_err(|err| anyhow!("line {}: {}", idx + 1, err))?;
+    }
+    if out.is_empty() {
+        return Err(anyhow!("Expected at least one hex encoded byte"));
+    }
+    Ok(out)
+}
+
+/// Returns the hex part of a line, without the offset and ASCII columns if
+/// the line is part of a hex dump.
+fn strip_dump_columns(line: &str) -> &str {
+    let line = line.trim_start();
+
+    // xxd and tcpdump: offset, colon, hex groups, two spaces, ASCII.
+    if let Some((offset, rest)) = line.split_once(':') {
+        let offset = offset.strip_prefix("0x").unwrap_or(offset);
+        if is_hex(offset) && rest.starts_with(' ') {
+            let rest = rest.trim_start();
+            return rest.split("  ").next().unwrap_or(rest);
+        }
+    }
+
+    // Wireshark: offset of at least four digits, two or more spaces,
+    // single bytes, three spaces, ASCII. Only the single bytes tell it
+    // apart from plain hex in groups separated by two spaces.
+    if let Some((offset, rest)) = line.split_once("  ") {
+        let rest = rest.trim_start();
+        let bytes = rest.split("   ").next().unwrap_or(rest);
+        let single_bytes = bytes
+            .split(' ')
+            .all(|token| token.len() == 2 && is_hex(token));
+        if offset.len() >= 4 && is_hex(offset) && single_bytes {
+            return bytes;
+        }
+    }
+
+    line
+}
+
+fn decode_line(line: &str, out: &mut Vec<u8>) -> Result<(), anyhow::Error> {
+    for token in line.split([' ', ',', '\t']).filter(|t| !t.is_empty()) {
+        let digits = token
+            .strip_prefix("0x")
+            .or_else(|| token.strip_prefix("0X"))
+            .unwrap_or(token);
+        if !is_hex(digits) {
+            return Err(anyhow!("'{token}' is not a hex byte string"));
+        }
+        if digits.len() == 1 {
+            out.push(u8::from_str_radix(digits, 16)?);
+            continue;
+        }
+        if !digits.len().is_multiple_of(2) {
+            return Err(anyhow!("'{token}' has an odd number of hex digits"));
+        }
+        for i in (0..digits.len()).step_by(2) {
+            out.push(u8::from_str_radix(&digits[i..i + 2], 16)?);
+        }
+    }
+    Ok(())
+}
+
+fn is_hex(s: &str) -> bool {
+    !s.is_empty() && s.bytes().all(|b| b.is_ascii_hexdigit())
+}
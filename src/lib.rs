@@ -1 +1,4 @@
 pub mod bmp;
+pub mod hex;
+pub mod mrt;
+pub mod pcap;
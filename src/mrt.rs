@@ -0,0 +1,546 @@
+//! Reading of MRT routing information export files.
+//!
+//! [`MrtReader`] reads the records of an [RFC 6396] MRT file, e.g. a RIB
+//! dump or an updates file from a route collector. The record types that
+//! carry BGP routing information are parsed, i.e.:
+//!
+//! - `TABLE_DUMP_V2` `PEER_INDEX_TABLE`, `RIB_IPV4_UNICAST` and
+//!   `RIB_IPV6_UNICAST`.
+//! - `BGP4MP` and `BGP4MP_ET` `STATE_CHANGE`, `STATE_CHANGE_AS4`, `MESSAGE`
+//!   and `MESSAGE_AS4`.
+//! - `BMP` `BMP_MESSAGE`, i.e. BMP messages as written by [`write_bmp_record`].
+//!
+//! All other records are returned as [`MrtBody::Unsupported`] so that a
+//! caller can report and skip them. Compressed files have to be
+//! decompressed first.
+//!
+//! [RFC 6396]: https://www.rfc-editor.org/rfc/rfc6396
+
+use std::fmt;
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+use bytes::Bytes;
+use routecore::addr::Prefix;
+use routecore::asn::Asn;
+
+use crate::bmp::clock::Timestamp;
+
+// MRT types and subtypes, from RFC 6396 section 4.
+const TABLE_DUMP_V2: u16 = 13;
+const BGP4MP: u16 = 16;
+const BGP4MP_ET: u16 = 17;
+
+/// The type of BMP messages, from draft-ietf-grow-mrt-bmp. Like the `_ET`
+/// types, it has a microsecond timestamp.
+const BMP: u16 = 18;
+
+const PEER_INDEX_TABLE: u16 = 1;
+const RIB_IPV4_UNICAST: u16 = 2;
+const RIB_IPV6_UNICAST: u16 = 4;
+
+const BGP4MP_STATE_CHANGE: u16 = 0;
+const BGP4MP_MESSAGE: u16 = 1;
+const BGP4MP_MESSAGE_AS4: u16 = 4;
+const BGP4MP_STATE_CHANGE_AS4: u16 = 5;
+
+const BMP_MESSAGE: u16 = 1;
+
+/// The length of the MRT common header.
+const COMMON_HEADER_LEN: usize = 12;
+
+//------------ MrtError ------------------------------------------------------
+
+/// The reason an MRT file could not be read.
+#[derive(Debug)]
+pub enum MrtError {
+    Io(io::Error),
+
+    /// A record ended before the named field.
+    Truncated(&'static str),
+
+    /// A field has a value that is not allowed by RFC 6396.
+    Invalid(&'static str),
+}
+
+impl fmt::Display for MrtError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            MrtError::Io(err) => write!(f, "Failed to read MRT file: {err}"),
+            MrtError::Truncated(field) => {
+                write!(f, "MRT record is truncated before the {field}")
+            }
+            MrtError::Invalid(field) => {
+                write!(f, "MRT record has an invalid {field}")
+            }
+        }
+    }
+}
+
+impl std::error::Error for MrtError {}
+
+impl From<io::Error> for MrtError {
+    fn from(err: io::Error) -> Self {
+        MrtError::Io(err)
+    }
+}
+
+//------------ MrtRecord -----------------------------------------------------
+
+/// A single MRT record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct MrtRecord {
+    /// The time the record was written, with microseconds for the `_ET`
+    /// record types.
+    pub timestamp: Timestamp,
+    pub body: MrtBody,
+}
+
+/// The parsed content of an MRT record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MrtBody {
+    /// The peers referred to by index in the RIB records that follow.
+    PeerIndexTable {
+        collector_bgp_id: [u8; 4],
+        view_name: String,
+        peers: Vec<PeerEntry>,
+    },
+
+    /// The routes of all peers for a single prefix.
+    Rib {
+        sequence: u32,
+        prefix: Prefix,
+        entries: Vec<RibEntry>,
+    },
+
+    /// A change in the BGP finite state machine of a session.
+    StateChange {
+        peer: Bgp4mpPeer,
+        old_state: BgpState,
+        new_state: BgpState,
+    },
+
+    /// A BGP message received from a peer.
+    Message {
+        peer: Bgp4mpPeer,
+
+        /// Whether the message was received over a session with 4-byte ASN
+        /// support, i.e. whether its AS_PATH has 4-byte ASNs.
+        as4: bool,
+
+        /// The complete BGP message, including the marker.
+        message: Bytes,
+    },
+
+    /// A complete BMP message.
+    Bmp { message: Bytes },
+
+    /// A record of a type or subtype that is not supported.
+    Unsupported { typ: u16, subtype: u16 },
+}
+
+/// A peer in a `PEER_INDEX_TABLE`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerEntry {
+    pub bgp_id: [u8; 4],
+    pub address: IpAddr,
+    pub asn: Asn,
+}
+
+/// The route of a single peer in a RIB record.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RibEntry {
+    /// The index of the peer in the preceding `PEER_INDEX_TABLE`.
+    pub peer_index: u16,
+
+    /// The time the route was received.
+    pub originated: Timestamp,
+
+    /// The path attributes, with 4-byte ASNs in the AS_PATH and only the
+    /// next hop in the MP_REACH_NLRI attribute of IPv6 routes.
+    pub attributes: Bytes,
+}
+
+/// The session a `BGP4MP` record refers to.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Bgp4mpPeer {
+    pub peer_as: Asn,
+    pub local_as: Asn,
+    pub interface_index: u16,
+    pub peer_address: IpAddr,
+    pub local_address: IpAddr,
+}
+
+/// A state of the BGP finite state machine, from RFC 4271 section 8.2.2.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct BgpState(pub u16);
+
+impl BgpState {
+    pub const IDLE: BgpState = BgpState(1);
+    pub const ESTABLISHED: BgpState = BgpState(6);
+
+    pub fn is_established(self) -> bool {
+        self == Self::ESTABLISHED
+    }
+}
+
+//------------ MrtReader -----------------------------------------------------
+
+/// Reads MRT records from a file or stream.
+///
+/// The reader is an iterator over the records and ends at the end of the
+/// input. A truncated last record is reported as an error.
+pub struct MrtReader<R> {
+    input: R,
+}
+
+impl<R: Read> MrtReader<R> {
+    pub fn new(input: R) -> Self {
+        MrtReader { input }
+    }
+
+    /// Reads the next record, or returns `None` at the end of the input.
+    pub fn read_record(&mut self) -> Result<Option<MrtRecord>, MrtError> {
+        let mut header = [0u8; COMMON_HEADER_LEN];
+        let mut filled = 0;
+        while filled < header.len() {
+            match self.input.read(&mut header[filled..]) {
+                Ok(0) if filled == 0 => return Ok(None),
+                Ok(0) => return Err(MrtError::Truncated("common header")),
+                Ok(n) => filled += n,
+                Err(err) if err.kind() == io::ErrorKind::Interrupted => {}
+                Err(err) => return Err(err.into()),
+            }
+        }
+        let mut hdr = Cursor::new(&header);
+        let secs = hdr.u32("timestamp")?;
+        let typ = hdr.u16("type")?;
+        let subtype = hdr.u16("subtype")?;
+        let len = hdr.u32("length")?;
+
+        // The buffer grows with what is actually read, so that a corrupt
+        // length doesn't allocate up to 4 GiB up front.
+        let mut msg = vec![];
+        self.input
+            .by_ref()
+            .take(u64::from(len))
+            .read_to_end(&mut msg)?;
+        if msg.len() as u64 != u64::from(len) {
+            return Err(MrtError::Truncated("message"));
+        }
+        let mut msg = Cursor::new(&msg);
+
+        let micros = if typ == BGP4MP_ET || typ == BMP {
+            msg.u32("microsecond timestamp")?
+        } else {
+            0
+        };
+        let timestamp = Timestamp::try_new(secs, micros)
+            .map_err(|_| MrtError::Invalid("microsecond timestamp"))?;
+
+        let body = match (typ, subtype) {
+            (TABLE_DUMP_V2, PEER_INDEX_TABLE) => parse_peer_index(&mut msg)?,
+            (TABLE_DUMP_V2, RIB_IPV4_UNICAST) => parse_rib(&mut msg, false)?,
+            (TABLE_DUMP_V2, RIB_IPV6_UNICAST) => parse_rib(&mut msg, true)?,
+            (BGP4MP | BGP4MP_ET, BGP4MP_STATE_CHANGE) => {
+                parse_state_change(&mut msg, false)?
+            }
+            (BGP4MP | BGP4MP_ET, BGP4MP_STATE_CHANGE_AS4) => {
+                parse_state_change(&mut msg, true)?
+            }
+            (BGP4MP | BGP4MP_ET, BGP4MP_MESSAGE) => {
+                parse_message(&mut msg, false)?
+            }
+            (BGP4MP | BGP4MP_ET, BGP4MP_MESSAGE_AS4) => {
+                parse_message(&mut msg, true)?
+            }
+            (BMP, BMP_MESSAGE) => MrtBody::Bmp {
+                message: Bytes::copy_from_slice(msg.rest()),
+            },
+            _ => MrtBody::Unsupported { typ, subtype },
+        };
+        Ok(Some(MrtRecord { timestamp, body }))
+    }
+}
+
+impl<R: Read> Iterator for MrtReader<R> {
+    type Item = Result<MrtRecord, MrtError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+//------------ Writing -------------------------------------------------------
+
+/// Writes a BMP message as an MRT record of type `BMP`.
+///
+/// The record has the microsecond timestamp of the `_ET` types and the
+/// unmodified BMP message as its message field, so that a capture can be
+/// read back with [`MrtReader`] and tools that know the BMP type.
+pub fn write_bmp_record<W: Write>(
+    out: &mut W,
+    timestamp: Timestamp,
+    msg: &[u8],
+) -> Result<(), io::Error> {
+    let len = u32::try_from(msg.len() + 4).map_err(|_| {
+        io::Error::new(io::ErrorKind::InvalidInput, "BMP message too long")
+    })?;
+    let mut header = Vec::with_capacity(COMMON_HEADER_LEN + 4);
+    header.extend_from_slice(&timestamp.secs.to_be_bytes());
+    header.extend_from_slice(&BMP.to_be_bytes());
+    header.extend_from_slice(&BMP_MESSAGE.to_be_bytes());
+    header.extend_from_slice(&len.to_be_bytes());
+    header.extend_from_slice(&timestamp.micros.to_be_bytes());
+    out.write_all(&header)?;
+    out.write_all(msg)
+}
+
+//------------ Parsing -------------------------------------------------------
+
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |                      Collector BGP ID                         |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |       View Name Length        |     View Name (variable)      |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |          Peer Count           |    Peer Entries (variable)
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//
+// From: https://www.rfc-editor.org/rfc/rfc6396#section-4.3.1
+fn parse_peer_index(msg: &mut Cursor) -> Result<MrtBody, MrtError> {
+    let collector_bgp_id = msg.array("collector BGP ID")?;
+    let view_name_len = msg.u16("view name length")?;
+    let view_name = String::from_utf8_lossy(
+        msg.bytes(view_name_len.into(), "view name")?,
+    )
+    .into_owned();
+    let peer_count = msg.u16("peer count")?;
+    let mut peers = Vec::with_capacity(peer_count.into());
+    for _ in 0..peer_count {
+        // "The Peer Type field is a bit field that encodes the type of
+        //  the AS and IP address as identified by the A and I bits,
+        //  respectively, below.
+        //
+        //   0 1 2 3 4 5 6 7
+        //  +-+-+-+-+-+-+-+-+
+        //  | | | | | | |A|I|
+        //  +-+-+-+-+-+-+-+-+
+        //
+        //  Bit 6: Peer AS number size:  0 = 16 bits, 1 = 32 bits
+        //  Bit 7: Peer IP Address family:  0 = IPv4,  1 = IPv6"
+        let peer_type = msg.u8("peer type")?;
+        let bgp_id = msg.array("peer BGP ID")?;
+        let address = msg.address(peer_type & 0x01 != 0, "peer address")?;
+        let asn = if peer_type & 0x02 != 0 {
+            msg.u32("peer AS")?
+        } else {
+            msg.u16("peer AS")?.into()
+        };
+        peers.push(PeerEntry {
+            bgp_id,
+            address,
+            asn: Asn::from_u32(asn),
+        });
+    }
+    Ok(MrtBody::PeerIndexTable {
+        collector_bgp_id,
+        view_name,
+        peers,
+    })
+}
+
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |                         Sequence Number                       |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// | Prefix Length |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |                        Prefix (variable)                      |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |         Entry Count           |  RIB Entries (variable)
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//
+// From: https://www.rfc-editor.org/rfc/rfc6396#section-4.3.2
+fn parse_rib(msg: &mut Cursor, ipv6: bool) -> Result<MrtBody, MrtError> {
+    let sequence = msg.u32("sequence number")?;
+    let len = msg.u8("prefix length")?;
+    let prefix_bytes = msg.bytes(usize::from(len).div_ceil(8), "prefix")?;
+    let prefix = if ipv6 {
+        let mut octets = [0u8; 16];
+        octets
+            .get_mut(..prefix_bytes.len())
+            .ok_or(MrtError::Invalid("prefix length"))?
+            .copy_from_slice(prefix_bytes);
+        Prefix::new_v6_relaxed(Ipv6Addr::from(octets), len)
+    } else {
+        let mut octets = [0u8; 4];
+        octets
+            .get_mut(..prefix_bytes.len())
+            .ok_or(MrtError::Invalid("prefix length"))?
+            .copy_from_slice(prefix_bytes);
+        Prefix::new_v4_relaxed(Ipv4Addr::from(octets), len)
+    }
+    .map_err(|_| MrtError::Invalid("prefix length"))?;
+
+    let entry_count = msg.u16("entry count")?;
+    let mut entries = Vec::with_capacity(entry_count.into());
+    for _ in 0..entry_count {
+        //  0                   1                   2                   3
+        //  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+        // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+        // |         Peer Index            |
+        // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+        // |                         Originated Time                       |
+        // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+        // |      Attribute Length         |
+        // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+        // |                    BGP Attributes... (variable)
+        // +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+        //
+        // From: https://www.rfc-editor.org/rfc/rfc6396#section-4.3.4
+        let peer_index = msg.u16("peer index")?;
+        let originated = Timestamp {
+            secs: msg.u32("originated time")?,
+            micros: 0,
+        };
+        let attributes_len = msg.u16("attribute length")?;
+        let attributes = Bytes::copy_from_slice(
+            msg.bytes(attributes_len.into(), "BGP attributes")?,
+        );
+        entries.push(RibEntry {
+            peer_index,
+            originated,
+            attributes,
+        });
+    }
+    Ok(MrtBody::Rib {
+        sequence,
+        prefix,
+        entries,
+    })
+}
+
+//  0                   1                   2                   3
+//  0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1 2 3 4 5 6 7 8 9 0 1
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |         Peer AS Number        |        Local AS Number        |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |        Interface Index        |        Address Family         |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |                      Peer IP Address (variable)               |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+// |                      Local IP Address (variable)              |
+// +-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+-+
+//
+// The AS numbers are 4 bytes in the _AS4 subtypes.
+//
+// From: https://www.rfc-editor.org/rfc/rfc6396#section-4.4.1
+fn parse_bgp4mp_peer(
+    msg: &mut Cursor,
+    as4: bool,
+) -> Result<Bgp4mpPeer, MrtError> {
+    let (peer_as, local_as) = if as4 {
+        (msg.u32("peer AS")?, msg.u32("local AS")?)
+    } else {
+        (msg.u16("peer AS")?.into(), msg.u16("local AS")?.into())
+    };
+    let interface_index = msg.u16("interface index")?;
+    let ipv6 = match msg.u16("address family")? {
+        1 => false,
+        2 => true,
+        _ => return Err(MrtError::Invalid("address family")),
+    };
+    Ok(Bgp4mpPeer {
+        peer_as: Asn::from_u32(peer_as),
+        local_as: Asn::from_u32(local_as),
+        interface_index,
+        peer_address: msg.address(ipv6, "peer IP address")?,
+        local_address: msg.address(ipv6, "local IP address")?,
+    })
+}
+
+fn parse_state_change(
+    msg: &mut Cursor,
+    as4: bool,
+) -> Result<MrtBody, MrtError> {
+    let peer = parse_bgp4mp_peer(msg, as4)?;
+    let old_state = BgpState(msg.u16("old state")?);
+    let new_state = BgpState(msg.u16("new state")?);
+    Ok(MrtBody::StateChange {
+        peer,
+        old_state,
+        new_state,
+    })
+}
+
+fn parse_message(msg: &mut Cursor, as4: bool) -> Result<MrtBody, MrtError> {
+    let peer = parse_bgp4mp_peer(msg, as4)?;
+    let message = Bytes::copy_from_slice(msg.rest());
+    Ok(MrtBody::Message { peer, as4, message })
+}
+
+//------------ Cursor --------------------------------------------------------
+
+/// Reads big-endian fields from a record, naming the field on truncation.
+struct Cursor<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Cursor<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Cursor { buf }
+    }
+
+    fn bytes(
+        &mut self,
+        len: usize,
+        field: &'static str,
+    ) -> Result<&'a [u8], MrtError> {
+        if self.buf.len() < len {
+            return Err(MrtError::Truncated(field));
+        }
+        let (bytes, rest) = self.buf.split_at(len);
+        self.buf = rest;
+        Ok(bytes)
+    }
+
+    fn array<const N: usize>(
+        &mut self,
+        field: &'static str,
+    ) -> Result<[u8; N], MrtError> {
+        let mut array = [0u8; N];
+        array.copy_from_slice(self.bytes(N, field)?);
+        Ok(array)
+    }
+
+    fn u8(&mut self, field: &'static str) -> Result<u8, MrtError> {
+        Ok(self.array::<1>(field)?[0])
+    }
+
+    fn u16(&mut self, field: &'static str) -> Result<u16, MrtError> {
+        Ok(u16::from_be_bytes(self.array(field)?))
+    }
+
+    fn u32(&mut self, field: &'static str) -> Result<u32, MrtError> {
+        Ok(u32::from_be_bytes(self.array(field)?))
+    }
+
+    fn address(
+        &mut self,
+        ipv6: bool,
+        field: &'static str,
+    ) -> Result<IpAddr, MrtError> {
+        if ipv6 {
+            Ok(Ipv6Addr::from(self.array::<16>(field)?).into())
+        } else {
+            Ok(Ipv4Addr::from(self.array::<4>(field)?).into())
+        }
+    }
+
+    fn rest(&mut self) -> &'a [u8] {
+        std::mem::take(&mut self.buf)
+    }
+}
@@ -0,0 +1,232 @@
+//! Writing of BMP messages as a pcap packet capture.
+//!
+//! [`PcapWriter`] wraps each message in synthetic Ethernet, IPv4 and TCP
+//! headers of a connection from a monitored router to port 11019 of a
+//! monitoring station, so that Wireshark and other tools decode the
+//! messages as if they had been sniffed from a real BMP session. The
+//! connection starts with a three-way handshake and the TCP sequence
+//! numbers follow the messages, so that stream reassembly works.
+//!
+//! The file is in the classic libpcap format with microsecond timestamps.
+
+use std::io::{self, Write};
+use std::net::Ipv4Addr;
+
+use crate::bmp::clock::Timestamp;
+
+/// The pcap link type for Ethernet.
+const LINKTYPE_ETHERNET: u32 = 1;
+
+/// The largest packet that is captured in full.
+const SNAPLEN: u32 = 65535;
+
+const ETHERNET_HEADER_LEN: usize = 14;
+const IPV4_HEADER_LEN: usize = 20;
+const TCP_HEADER_LEN: usize = 20;
+
+/// The largest TCP payload that fits in a single IPv4 packet.
+const MAX_SEGMENT_LEN: usize = 65535 - IPV4_HEADER_LEN - TCP_HEADER_LEN;
+
+const TCP_FIN: u8 = 0x01;
+const TCP_SYN: u8 = 0x02;
+const TCP_PSH: u8 = 0x08;
+const TCP_ACK: u8 = 0x10;
+
+/// One end of the synthetic TCP connection.
+struct Endpoint {
+    mac: [u8; 6],
+    addr: Ipv4Addr,
+    port: u16,
+
+    /// The next sequence number this end sends.
+    seq: u32,
+}
+
+// The addresses are from the documentation ranges of RFC 7042 and
+// RFC 5737.
+fn router() -> Endpoint {
+    Endpoint {
+        mac: [0x00, 0x00, 0x5e, 0x00, 0x53, 0x01],
+        addr: Ipv4Addr::new(192, 0, 2, 1),
+        port: 50000,
+        seq: 1_000_000,
+    }
+}
+
+fn station() -> Endpoint {
+    Endpoint {
+        mac: [0x00, 0x00, 0x5e, 0x00, 0x53, 0x02],
+        addr: Ipv4Addr::new(192, 0, 2, 2),
+        port: 11019,
+        seq: 2_000_000,
+    }
+}
+
+//------------ PcapWriter ----------------------------------------------------
+
+/// Writes BMP messages to a pcap file as a TCP session.
+pub struct PcapWriter<W> {
+    out: W,
+    router: Endpoint,
+    station: Endpoint,
+
+    /// Whether the handshake has been written.
+    connected: bool,
+
+    /// The IPv4 identification of the next packet.
+    ip_id: u16,
+}
+
+impl<W: Write> PcapWriter<W> {
+    /// Creates a writer, writing the pcap file header right away.
+    pub fn new(mut out: W) -> Result<Self, io::Error> {
+        let mut header = Vec::with_capacity(24);
+        header.extend_from_slice(&0xa1b2_c3d4u32.to_le_bytes());
+        header.extend_from_slice(&2u16.to_le_bytes());
+        header.extend_from_slice(&4u16.to_le_bytes());
+        header.extend_from_slice(&0i32.to_le_bytes());
+        header.extend_from_slice(&0u32.to_le_bytes());
+        header.extend_from_slice(&SNAPLEN.to_le_bytes());
+        header.extend_from_slice(&LINKTYPE_ETHERNET.to_le_bytes());
+        out.write_all(&header)?;
+        out.flush()?;
+        Ok(PcapWriter {
+            out,
+            router: router(),
+            station: station(),
+            connected: false,
+            ip_id: 0,
+        })
+    }
+
+    /// Writes a message sent by the router at the given time.
+    ///
+    /// The first message is preceded by the TCP handshake. Messages that do
+    /// not fit in a single packet are split into several segments.
+    pub fn write_message(
+        &mut self,
+        msg: &[u8],
+        ts: Timestamp,
+    ) -> Result<(), io::Error> {
+        if !self.connected {
+            self.packet(ts, true, TCP_SYN, &[])?;
+            self.router.seq = self.router.seq.wrapping_add(1);
+            self.packet(ts, false, TCP_SYN | TCP_ACK, &[])?;
+            self.station.seq = self.station.seq.wrapping_add(1);
+            self.packet(ts, true, TCP_ACK, &[])?;
+            self.connected = true;
+        }
+        for segment in msg.chunks(MAX_SEGMENT_LEN) {
+            self.packet(ts, true, TCP_PSH | TCP_ACK, segment)?;
+            self.router.seq =
+                self.router.seq.wrapping_add(segment.len() as u32);
+        }
+        self.out.flush()
+    }
+
+    /// Writes the router closing the connection at the given time.
+    ///
+    /// Nothing is written if no message was written before.
+    pub fn close(&mut self, ts: Timestamp) -> Result<(), io::Error> {
+        if !self.connected {
+            return Ok(());
+        }
+        self.packet(ts, true, TCP_FIN | TCP_ACK, &[])?;
+        self.router.seq = self.router.seq.wrapping_add(1);
+        self.connected = false;
+        self.out.flush()
+    }
+
+    pub fn into_inner(self) -> W {
+        self.out
+    }
+
+    /// Writes a single packet from the router or from the station.
+    fn packet(
+        &mut self,
+        ts: Timestamp,
+        from_router: bool,
+        flags: u8,
+        payload: &[u8],
+    ) -> Result<(), io::Error> {
+        let (src, dst) = if from_router {
+            (&self.router, &self.station)
+        } else {
+            (&self.station, &self.router)
+        };
+        let ip_len = IPV4_HEADER_LEN + TCP_HEADER_LEN + payload.len();
+        let frame_len = ETHERNET_HEADER_LEN + ip_len;
+        let mut frame = Vec::with_capacity(16 + frame_len);
+
+        // pcap record header.
+        frame.extend_from_slice(&ts.secs.to_le_bytes());
+        frame.extend_from_slice(&ts.micros.to_le_bytes());
+        frame.extend_from_slice(&(frame_len as u32).to_le_bytes());
+        frame.extend_from_slice(&(frame_len as u32).to_le_bytes());
+
+        // Ethernet.
+        frame.extend_from_slice(&dst.mac);
+        frame.extend_from_slice(&src.mac);
+        frame.extend_from_slice(&0x0800u16.to_be_bytes());
+
+        // IPv4, with the Don't Fragment flag.
+        let ip_start = frame.len();
+        frame.extend_from_slice(&[0x45, 0]);
+        frame.extend_from_slice(&(ip_len as u16).to_be_bytes());
+        frame.extend_from_slice(&self.ip_id.to_be_bytes());
+        frame.extend_from_slice(&0x4000u16.to_be_bytes());
+        frame.extend_from_slice(&[64, 6, 0, 0]);
+        frame.extend_from_slice(&src.addr.octets());
+        frame.extend_from_slice(&dst.addr.octets());
+        let checksum = internet_checksum(0, &frame[ip_start..]);
+        frame[ip_start + 10..ip_start + 12]
+            .copy_from_slice(&checksum.to_be_bytes());
+        self.ip_id = self.ip_id.wrapping_add(1);
+
+        // TCP, acknowledging everything the other end sent.
+        let tcp_start = frame.len();
+        let ack = if flags & TCP_ACK != 0 { dst.seq } else { 0 };
+        frame.extend_from_slice(&src.port.to_be_bytes());
+        frame.extend_from_slice(&dst.port.to_be_bytes());
+        frame.extend_from_slice(&src.seq.to_be_bytes());
+        frame.extend_from_slice(&ack.to_be_bytes());
+        frame.extend_from_slice(&[(TCP_HEADER_LEN as u8 / 4) << 4, flags]);
+        frame.extend_from_slice(&0xffffu16.to_be_bytes());
+        frame.extend_from_slice(&[0, 0, 0, 0]);
+        frame.extend_from_slice(payload);
+
+        // The TCP checksum covers a pseudo header with the addresses,
+        // protocol and TCP length.
+        let mut pseudo = Vec::with_capacity(12);
+        pseudo.extend_from_slice(&src.addr.octets());
+        pseudo.extend_from_slice(&dst.addr.octets());
+        pseudo.extend_from_slice(&[0, 6]);
+        pseudo.extend_from_slice(
+            &((TCP_HEADER_LEN + payload.len()) as u16).to_be_bytes(),
+        );
+        let sum = internet_checksum(0, &pseudo);
+        let checksum = internet_checksum(!sum, &frame[tcp_start..]);
+        frame[tcp_start + 16..tcp_start + 18]
+            .copy_from_slice(&checksum.to_be_bytes());
+
+        self.out.write_all(&frame)
+    }
+}
+
+/// Returns the RFC 1071 checksum of the data, continuing from the one's
+/// complement `initial` sum of preceding data.
+fn internet_checksum(initial: u16, data: &[u8]) -> u16 {
+    let mut sum = u32::from(initial);
+    for chunk in data.chunks(2) {
+        let word = match chunk {
+            [hi, lo] => u16::from_be_bytes([*hi, *lo]),
+            [hi] => u16::from_be_bytes([*hi, 0]),
+            _ => unreachable!(),
+        };
+        sum += u32::from(word);
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
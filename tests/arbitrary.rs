@@ -0,0 +1,39 @@
+//! Checks that the `routes::bmp::arbitrary` generators produce messages
+//! that encode without warnings and parse with routecore.
+#![cfg(feature = "arbitrary")]
+
+use proptest::prelude::*;
+use routecore::bgp::message::update::SessionConfig;
+use routecore::bmp::message::Message;
+
+use routes::bmp::arbitrary::{bmp_message, route_monitoring};
+
+proptest! {
+    #[test]
+    fn generated_messages_parse(msg in bmp_message()) {
+        let (bytes, warnings) = msg.build().unwrap();
+        prop_assert!(warnings.is_empty(), "{:?}", warnings);
+        let parsed = Message::from_octets(bytes.as_ref()).unwrap();
+        prop_assert_eq!(parsed.length() as usize, bytes.len());
+    }
+
+    #[test]
+    fn generated_updates_parse(msg in route_monitoring()) {
+        let (bytes, warnings) = msg.build().unwrap();
+        prop_assert!(warnings.is_empty(), "{:?}", warnings);
+        let Message::RouteMonitoring(rm) =
+            Message::from_octets(bytes.as_ref()).unwrap()
+        else {
+            panic!("expected Route Monitoring");
+        };
+        let config = if rm.per_peer_header().is_legacy_format() {
+            SessionConfig::legacy()
+        } else {
+            SessionConfig::modern()
+        };
+        let update = rm.bgp_update(config).unwrap();
+        update.unicast_withdrawals_vec().unwrap();
+        update.unicast_announcements_vec().unwrap();
+        update.all_communities().unwrap();
+    }
+}
@@ -0,0 +1,626 @@
+//! Runs the `bmp-speaker` binary and checks what it writes.
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::TcpStream;
+use std::process::{Command, Output, Stdio};
+
+use routecore::bgp::message::update::SessionConfig;
+use routecore::bmp::message::{Message, MessageType};
+
+use routes::bmp::capture::BmpReader;
+
+/// Runs `bmp-speaker` with the given arguments and REPL input.
+fn bmp_speaker(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_bmp-speaker"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(input.as_bytes())
+        .unwrap();
+    child.wait_with_output().unwrap()
+}
+
+fn message_types(bytes: &[u8]) -> Vec<MessageType> {
+    BmpReader::new(bytes)
+        .map(|msg| {
+            let msg = msg.unwrap();
+            Message::from_octets(msg.as_ref()).unwrap().msg_type()
+        })
+        .collect()
+}
+
+#[test]
+fn repl_to_stdout() {
+    let out = bmp_speaker(
+        &["--output", "-"],
+        "initiation my-name my-descr\n\
+         route_monitoring global 0 10.0.0.1 12345 0 none \"i [12345] 10.0.0.1 none 10.1.0.0/16\"\n\
+         termination\n",
+    );
+    assert!(out.status.success());
+    assert_eq!(
+        message_types(&out.stdout),
+        [
+            MessageType::InitiationMessage,
+            MessageType::RouteMonitoring,
+            MessageType::TerminationMessage
+        ]
+    );
+}
+
+#[test]
+fn sequence_warnings_and_show() {
+    let out = bmp_speaker(
+        &["--output", "-"],
+        "initiation my-name my-descr\n\
+         route_monitoring global 0 10.0.0.1 12345 0 none \"i [12345] 10.0.0.1 none 10.1.0.0/16\"\n\
+         peer_up_notification global 0 10.0.0.1 12345 127.0.0.1 80 81 888 999 0 0\n\
+         route_monitoring global 0 10.0.0.1 12345 0 none \"i [12345] 10.0.0.1 none 10.2.0.0/16\"\n\
+         show peers\n\
+         show routes\n",
+    );
+    assert!(out.status.success());
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains(
+        "Warning: RFC 7854 section 3.3 sequence violation: \
+         RouteMonitoring for peer 10.0.0.1 which is not up"
+    ));
+    assert!(stderr.contains("10.0.0.1 AS12345 BGP ID "));
+    assert!(stderr.contains("10.0.0.1 AS12345 pre-policy:"));
+    // The Peer Up started a new session with the peer, without the
+    // routes sent before it.
+    assert!(!stderr.contains("10.1.0.0/16"));
+    assert!(stderr.contains("  10.2.0.0/16 i [12345] 10.0.0.1 none"));
+    // Out of order messages are sent all the same.
+    assert_eq!(message_types(&out.stdout).len(), 4);
+}
+
+#[test]
+fn named_peers() {
+    let explicit = bmp_speaker(
+        &["--output", "-"],
+        "clock_fixed 1700000000\n\
+         peer_up_notification global 64 10.0.0.1 12345 127.0.0.1 80 81 888 999 1 2\n\
+         route_monitoring global 64 10.0.0.1 12345 2 none \"i [12345] 10.0.0.1 none 10.1.0.0/16\"\n\
+         raw_route_monitoring global 64 10.0.0.1 12345 2 ffffffffffffffffffffffffffffffff001702000000000000\n\
+         peer_down_notification global 64 10.0.0.1 12345 2\n",
+    );
+    let named = bmp_speaker(
+        &["--output", "-"],
+        "clock_fixed 1700000000\n\
+         peer_define r1 global 64 10.0.0.1 12345 2\n\
+         peer_up_notification r1 127.0.0.1 80 81 888 999 1 2\n\
+         route_monitoring r1 none \"i [12345] 10.0.0.1 none 10.1.0.0/16\"\n\
+         raw_route_monitoring r1 ffffffffffffffffffffffffffffffff001702000000000000\n\
+         peer_down_notification r1\n\
+         peer_down_notification r2\n",
+    );
+    assert!(named.status.success());
+    assert_eq!(message_types(&named.stdout).len(), 4);
+    assert_eq!(named.stdout, explicit.stdout);
+    assert!(String::from_utf8_lossy(&named.stderr).contains(
+        "Error: Unknown peer 'r2', define it with peer_define first"
+    ));
+}
+
+#[test]
+fn replay_to_file() {
+    let dir = std::env::temp_dir();
+    let capture = dir.join(format!("bmp-speaker-{}.in", std::process::id()));
+    let output = dir.join(format!("bmp-speaker-{}.out", std::process::id()));
+
+    let out = bmp_speaker(
+        &["--output", output.to_str().unwrap()],
+        "initiation a b\ntermination\n",
+    );
+    assert!(out.status.success());
+    std::fs::rename(&output, &capture).unwrap();
+
+    let out = bmp_speaker(
+        &[
+            "--output",
+            output.to_str().unwrap(),
+            "replay",
+            "--format",
+            "bmp",
+            capture.to_str().unwrap(),
+        ],
+        "",
+    );
+    assert!(out.status.success());
+    assert_eq!(
+        std::fs::read(&output).unwrap(),
+        std::fs::read(&capture).unwrap()
+    );
+    std::fs::remove_file(capture).unwrap();
+    std::fs::remove_file(output).unwrap();
+}
+
+#[test]
+fn run_script() {
+    let dir = std::env::temp_dir()
+        .join(format!("bmp-speaker-script-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(
+        dir.join("setup.bmps"),
+        "# Shared by several scenarios\n\
+         initiation a b\n\
+         peer_define r1 global 0 10.0.0.1 65001 1\n\
+         peer_up_notification r1 10.0.0.2 179 50000 65002 65001 2 1\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("main.bmps"),
+        "include setup.bmps\n\
+         let attrs = i [65001] 10.0.0.1 none\n\
+         for prefix in 10.1.0.0/23 split /24\n\
+         \x20   route_monitoring r1 none \"$attrs $prefix\"\n\
+         end\n\
+         repeat 2\n\
+         \x20   sleep 1ms\n\
+         \x20   for i in 1..3\n\
+         \x20       route_monitoring r1 none \"${attrs} 10.2.$i.0/24\"\n\
+         \x20   end\n\
+         end\n\
+         termination\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("broken.bmps"),
+        "include setup.bmps\n\
+         route_monitoring r2 none \"i [65001] 10.0.0.1 none 10.1.0.0/24\"\n\
+         termination\n",
+    )
+    .unwrap();
+
+    let out = bmp_speaker(
+        &[
+            "--output",
+            "-",
+            "run",
+            dir.join("main.bmps").to_str().unwrap(),
+        ],
+        "",
+    );
+    assert!(out.status.success());
+    let types = message_types(&out.stdout);
+    assert_eq!(types.len(), 1 + 1 + 2 + 2 * 3 + 1);
+    assert_eq!(types[0], MessageType::InitiationMessage);
+    assert_eq!(types[10], MessageType::TerminationMessage);
+
+    // The script stops at the first error, reporting where it is.
+    let broken = dir.join("broken.bmps");
+    let out =
+        bmp_speaker(&["--output", "-", "run", broken.to_str().unwrap()], "");
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr)
+        .contains(&format!("{}:2: Unknown peer 'r2'", broken.display())));
+    assert_eq!(message_types(&out.stdout).len(), 2);
+
+    let too_long = dir.join("too_long.bmps");
+    std::fs::write(
+        &too_long,
+        "initiation a b\nsleep 99999999999999999999999s\n",
+    )
+    .unwrap();
+    let out = bmp_speaker(
+        &["--output", "-", "run", too_long.to_str().unwrap()],
+        "",
+    );
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr).contains(&format!(
+        "{}:2: Duration 99999999999999999999999s is out of range",
+        too_long.display()
+    )));
+
+    std::fs::remove_dir_all(dir).unwrap();
+}
+
+#[test]
+fn bench() {
+    let out = bmp_speaker(
+        &[
+            "--output",
+            "-",
+            "bench",
+            "--peers",
+            "2",
+            "--prefixes",
+            "10",
+            "--attribute-sets",
+            "2",
+            "--batch",
+            "4",
+        ],
+        "",
+    );
+    assert!(out.status.success());
+    use MessageType::*;
+    // Batches of prefixes 0-3, 4-7 and 8-9 per peer, where the second is
+    // split in two by the change of path attributes after prefix 4.
+    let rm = [RouteMonitoring; 4];
+    assert_eq!(
+        message_types(&out.stdout),
+        [
+            &[InitiationMessage, PeerUpNotification, PeerUpNotification][..],
+            &rm,
+            &rm,
+            &[RouteMonitoring, RouteMonitoring, TerminationMessage],
+        ]
+        .concat()
+    );
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("Sent 10 messages"));
+    assert!(stderr.contains("with 20 routes"));
+    assert!(stderr.contains("Blocked on writes for "));
+
+    // The /24s from 1.0.0.0 up run out at 255.255.255.0/24.
+    let out = bmp_speaker(
+        &["--output", "-", "bench", "--prefixes", "16711681"],
+        "",
+    );
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr)
+        .contains("At most 16711680 prefixes per peer"));
+}
+
+#[test]
+fn bench_synthetic_table() {
+    let args = [
+        "--output",
+        "-",
+        "bench",
+        "--table",
+        "synthetic",
+        "--prefixes",
+        "300",
+        "--ipv6-prefixes",
+        "100",
+        "--batch",
+        "50",
+        "--seed",
+        "7",
+    ];
+    let out = bmp_speaker(&args, "");
+    assert!(out.status.success());
+    let types = message_types(&out.stdout);
+    use MessageType::*;
+    // An IPv4 and an IPv6 End-of-RIB marker before the Termination.
+    assert_eq!(types[..2], [InitiationMessage, PeerUpNotification]);
+    assert_eq!(
+        types[types.len() - 3..],
+        [RouteMonitoring, RouteMonitoring, TerminationMessage]
+    );
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains("with 400 routes"));
+
+    // The same seed gives the same messages, apart from the timestamps.
+    let again = bmp_speaker(&args, "");
+    assert_eq!(message_types(&again.stdout), types);
+    assert_eq!(again.stdout.len(), out.stdout.len());
+}
+
+#[test]
+fn churn() {
+    let out = bmp_speaker(
+        &[
+            "--output",
+            "-",
+            "churn",
+            "--peers",
+            "2",
+            "--prefixes",
+            "200",
+            "--rate",
+            "5",
+            "--duration",
+            "1m",
+            "--burst-interval",
+            "30s",
+            "--speed",
+            "max",
+        ],
+        "",
+    );
+    assert!(out.status.success());
+    let types = message_types(&out.stdout);
+    use MessageType::*;
+    assert_eq!(
+        types[..3],
+        [InitiationMessage, PeerUpNotification, PeerUpNotification]
+    );
+    assert_eq!(types.last(), Some(&TerminationMessage));
+    assert!(types[3..types.len() - 1]
+        .iter()
+        .all(|t| *t == RouteMonitoring));
+    let stderr = String::from_utf8_lossy(&out.stderr);
+    assert!(stderr.contains(" churn events in "), "{stderr}");
+    assert!(!stderr.contains("Warning"), "{stderr}");
+
+    let out =
+        bmp_speaker(&["--output", "-", "churn", "--rate", "1e-310"], "");
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr)
+        .contains("Rate 1e-310 is too small"));
+
+    let out = bmp_speaker(
+        &["--output", "-", "churn", "--burst-interval", "0s"],
+        "",
+    );
+    assert!(!out.status.success());
+    assert!(String::from_utf8_lossy(&out.stderr)
+        .contains("Expected a burst interval above zero or none"));
+}
+
+#[test]
+fn prefix_ranges_are_split_over_messages() {
+    // 1024 IPv4 /24s don't fit in one 4096 octet UPDATE.
+    let out = bmp_speaker(
+        &["--output", "-"],
+        "route_monitoring global 0 10.0.0.1 65001 0 none \
+         \"i [65001] 10.0.0.1 none 10.0.0.0/14 split /24\"\n\
+         route_monitoring global 0 10.0.0.1 65001 0 \
+         10.0.0.0/24..10.0.9.0/24 none\n",
+    );
+    assert!(out.status.success());
+    assert_eq!(
+        message_types(&out.stdout),
+        [MessageType::RouteMonitoring; 3]
+    );
+}
+
+#[test]
+fn announcements_are_packed_by_path_attributes() {
+    let out = bmp_speaker(
+        &["--output", "-"],
+        "route_monitoring global 0 10.0.0.1 65001 0 none \
+         \"i [65001] 10.0.0.1 none 10.1.0.0/24; \
+         i [65001,65002] 10.0.0.1 none 10.2.0.0/24; \
+         i [65001] 10.0.0.1 none 10.3.0.0/24\"\n",
+    );
+    assert!(out.status.success());
+    assert_eq!(
+        message_types(&out.stdout),
+        [MessageType::RouteMonitoring; 2]
+    );
+}
+
+#[test]
+fn dry_run() {
+    let out = bmp_speaker(
+        &["--dry-run"],
+        "initiation my-name my-descr\n\
+         route_monitoring global 0 10.0.0.1 65001 0 none \
+         \"i [65001] 10.0.0.1 none 10.1.0.0/16\"\n",
+    );
+    assert!(out.status.success());
+    let stdout = String::from_utf8(out.stdout).unwrap();
+    assert!(stdout.starts_with(
+        "Initiation, 29 octets\n  information TLVs: 2\n    SysName: my-name\n"
+    ));
+    assert!(stdout.contains("\nRoute Monitoring, "));
+    assert!(stdout.contains("\n      10.1.0.0/16\n"));
+    assert!(stdout.contains("   announced 10.1.0.0/16\n"));
+
+    // Nothing is sent, so there is nothing to record either.
+    let out = bmp_speaker(&["--dry-run", "--record", "unused.bmp"], "");
+    assert!(!out.status.success());
+    assert!(String::from_utf8(out.stderr)
+        .unwrap()
+        .contains("'--dry-run' cannot be used with '--record <FILE>'"));
+}
+
+#[test]
+fn verbose_and_hexdump() {
+    let out = bmp_speaker(
+        &["--output", "-", "--verbose", "--hexdump"],
+        "initiation my-name my-descr\n",
+    );
+    assert!(out.status.success());
+    assert_eq!(message_types(&out.stdout), [MessageType::InitiationMessage]);
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    assert!(stderr.contains("Initiation, 29 octets\n"));
+    assert!(stderr.contains("   message type: Initiation\n"));
+
+    // Only the messages themselves go to standard output without
+    // --dry-run, and nothing is printed unless asked for.
+    let out =
+        bmp_speaker(&["--output", "-"], "initiation my-name my-descr\n");
+    assert!(!String::from_utf8_lossy(&out.stderr).contains("Initiation"));
+}
+
+#[test]
+fn needs_a_destination() {
+    let out = bmp_speaker(&[], "");
+    assert!(!out.status.success());
+    let out = bmp_speaker(&["--server", "127.0.0.1", "--output", "-"], "");
+    assert!(!out.status.success());
+}
+
+#[test]
+fn listen() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_bmp-speaker"))
+        .args(["--listen", "127.0.0.1:0"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+
+    // The address is only known once the speaker is listening.
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let mut line = String::new();
+    stderr.read_line(&mut line).unwrap();
+    let addr = line.trim().rsplit(' ').next().unwrap();
+    let mut station = TcpStream::connect(addr).unwrap();
+
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"initiation a b\ntermination\n")
+        .unwrap();
+    let mut received = vec![];
+    station.read_to_end(&mut received).unwrap();
+    assert!(child.wait().unwrap().success());
+    assert_eq!(
+        message_types(&received),
+        [
+            MessageType::InitiationMessage,
+            MessageType::TerminationMessage
+        ]
+    );
+}
+
+#[test]
+fn listen_on_bare_ipv6_address() {
+    // Whether or not the address can be bound here, it gets the default
+    // port.
+    let mut child = Command::new(env!("CARGO_BIN_EXE_bmp-speaker"))
+        .args(["--listen", "::1"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut line = String::new();
+    BufReader::new(child.stderr.take().unwrap())
+        .read_line(&mut line)
+        .unwrap();
+    child.kill().unwrap();
+    child.wait().unwrap();
+    assert!(line.contains("[::1]:11019"), "{line}");
+}
+
+#[test]
+fn replay_session_to_next_station() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_bmp-speaker"))
+        .args(["--listen", "127.0.0.1:0", "--accept-multiple"])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    let mut stderr = BufReader::new(child.stderr.take().unwrap());
+    let mut line = String::new();
+    stderr.read_line(&mut line).unwrap();
+    let addr = line.trim().rsplit(' ').next().unwrap().to_string();
+
+    let route_monitoring = |prefix: &str| {
+        format!(
+            "route_monitoring global 0 10.0.0.1 65001 0 none \
+             \"i [65001] 10.0.0.1 none {prefix}\"\n"
+        )
+    };
+    let mut stdin = child.stdin.take().unwrap();
+    let station = TcpStream::connect(&addr).unwrap();
+    stdin
+        .write_all(
+            format!(
+                "initiation a b\n\
+                 peer_up_notification global 0 10.0.0.1 65001 10.0.0.2 \
+                 179 50000 65002 65001 1 2\n\
+                 {}",
+                route_monitoring("10.1.0.0/16")
+            )
+            .as_bytes(),
+        )
+        .unwrap();
+    let mut received = vec![0u8; 1];
+    (&station).read_exact(&mut received).unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    drop(station);
+
+    // Writing to the closed connection fails once the station's reset
+    // arrives, at the latest with the last message.
+    stdin
+        .write_all(route_monitoring("10.2.0.0/16").as_bytes())
+        .unwrap();
+    std::thread::sleep(std::time::Duration::from_millis(200));
+    let mut station = TcpStream::connect(&addr).unwrap();
+    stdin
+        .write_all(
+            format!("{}termination\n", route_monitoring("10.3.0.0/16"))
+                .as_bytes(),
+        )
+        .unwrap();
+    drop(stdin);
+    let mut received = vec![];
+    station.read_to_end(&mut received).unwrap();
+    assert!(child.wait().unwrap().success());
+
+    let types = message_types(&received);
+    assert_eq!(types[0], MessageType::InitiationMessage);
+    assert_eq!(types[1], MessageType::PeerUpNotification);
+    assert_eq!(types.last(), Some(&MessageType::TerminationMessage));
+    let announced: Vec<_> = BmpReader::new(received.as_slice())
+        .filter_map(|msg| {
+            let msg = msg.unwrap();
+            match Message::from_octets(msg.as_ref()).unwrap() {
+                Message::RouteMonitoring(rm) => Some(
+                    rm.bgp_update(SessionConfig::modern())
+                        .unwrap()
+                        .unicast_announcements_vec()
+                        .unwrap()
+                        .into_iter()
+                        .map(|nlri| nlri.prefix.to_string())
+                        .collect::<Vec<_>>(),
+                ),
+                _ => None,
+            }
+        })
+        .flatten()
+        .collect();
+    for prefix in ["10.1.0.0/16", "10.2.0.0/16", "10.3.0.0/16"] {
+        assert!(announced.iter().any(|p| p == prefix), "{prefix} missing");
+    }
+}
+
+#[test]
+fn fan_out() {
+    let listeners: Vec<_> = (0..2)
+        .map(|_| std::net::TcpListener::bind("127.0.0.1:0").unwrap())
+        .collect();
+    let addrs: Vec<_> = listeners
+        .iter()
+        .map(|listener| listener.local_addr().unwrap().to_string())
+        .collect();
+    let stations: Vec<_> = listeners
+        .into_iter()
+        .map(|listener| {
+            std::thread::spawn(move || {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut received = vec![];
+                stream.read_to_end(&mut received).unwrap();
+                received
+            })
+        })
+        .collect();
+
+    let out = bmp_speaker(
+        &["--server", &addrs[0], "--server", &addrs[1]],
+        "initiation a b\nstations\ntermination\n",
+    );
+    assert!(out.status.success());
+    let stderr = String::from_utf8(out.stderr).unwrap();
+    for addr in &addrs {
+        assert!(stderr.contains(&format!("{addr}: connected")));
+    }
+    let received: Vec<_> =
+        stations.into_iter().map(|t| t.join().unwrap()).collect();
+    assert_eq!(
+        message_types(&received[0]),
+        [
+            MessageType::InitiationMessage,
+            MessageType::TerminationMessage
+        ]
+    );
+    assert_eq!(received[0], received[1]);
+}
@@ -0,0 +1,81 @@
+//! Checks that `routes::bmp::capture` splits captures into messages and
+//! reads and rewrites Per-Peer Header timestamps.
+
+use std::io;
+
+use bytes::Bytes;
+use routecore::asn::Asn;
+use routecore::bmp::message::Message;
+
+use routes::bmp::builder::{Initiation, PeerDown, Termination};
+use routes::bmp::capture::{set_timestamp, timestamp, BmpReader};
+use routes::bmp::clock::Timestamp;
+use routes::bmp::encode::PerPeerHeader;
+
+const TIMESTAMP: Timestamp = Timestamp {
+    secs: 1_700_000_000,
+    micros: 123_456,
+};
+
+fn peer_down(ts: Timestamp) -> Bytes {
+    let pph =
+        PerPeerHeader::new("10.0.0.1".parse().unwrap(), Asn::from_u32(65001))
+            .timestamp(ts);
+    PeerDown::new(pph).build().unwrap().0
+}
+
+fn capture() -> Vec<Bytes> {
+    vec![
+        Initiation::new().build().unwrap(),
+        peer_down(TIMESTAMP),
+        Termination::new().build().unwrap(),
+    ]
+}
+
+#[test]
+fn read_messages() {
+    let file = capture().concat();
+    let messages: Vec<_> = BmpReader::new(file.as_slice())
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(messages, capture());
+}
+
+#[test]
+fn read_errors() {
+    let file = capture().concat();
+    let mut reader = BmpReader::new(&file[..file.len() - 1]);
+    assert!(reader.read_message().unwrap().is_some());
+    assert!(reader.read_message().unwrap().is_some());
+    assert_eq!(
+        reader.read_message().unwrap_err().kind(),
+        io::ErrorKind::UnexpectedEof
+    );
+
+    let mut reader = BmpReader::new(&[3, 0, 0, 0, 5, 4][..]);
+    assert_eq!(
+        reader.read_message().unwrap_err().kind(),
+        io::ErrorKind::InvalidData
+    );
+}
+
+#[test]
+fn timestamps() {
+    let [initiation, peer_down_msg, _] = &capture()[..] else {
+        unreachable!()
+    };
+    assert_eq!(timestamp(initiation), None);
+    assert_eq!(timestamp(peer_down_msg), Some(TIMESTAMP));
+    assert_eq!(timestamp(&peer_down(Timestamp::default())), None);
+
+    let now = Timestamp::try_new(1_800_000_000, 42).unwrap();
+    let changed = set_timestamp(peer_down_msg.clone(), now);
+    assert_eq!(timestamp(&changed), Some(now));
+    let Message::PeerDownNotification(msg) =
+        Message::from_octets(changed.as_ref()).unwrap()
+    else {
+        panic!("expected Peer Down");
+    };
+    assert_eq!(msg.per_peer_header().timestamp().timestamp(), 1_800_000_000);
+    assert_eq!(set_timestamp(initiation.clone(), now), *initiation);
+}
@@ -0,0 +1,264 @@
+//! Runs `routes::bmp::churn` over small synthetic tables and checks the
+//! events against a model of the RIB of each peer, the timing of the events
+//! and the messages they turn into.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use routecore::addr::Prefix;
+use routecore::asn::Asn;
+
+use routes::bmp::builder::{Initiation, PeerUp};
+use routes::bmp::churn::{
+    Churn, ChurnConfig, ChurnEvent, EventKind, EventMix,
+};
+use routes::bmp::encode::PerPeerHeader;
+use routes::bmp::session::Session;
+use routes::bmp::table::{PathAttributes, Table, TableConfig};
+
+fn table(seed: u64) -> Table {
+    Table::generate(&TableConfig {
+        ipv4_prefixes: 800,
+        ipv6_prefixes: 200,
+        seed,
+    })
+}
+
+fn events(table: &Table, config: ChurnConfig, secs: u64) -> Vec<ChurnEvent> {
+    Churn::new(table, 3, config)
+        .take_while(|event| event.at < Duration::from_secs(secs))
+        .collect()
+}
+
+/// Returns what the events do, without the path attributes.
+fn summary(events: &[ChurnEvent]) -> Vec<String> {
+    events
+        .iter()
+        .map(|event| {
+            let announced: Vec<_> = event
+                .announcements
+                .iter()
+                .flat_map(|(_, prefixes)| prefixes)
+                .collect();
+            format!(
+                "{:?} {} {} {:?} {:?}",
+                event.at,
+                event.peer,
+                event.kind,
+                event.withdrawals,
+                announced
+            )
+        })
+        .collect()
+}
+
+fn mix(announce: u32, replace: u32, withdraw: u32, flap: u32) -> EventMix {
+    EventMix {
+        announce,
+        replace,
+        withdraw,
+        flap,
+    }
+}
+
+#[test]
+fn reproducible() {
+    let table = table(1);
+    let config = ChurnConfig {
+        rate: 20.0,
+        burst_interval: Some(Duration::from_secs(60)),
+        seed: 5,
+        ..Default::default()
+    };
+    let a = events(&table, config, 300);
+    let b = events(&table, config, 300);
+    assert!(!a.is_empty());
+    assert_eq!(summary(&a), summary(&b));
+    let c = events(&table, ChurnConfig { seed: 6, ..config }, 300);
+    assert_ne!(summary(&a), summary(&c));
+}
+
+#[test]
+fn follows_the_rib() {
+    let table = table(2);
+    let config = ChurnConfig {
+        rate: 50.0,
+        flap_interval: Duration::from_secs(2),
+        burst_interval: Some(Duration::from_secs(30)),
+        burst_recovery: Duration::from_secs(20),
+        seed: 7,
+        ..Default::default()
+    };
+    let mut churn = Churn::new(&table, 3, config);
+    let mut ribs: Vec<BTreeMap<Prefix, Arc<PathAttributes>>> = (0..3)
+        .map(|_| {
+            table
+                .routes()
+                .iter()
+                .map(|route| (route.prefix, route.attributes.clone()))
+                .collect()
+        })
+        .collect();
+    let mut kinds = BTreeSet::new();
+    let mut last = Duration::ZERO;
+    for event in churn.by_ref().take(5000) {
+        assert!(event.at >= last);
+        last = event.at;
+        kinds.insert(event.kind);
+        let rib = &mut ribs[event.peer];
+        for prefix in &event.withdrawals {
+            assert!(rib.remove(prefix).is_some(), "{prefix} not announced");
+        }
+        for (attributes, prefixes) in &event.announcements {
+            for prefix in prefixes {
+                let old = rib.insert(*prefix, attributes.clone());
+                match event.kind {
+                    EventKind::Replace => {
+                        let old = old.unwrap();
+                        assert_eq!(old.origin_asn(), attributes.origin_asn());
+                        assert_eq!(old.origin, attributes.origin);
+                    }
+                    _ => assert!(old.is_none(), "{prefix} already announced"),
+                }
+            }
+        }
+    }
+    assert_eq!(kinds.len(), 7, "{kinds:?}");
+    for (peer, rib) in ribs.iter().enumerate() {
+        let tracked: Vec<_> = churn
+            .routes(peer)
+            .map(|(prefix, attributes)| (prefix, attributes.clone()))
+            .collect();
+        let expected: Vec<_> =
+            rib.iter().map(|(p, a)| (*p, a.clone())).collect();
+        assert_eq!(tracked, expected);
+    }
+}
+
+#[test]
+fn poisson_rate() {
+    let table = table(3);
+    let config = ChurnConfig {
+        rate: 25.0,
+        mix: mix(0, 1, 0, 0),
+        ..Default::default()
+    };
+    let events = events(&table, config, 2000);
+    assert!(events.iter().all(|event| event.kind == EventKind::Replace));
+    // 50000 expected, with a standard deviation of about 224.
+    assert!((49_000..51_000).contains(&events.len()), "{}", events.len());
+    for peer in 0..3 {
+        let count = events.iter().filter(|event| event.peer == peer).count();
+        assert!((16_000..17_400).contains(&count), "{count}");
+    }
+}
+
+#[test]
+fn tiny_rates() {
+    // Too small a rate for the mean time between events to fit a Duration
+    // means no events, and one that only just fits doesn't overflow.
+    let table = table(3);
+    for rate in [1e-310, 1e-19] {
+        let config = ChurnConfig {
+            rate,
+            ..Default::default()
+        };
+        assert!(events(&table, config, 1_000_000).is_empty());
+    }
+}
+
+#[test]
+fn bursts() {
+    let table = table(4);
+    let config = ChurnConfig {
+        rate: 0.0,
+        burst_interval: Some(Duration::from_secs(600)),
+        burst_share: 0.5,
+        burst_recovery: Duration::from_secs(60),
+        ..Default::default()
+    };
+    let events = events(&table, config, 36_000);
+    assert!(events.len() > 20);
+    let mut lost: BTreeMap<usize, Vec<Prefix>> = BTreeMap::new();
+    for event in &events {
+        match event.kind {
+            EventKind::Burst => {
+                // Half of what the peer announces, which is all of its
+                // table unless an earlier burst has not recovered yet.
+                let held: usize = lost.values().map(Vec::len).sum();
+                assert!(event.withdrawals.len() >= 250 || held > 0);
+                assert!(event.withdrawals.len() <= 500);
+                lost.entry(event.peer)
+                    .or_default()
+                    .extend(&event.withdrawals);
+            }
+            EventKind::Recovery => {
+                let mut announced: Vec<Prefix> = event
+                    .announcements
+                    .iter()
+                    .flat_map(|(_, prefixes)| prefixes.clone())
+                    .collect();
+                announced.sort();
+                let lost = lost.get_mut(&event.peer).unwrap();
+                assert!(announced.iter().all(|p| lost.contains(p)));
+                lost.retain(|p| !announced.contains(p));
+            }
+            kind => panic!("unexpected {kind}"),
+        }
+    }
+}
+
+#[test]
+fn zero_burst_interval() {
+    // Every burst would be due at once, so there are none.
+    let table = table(2);
+    let config = ChurnConfig {
+        rate: 0.0,
+        burst_interval: Some(Duration::ZERO),
+        burst_share: 1.0,
+        ..Default::default()
+    };
+    assert_eq!(Churn::new(&table, 1, config).take(3).count(), 0);
+}
+
+#[test]
+fn messages_update_the_session() {
+    let table = table(5);
+    let pph = PerPeerHeader::new([10, 0, 0, 1].into(), Asn::from_u32(64512));
+    let config = ChurnConfig {
+        rate: 100.0,
+        burst_interval: Some(Duration::from_secs(20)),
+        burst_share: 0.9,
+        seed: 9,
+        ..Default::default()
+    };
+    let mut session = Session::new();
+    session.observe(&Initiation::new().build().unwrap());
+    session.observe(&PeerUp::new(pph.clone()).build().unwrap().0);
+    // The table itself, then the churn.
+    let mut churn = Churn::new(&table, 1, config);
+    for event in table
+        .by_attributes()
+        .into_iter()
+        .map(|(attributes, prefixes)| ChurnEvent {
+            at: Duration::ZERO,
+            peer: 0,
+            kind: EventKind::Announce,
+            withdrawals: vec![],
+            announcements: vec![(attributes, prefixes)],
+        })
+        .chain(churn.by_ref().take(3000))
+    {
+        let messages = event
+            .messages(&pph, [10, 0, 0, 1].into(), "fd00::1".parse().unwrap())
+            .unwrap();
+        let (messages, warnings) = messages;
+        assert!(warnings.is_empty(), "{warnings:?}");
+        for msg in messages {
+            assert!(session.check(&msg).is_empty());
+            session.observe(&msg);
+        }
+    }
+    assert_eq!(session.route_count(), churn.routes(0).count());
+}
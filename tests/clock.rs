@@ -0,0 +1,88 @@
+//! Checks the timestamps and clocks of `routes::bmp::clock`.
+
+use chrono::{DateTime, Duration, Utc};
+
+use routes::bmp::clock::{Clock, FixedClock, SimulatedClock, Timestamp};
+use routes::bmp::encode::EncodeError;
+
+fn ts(secs: u32, micros: u32) -> Timestamp {
+    Timestamp::try_new(secs, micros).unwrap()
+}
+
+#[test]
+fn parse_and_format() {
+    for (text, expected) in [
+        ("0.000000", ts(0, 0)),
+        ("1700000000.123456", ts(1_700_000_000, 123_456)),
+        ("4294967295.999999", ts(u32::MAX, 999_999)),
+    ] {
+        assert_eq!(text.parse::<Timestamp>().unwrap(), expected);
+        assert_eq!(expected.to_string(), text);
+    }
+    assert_eq!(
+        "1700000000.5".parse::<Timestamp>().unwrap(),
+        ts(1_700_000_000, 500_000)
+    );
+    assert_eq!(
+        "2023-11-14T22:13:20.25Z".parse::<Timestamp>().unwrap(),
+        ts(1_700_000_000, 250_000)
+    );
+    for text in ["", "x", "1.1234567", "4294967296", "1969-12-31T23:59:59Z"] {
+        assert!(text.parse::<Timestamp>().is_err(), "{text}");
+    }
+}
+
+#[test]
+fn date_time_round_trip() {
+    let stamp = ts(1_700_000_000, 123_456);
+    let dt = DateTime::<Utc>::try_from(stamp).unwrap();
+    assert_eq!(dt.to_rfc3339(), "2023-11-14T22:13:20.123456+00:00");
+    assert_eq!(Timestamp::try_from(dt).unwrap(), stamp);
+}
+
+#[test]
+fn leap_second() {
+    assert_eq!(
+        "2016-12-31T23:59:60.5Z".parse::<Timestamp>().unwrap(),
+        ts(1_483_228_800, 500_000)
+    );
+    assert_eq!(
+        "2016-12-31T23:59:60Z".parse::<Timestamp>().unwrap(),
+        ts(1_483_228_800, 0)
+    );
+}
+
+#[test]
+fn invalid_microseconds() {
+    assert_eq!(
+        Timestamp::try_new(0, 1_000_000),
+        Err(EncodeError::InvalidMicroseconds(1_000_000))
+    );
+    for micros in [1_000_000, 4_294_968, u32::MAX] {
+        let ts = Timestamp { secs: 59, micros };
+        assert_eq!(
+            DateTime::<Utc>::try_from(ts),
+            Err(EncodeError::InvalidMicroseconds(micros))
+        );
+        assert!(ts.checked_add(Duration::seconds(1)).is_err());
+    }
+}
+
+#[test]
+fn simulated_clock_steps() {
+    let mut clock = SimulatedClock::new(
+        ts(1_700_000_000, 750_000),
+        Duration::milliseconds(500),
+    );
+    assert_eq!(clock.now().unwrap(), ts(1_700_000_000, 750_000));
+    assert_eq!(clock.now().unwrap(), ts(1_700_000_001, 250_000));
+    clock.advance(Duration::seconds(-10)).unwrap();
+    assert_eq!(clock.now().unwrap(), ts(1_699_999_991, 750_000));
+
+    let mut clock =
+        SimulatedClock::new(ts(u32::MAX, 0), Duration::seconds(1));
+    assert!(clock.now().is_err());
+
+    let mut clock = FixedClock(ts(42, 0));
+    assert_eq!(clock.now().unwrap(), clock.now().unwrap());
+}
@@ -0,0 +1,189 @@
+//! Checks the human-readable descriptions of `routes::bmp::describe`.
+
+use bytes::Bytes;
+use routecore::asn::Asn;
+use routecore::bmp::message::InformationTlvType;
+
+use routes::bmp::builder::{
+    Initiation, PeerDown, PeerUp, RouteMonitoring, StatisticsReport,
+    Termination,
+};
+use routes::bmp::clock::Timestamp;
+use routes::bmp::describe::{hexdump, tree};
+use routes::bmp::encode::{Open, PerPeerHeader};
+use routes::hex;
+
+fn pph() -> PerPeerHeader {
+    PerPeerHeader::new("10.0.0.1".parse().unwrap(), Asn::from_u32(65001))
+        .timestamp(Timestamp::try_new(1_700_000_000, 0).unwrap())
+}
+
+fn route_monitoring() -> Bytes {
+    RouteMonitoring::new(pph())
+        .withdraw("192.0.2.0/24".parse().unwrap())
+        .announce(
+            "i [65001,65002] 10.0.0.1 123:44 10.1.0.0/16"
+                .parse()
+                .unwrap(),
+        )
+        .build()
+        .unwrap()
+        .0
+}
+
+fn all_kinds() -> Vec<Bytes> {
+    vec![
+        Initiation::new().sys_name("r1").build().unwrap(),
+        PeerUp::new(pph())
+            .local("10.0.0.2".parse().unwrap(), 179)
+            .sent_open(Open::new(65002, 0x0a000002))
+            .information(InformationTlvType::String, "hello")
+            .build()
+            .unwrap()
+            .0,
+        route_monitoring(),
+        StatisticsReport::new(pph()).build().unwrap().0,
+        PeerDown::new(pph()).build().unwrap().0,
+        Termination::new().build().unwrap(),
+    ]
+}
+
+#[test]
+fn route_monitoring_tree() {
+    assert_eq!(
+        tree(&route_monitoring()),
+        "Route Monitoring, 109 octets
+  Per-Peer Header
+    peer type: GlobalInstance
+    flags: 0x00 (IPv4, pre-policy, 4-octet AS path)
+    distinguisher: 00 00 00 00 00 00 00 00
+    address: 10.0.0.1
+    AS: 65001
+    BGP ID: 10.0.0.1
+    timestamp: 2023-11-14 22:13:20 UTC
+  BGP UPDATE, 61 octets
+    withdrawals: 1
+      192.0.2.0/24
+    path attributes: 4
+      Origin: Igp
+      AsPath: AS65001 AS65002
+      NextHop: 10.0.0.1
+      Communities: AS123:44
+    announcements: 1
+      10.1.0.0/16
+"
+    );
+}
+
+#[test]
+fn peer_up_tree() {
+    let text = tree(&all_kinds()[1]);
+    assert!(text.starts_with("Peer Up Notification, "));
+    assert!(text.contains("\n  local address: 10.0.0.2\n  local port: 179\n"));
+    assert!(text.contains("\n  sent OPEN\n    version: 4\n    AS: 65002\n"));
+    assert!(text.ends_with("\n  information TLVs: 1\n    String: hello\n"));
+}
+
+#[test]
+fn route_monitoring_hexdump() {
+    let dump = hexdump(&route_monitoring());
+    let lines: Vec<&str> = dump.lines().collect();
+    assert_eq!(
+        lines[..3],
+        [
+            "0000  03                                                \
+             version: 3",
+            "0001  00 00 00 6d                                       \
+             message length: 109",
+            "0005  00                                                \
+             message type: Route Monitoring",
+        ]
+    );
+    for field in [
+        "peer address: 10.0.0.1",
+        "withdrawn 192.0.2.0/24",
+        "attribute type: AsPath",
+        "announced 10.1.0.0/16",
+    ] {
+        assert!(dump.contains(field), "no {field} in\n{dump}");
+    }
+}
+
+#[test]
+fn hexdump_reads_back() {
+    for msg in all_kinds() {
+        let dump = hexdump(&msg);
+        assert_eq!(hex::decode(&dump).unwrap(), msg.as_ref(), "{dump}");
+        assert!(!dump.contains("unexpected octets"), "{dump}");
+    }
+}
+
+#[test]
+fn malformed_messages() {
+    // Every truncation, and every message with a garbled octet, is still
+    // described in full.
+    for msg in all_kinds() {
+        for len in 1..msg.len() {
+            let truncated = &msg[..len];
+            assert!(tree(truncated).lines().count() > 1);
+            assert_eq!(hex::decode(&hexdump(truncated)).unwrap(), truncated);
+        }
+        for idx in 0..msg.len() {
+            for garble in [0xff, 0x01] {
+                let mut garbled = msg.to_vec();
+                garbled[idx] ^= garble;
+                tree(&garbled);
+                let dump = hexdump(&garbled);
+                assert_eq!(hex::decode(&dump).unwrap(), garbled);
+            }
+        }
+    }
+}
+
+#[test]
+fn routecore_pitfalls() {
+    // Each of these makes one of routecore's accessors panic, so these are
+    // checked before.
+    let peer_up = PeerUp::new(pph())
+        .local("10.0.0.2".parse().unwrap(), 179)
+        .sent_open(Open::new(65002, 0x0a000002).eor_capable(true))
+        .build()
+        .unwrap()
+        .0;
+    let with = |patch: &[(usize, u8)]| {
+        let mut msg = peer_up.to_vec();
+        for &(idx, octet) in patch {
+            msg[idx] = octet;
+        }
+        tree(&msg)
+    };
+    let micros =
+        with(&[(0x2c, 0xff), (0x2d, 0xff), (0x2e, 0xff), (0x2f, 0xff)]);
+    assert!(micros.contains(
+        "\n    timestamp: 1700000000.4294967295 \
+         (timestamp microseconds 4294967295 exceed 999999)\n"
+    ));
+    let short_four_octet_asn = with(&[(0x63, 65)]);
+    assert!(short_four_octet_asn.contains(
+        "\n    AS: 65002\n    hold time: 0\n    BGP ID: 10.0.0.2\n    \
+         capabilities: 1\n      FourOctetAsn\n"
+    ));
+    let empty_multisession = with(&[(0x63, 68), (0x64, 0)]);
+    assert!(empty_multisession
+        .ends_with("\n  cannot be parsed: empty Multisession capability\n"));
+    let parameter_overrun = with(&[(0x62, 5)]);
+    assert!(parameter_overrun.ends_with(
+        "\n  cannot be parsed: OPEN optional parameters do not add up\n"
+    ));
+
+    let mut peer_down = PeerDown::new(pph()).build().unwrap().0.to_vec();
+    peer_down.truncate(49);
+    peer_down[48] = 1;
+    assert!(tree(&peer_down).ends_with("\n  reason: LocalNotification\n"));
+
+    let termination = [3, 0, 0, 0, 11, 5, 0, 1, 0, 1, 0];
+    assert_eq!(
+        tree(&termination),
+        "Termination, 11 octets\n  information TLVs: 1\n    type 1: 00\n"
+    );
+}
@@ -0,0 +1,77 @@
+//! Checks the hex formats accepted by `routes::hex::decode`.
+
+use routes::hex::decode;
+
+const BYTES: [u8; 6] = [0x03, 0x00, 0x00, 0x00, 0x06, 0xff];
+
+#[test]
+fn contiguous() {
+    assert_eq!(decode("0300000006ff").unwrap(), BYTES);
+    assert_eq!(decode("0x0300000006FF").unwrap(), BYTES);
+}
+
+#[test]
+fn separated() {
+    assert_eq!(decode("03 00 00 00 06 ff").unwrap(), BYTES);
+    assert_eq!(decode("03,00,00,00,06,ff").unwrap(), BYTES);
+    assert_eq!(decode("0x03, 0x0, 0x00,0x00 0x06 0xff").unwrap(), BYTES);
+    assert_eq!(decode("3 0 0 0 6 ff").unwrap(), BYTES);
+    assert_eq!(decode("0300 0000 06ff").unwrap(), BYTES);
+}
+
+#[test]
+fn grouped() {
+    // Not to be mistaken for the offset of a Wireshark hex dump.
+    assert_eq!(decode("0300  0000  06ff").unwrap(), BYTES);
+    assert_eq!(decode("ffffffff  ffffffff").unwrap(), [0xff; 8]);
+}
+
+#[test]
+fn single_bytes_are_not_widened() {
+    assert_eq!(decode("ff").unwrap(), [0xff]);
+    assert_eq!(decode("ffff01").unwrap(), [0xff, 0xff, 0x01]);
+}
+
+#[test]
+fn xxd() {
+    let dump = "\
+00000000: 0300 0000 06ff                           ......
+";
+    assert_eq!(decode(dump).unwrap(), BYTES);
+
+    let dump = "\
+00000000: 0300 0000 6000 0000 0000 0000 0000 0000  ....`...........
+00000010: 0000                                     ..
+";
+    let bytes = decode(dump).unwrap();
+    assert_eq!(bytes.len(), 18);
+    assert_eq!(bytes[4], 0x60);
+}
+
+#[test]
+fn tcpdump() {
+    let dump = "\t0x0000:  0300 0000 06ff                           ......";
+    assert_eq!(decode(dump).unwrap(), BYTES);
+}
+
+#[test]
+fn wireshark() {
+    let dump = "\
+0000   03 00 00 00 06 ff                                 ......
+";
+    assert_eq!(decode(dump).unwrap(), BYTES);
+
+    let dump = "\
+0000   00 01 02 03 04 05 06 07 08 09 0a 0b 0c 0d 0e 0f   ................
+0010   10 11                                             ..
+";
+    assert_eq!(decode(dump).unwrap(), (0u8..18).collect::<Vec<_>>());
+}
+
+#[test]
+fn errors() {
+    assert!(decode("").is_err());
+    assert!(decode("abc").is_err());
+    assert!(decode("zz").is_err());
+    assert!(decode("ff\nxx").unwrap_err().to_string().contains("line 2"));
+}
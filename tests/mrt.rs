@@ -0,0 +1,392 @@
+//! Reads hand-made MRT records with `routes::mrt` and checks that
+//! `routes::bmp::replay` turns them into the expected BMP messages.
+
+use std::net::{IpAddr, Ipv6Addr};
+
+use bytes::BufMut;
+use routecore::addr::Prefix;
+use routecore::asn::Asn;
+use routecore::bgp::message::update::SessionConfig;
+use routecore::bmp::message::{Message, MessageType, PeerDownReason};
+
+use routes::bmp::clock::Timestamp;
+use routes::bmp::replay::MrtToBmp;
+use routes::mrt::{BgpState, MrtBody, MrtError, MrtReader, MrtRecord};
+
+const TS: u32 = 1_700_000_000;
+
+fn record(typ: u16, subtype: u16, body: &[u8]) -> Vec<u8> {
+    let mut out = vec![];
+    out.put_u32(TS);
+    out.put_u16(typ);
+    out.put_u16(subtype);
+    out.put_u32(body.len() as u32);
+    out.extend_from_slice(body);
+    out
+}
+
+/// A PEER_INDEX_TABLE with an IPv4 peer with a 4-byte ASN and an IPv6
+/// peer with a 2-byte ASN.
+fn peer_index_table() -> Vec<u8> {
+    let mut body = vec![];
+    body.extend_from_slice(&[192, 0, 2, 254]);
+    body.put_u16(4);
+    body.extend_from_slice(b"test");
+    body.put_u16(2);
+
+    body.put_u8(0x02);
+    body.extend_from_slice(&[10, 0, 0, 1]);
+    body.extend_from_slice(&[10, 0, 0, 1]);
+    body.put_u32(4_200_000_001);
+
+    body.put_u8(0x01);
+    body.extend_from_slice(&[10, 0, 0, 2]);
+    body.extend_from_slice(&ipv6("2001:db8::2"));
+    body.put_u16(65002);
+    record(13, 1, &body)
+}
+
+fn ipv6(s: &str) -> [u8; 16] {
+    s.parse::<Ipv6Addr>().unwrap().octets()
+}
+
+/// ORIGIN IGP and an AS_PATH with a single 4-byte ASN.
+fn common_attributes(asn: u32) -> Vec<u8> {
+    let mut out = vec![0x40, 1, 1, 0, 0x40, 2, 6, 2, 1];
+    out.put_u32(asn);
+    out
+}
+
+fn rib(
+    subtype: u16,
+    prefix: &[u8],
+    peer_index: u16,
+    attrs: &[u8],
+) -> Vec<u8> {
+    let mut body = vec![];
+    body.put_u32(0);
+    body.extend_from_slice(prefix);
+    body.put_u16(1);
+    body.put_u16(peer_index);
+    body.put_u32(TS - 10);
+    body.put_u16(attrs.len() as u16);
+    body.extend_from_slice(attrs);
+    record(13, subtype, &body)
+}
+
+fn rib_ipv4() -> Vec<u8> {
+    let mut attrs = common_attributes(4_200_000_001);
+    attrs.extend_from_slice(&[0x40, 3, 4, 10, 0, 0, 1]);
+    rib(2, &[16, 10, 1], 0, &attrs)
+}
+
+fn rib_ipv6() -> Vec<u8> {
+    let mut attrs = common_attributes(65002);
+    // The abbreviated MP_REACH_NLRI of RFC 6396 section 4.3.4: only the
+    // next hop length and next hop.
+    attrs.extend_from_slice(&[0x80, 14, 17, 16]);
+    attrs.extend_from_slice(&ipv6("2001:db8::2"));
+    rib(4, &[32, 0x20, 0x01, 0x0d, 0xb8], 1, &attrs)
+}
+
+/// The BGP4MP peer part with 4-byte ASNs and IPv4 addresses.
+fn bgp4mp_peer() -> Vec<u8> {
+    let mut out = vec![];
+    out.put_u32(65003);
+    out.put_u32(65000);
+    out.put_u16(0);
+    out.put_u16(1);
+    out.extend_from_slice(&[192, 0, 2, 3]);
+    out.extend_from_slice(&[192, 0, 2, 254]);
+    out
+}
+
+fn bgp4mp_update() -> Vec<u8> {
+    let mut attrs = common_attributes(65003);
+    attrs.extend_from_slice(&[0x40, 3, 4, 192, 0, 2, 3]);
+    let nlri = [24, 198, 51, 100];
+    let mut msg = vec![0xff; 16];
+    msg.put_u16((19 + 2 + 2 + attrs.len() + nlri.len()) as u16);
+    msg.put_u8(2);
+    msg.put_u16(0);
+    msg.put_u16(attrs.len() as u16);
+    msg.extend_from_slice(&attrs);
+    msg.extend_from_slice(&nlri);
+
+    let mut body = bgp4mp_peer();
+    body.extend_from_slice(&msg);
+    record(16, 4, &body)
+}
+
+fn bgp4mp_state_change(old: u16, new: u16) -> Vec<u8> {
+    let mut body = bgp4mp_peer();
+    body.put_u16(old);
+    body.put_u16(new);
+    record(16, 5, &body)
+}
+
+fn mrt_file() -> Vec<u8> {
+    [
+        peer_index_table(),
+        rib_ipv4(),
+        rib_ipv6(),
+        record(12, 1, &[0; 8]),
+        bgp4mp_update(),
+        bgp4mp_state_change(6, 1),
+    ]
+    .concat()
+}
+
+fn read_all(bytes: &[u8]) -> Vec<MrtRecord> {
+    MrtReader::new(bytes).collect::<Result<_, _>>().unwrap()
+}
+
+#[test]
+fn read_records() {
+    let records = read_all(&mrt_file());
+    assert_eq!(records.len(), 6);
+    assert!(records
+        .iter()
+        .all(|r| r.timestamp == Timestamp::try_new(TS, 0).unwrap()));
+
+    let MrtBody::PeerIndexTable {
+        view_name, peers, ..
+    } = &records[0].body
+    else {
+        panic!("expected a peer index table");
+    };
+    assert_eq!(view_name, "test");
+    assert_eq!(peers.len(), 2);
+    assert_eq!(peers[0].asn, Asn::from_u32(4_200_000_001));
+    assert_eq!(peers[1].address, "2001:db8::2".parse::<IpAddr>().unwrap());
+    assert_eq!(peers[1].asn, Asn::from_u32(65002));
+
+    let MrtBody::Rib {
+        prefix, entries, ..
+    } = &records[2].body
+    else {
+        panic!("expected a RIB record");
+    };
+    assert_eq!(*prefix, "2001:db8::/32".parse::<Prefix>().unwrap());
+    assert_eq!(entries[0].peer_index, 1);
+    assert_eq!(
+        entries[0].originated,
+        Timestamp::try_new(TS - 10, 0).unwrap()
+    );
+
+    assert_eq!(
+        records[3].body,
+        MrtBody::Unsupported {
+            typ: 12,
+            subtype: 1
+        }
+    );
+
+    let MrtBody::Message { peer, as4, message } = &records[4].body else {
+        panic!("expected a BGP4MP message");
+    };
+    assert!(as4);
+    assert_eq!(peer.peer_as, Asn::from_u32(65003));
+    assert_eq!(message[18], 2);
+
+    let MrtBody::StateChange {
+        old_state,
+        new_state,
+        ..
+    } = &records[5].body
+    else {
+        panic!("expected a BGP4MP state change");
+    };
+    assert!(old_state.is_established());
+    assert_eq!(*new_state, BgpState::IDLE);
+}
+
+#[test]
+fn extended_timestamp() {
+    let mut body = vec![];
+    body.put_u32(250_000);
+    body.extend_from_slice(&bgp4mp_state_change(1, 6)[12..]);
+    let records = read_all(&record(17, 5, &body));
+    assert_eq!(
+        records[0].timestamp,
+        Timestamp::try_new(TS, 250_000).unwrap()
+    );
+    assert!(matches!(
+        records[0].body,
+        MrtBody::StateChange { new_state, .. } if new_state.is_established()
+    ));
+}
+
+#[test]
+fn truncated() {
+    let file = mrt_file();
+    let mut reader = MrtReader::new(&file[..file.len() - 1]);
+    for _ in 0..5 {
+        assert!(reader.read_record().unwrap().is_some());
+    }
+    assert!(matches!(
+        reader.read_record(),
+        Err(MrtError::Truncated("message"))
+    ));
+
+    let mut reader = MrtReader::new(&file[..5]);
+    assert!(matches!(
+        reader.read_record(),
+        Err(MrtError::Truncated("common header"))
+    ));
+
+    let mut huge = record(13, 1, &[0; 8]);
+    huge[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+    let mut reader = MrtReader::new(&huge[..]);
+    assert!(matches!(
+        reader.read_record(),
+        Err(MrtError::Truncated("message"))
+    ));
+}
+
+#[test]
+fn convert() {
+    let mut converter = MrtToBmp::new();
+    let mut out = vec![];
+    for record in read_all(&mrt_file()) {
+        out.extend(converter.convert(&record).unwrap());
+    }
+    out.extend(converter.finish().unwrap());
+    assert_eq!(converter.skipped(), 1);
+    assert!(out.iter().all(|(_, warnings)| warnings.is_empty()));
+
+    let msgs: Vec<_> = out
+        .iter()
+        .map(|(bytes, _)| Message::from_octets(bytes.as_ref()).unwrap())
+        .collect();
+    let types: Vec<_> = msgs.iter().map(|msg| msg.msg_type()).collect();
+    use MessageType::*;
+    assert_eq!(
+        types,
+        [
+            // The peer index table.
+            PeerUpNotification,
+            PeerUpNotification,
+            // The RIB records.
+            RouteMonitoring,
+            RouteMonitoring,
+            // End-of-RIB for both address families of both peers.
+            RouteMonitoring,
+            RouteMonitoring,
+            RouteMonitoring,
+            RouteMonitoring,
+            // The UPDATE of a peer without a state change.
+            PeerUpNotification,
+            RouteMonitoring,
+            // The state change from Established to Idle.
+            PeerDownNotification,
+        ]
+    );
+
+    let Message::RouteMonitoring(rm) = &msgs[2] else {
+        unreachable!()
+    };
+    assert_eq!(rm.per_peer_header().asn(), Asn::from_u32(4_200_000_001));
+    assert_eq!(rm.per_peer_header().timestamp().timestamp(), i64::from(TS));
+    let update = rm.bgp_update(SessionConfig::modern()).unwrap();
+    let announced: Vec<_> = update
+        .unicast_announcements_vec()
+        .unwrap()
+        .into_iter()
+        .map(|n| n.prefix)
+        .collect();
+    assert_eq!(announced, ["10.1.0.0/16".parse::<Prefix>().unwrap()]);
+
+    let Message::RouteMonitoring(rm) = &msgs[3] else {
+        unreachable!()
+    };
+    assert!(rm.per_peer_header().is_ipv6());
+    let update = rm.bgp_update(SessionConfig::modern()).unwrap();
+    let announced: Vec<_> = update
+        .unicast_announcements_vec()
+        .unwrap()
+        .into_iter()
+        .map(|n| n.prefix)
+        .collect();
+    assert_eq!(announced, ["2001:db8::/32".parse::<Prefix>().unwrap()]);
+
+    for msg in &msgs[4..8] {
+        let Message::RouteMonitoring(rm) = msg else {
+            unreachable!()
+        };
+        let update = rm.bgp_update(SessionConfig::modern()).unwrap();
+        assert!(update.is_eor().unwrap().is_some());
+    }
+
+    let Message::RouteMonitoring(rm) = &msgs[9] else {
+        unreachable!()
+    };
+    assert_eq!(rm.per_peer_header().asn(), Asn::from_u32(65003));
+    let update = rm.bgp_update(SessionConfig::modern()).unwrap();
+    assert_eq!(update.unicast_announcements_vec().unwrap().len(), 1);
+
+    let Message::PeerDownNotification(pd) = &msgs[10] else {
+        unreachable!()
+    };
+    assert_eq!(pd.reason(), PeerDownReason::RemoteNodata);
+}
+
+#[test]
+fn unknown_peer_index() {
+    let mut converter = MrtToBmp::new();
+    let record = read_all(&rib_ipv4()).remove(0);
+    assert!(converter.convert(&record).is_err());
+}
+
+#[test]
+fn empty_mp_reach_nlri() {
+    let mut converter = MrtToBmp::new();
+    let mut attrs = common_attributes(65002);
+    attrs.extend_from_slice(&[0x80, 14, 0]);
+    let file = [
+        peer_index_table(),
+        rib(4, &[32, 0x20, 0x01, 0x0d, 0xb8], 1, &attrs),
+    ]
+    .concat();
+    let mut records = read_all(&file);
+    converter.convert(&records.remove(0)).unwrap();
+    assert!(converter.convert(&records.remove(0)).is_err());
+}
+
+#[test]
+fn end_of_rib_on_finish() {
+    let mut converter = MrtToBmp::new();
+    let file = [peer_index_table(), rib_ipv4()].concat();
+    for record in read_all(&file) {
+        converter.convert(&record).unwrap();
+    }
+    // Only IPv4 routes were dumped, so one marker per peer.
+    assert_eq!(converter.finish().unwrap().len(), 2);
+    assert!(converter.finish().unwrap().is_empty());
+}
+
+#[test]
+fn state_changes() {
+    let mut converter = MrtToBmp::new();
+    let file = [
+        bgp4mp_state_change(5, 6),
+        bgp4mp_state_change(6, 6),
+        bgp4mp_state_change(6, 1),
+        bgp4mp_state_change(1, 2),
+    ]
+    .concat();
+    let types: Vec<_> = read_all(&file)
+        .iter()
+        .flat_map(|record| converter.convert(record).unwrap())
+        .map(|(bytes, _)| {
+            Message::from_octets(bytes.as_ref()).unwrap().msg_type()
+        })
+        .collect();
+    assert_eq!(
+        types,
+        [
+            MessageType::PeerUpNotification,
+            MessageType::PeerDownNotification
+        ]
+    );
+}
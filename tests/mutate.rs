@@ -0,0 +1,239 @@
+//! Checks that `routes::bmp::mutate` breaks exactly what it is asked to.
+
+use bytes::Bytes;
+use routecore::asn::Asn;
+use routecore::bgp::message::update::SessionConfig;
+use routecore::bgp::types::PathAttributeType;
+use routecore::bmp::message::{InformationTlvType, Message};
+
+use routes::bmp::builder::{Initiation, PeerDown, PeerUp, RouteMonitoring};
+use routes::bmp::clock::Timestamp;
+use routes::bmp::encode::{Open, PerPeerHeader};
+use routes::bmp::mutate::{fix_lengths, mutate, MutateError, Mutation};
+
+fn route_monitoring() -> Bytes {
+    let pph =
+        PerPeerHeader::new("10.0.0.1".parse().unwrap(), Asn::from_u32(65001))
+            .timestamp(Timestamp::try_new(1_700_000_000, 0).unwrap());
+    RouteMonitoring::new(pph)
+        .withdraw("192.0.2.0/24".parse().unwrap())
+        .announce(
+            "i [65001,65002] 10.0.0.1 123:44 10.1.0.0/16"
+                .parse()
+                .unwrap(),
+        )
+        .build()
+        .unwrap()
+        .0
+}
+
+fn bmp_length(msg: &[u8]) -> usize {
+    u32::from_be_bytes([msg[1], msg[2], msg[3], msg[4]]) as usize
+}
+
+/// The BGP message starts after the common and Per-Peer headers.
+const BGP: usize = 6 + 42;
+
+fn bgp_length(msg: &[u8]) -> usize {
+    usize::from(u16::from_be_bytes([msg[BGP + 16], msg[BGP + 17]]))
+}
+
+#[test]
+fn no_mutations() {
+    let msg = route_monitoring();
+    assert_eq!(mutate(&msg, &[]).unwrap(), msg);
+}
+
+#[test]
+fn single_field_mutations() {
+    let msg = route_monitoring();
+
+    let out = mutate(&msg, &[Mutation::BmpLength(7)]).unwrap();
+    assert_eq!(bmp_length(&out), 7);
+
+    let out = mutate(&msg, &[Mutation::BgpLength(5)]).unwrap();
+    assert_eq!(bgp_length(&out), 5);
+    assert_eq!(bmp_length(&out), msg.len());
+
+    let out = mutate(&msg, &[Mutation::Version(1)]).unwrap();
+    assert_eq!(out[0], 1);
+    assert!(Message::from_octets(out.as_ref()).is_err());
+
+    let out = mutate(&msg, &[Mutation::CorruptMarker]).unwrap();
+    assert_eq!(&out[BGP..BGP + 16], &[0; 16]);
+    let Message::RouteMonitoring(rm) =
+        Message::from_octets(out.as_ref()).unwrap()
+    else {
+        panic!("expected Route Monitoring");
+    };
+    assert!(rm.bgp_update(SessionConfig::modern()).is_err());
+}
+
+#[test]
+fn truncate_is_applied_last() {
+    let msg = route_monitoring();
+    let out = mutate(
+        &msg,
+        &[
+            Mutation::Truncate(10),
+            Mutation::TrailingGarbage(Bytes::from(vec![0xde, 0xad])),
+        ],
+    )
+    .unwrap();
+    assert_eq!(out.len(), 10);
+    assert_eq!(bmp_length(&out), msg.len() + 2);
+}
+
+#[test]
+fn trailing_garbage_stays_inside_the_bmp_message() {
+    let msg = route_monitoring();
+    let out = mutate(
+        &msg,
+        &[Mutation::TrailingGarbage(Bytes::from_static(b"\xde\xad"))],
+    )
+    .unwrap();
+    assert_eq!(out.len(), msg.len() + 2);
+    assert_eq!(bmp_length(&out), out.len());
+    assert_eq!(bgp_length(&out), bgp_length(&msg));
+    assert!(out.ends_with(&[0xde, 0xad]));
+}
+
+#[test]
+fn duplicate_attribute_keeps_lengths_consistent() {
+    let msg = route_monitoring();
+    let out = mutate(
+        &msg,
+        &[Mutation::DuplicateAttribute(PathAttributeType::AsPath)],
+    )
+    .unwrap();
+    assert!(out.len() > msg.len());
+    assert_eq!(bmp_length(&out), out.len());
+    assert_eq!(bgp_length(&out), out.len() - BGP);
+
+    // The BMP message itself is still well-formed.
+    assert!(Message::from_octets(out.as_ref()).is_ok());
+}
+
+#[test]
+fn attribute_flags() {
+    let msg = route_monitoring();
+    let out = mutate(
+        &msg,
+        &[Mutation::AttributeFlags(PathAttributeType::Origin, 0x80)],
+    )
+    .unwrap();
+    assert_eq!(out.len(), msg.len());
+    assert_eq!(
+        out.iter().zip(msg.iter()).filter(|(a, b)| a != b).count(),
+        1
+    );
+}
+
+#[test]
+fn prefix_length_out_of_range() {
+    let msg = route_monitoring();
+    let out = mutate(&msg, &[Mutation::PrefixLength(33)]).unwrap();
+    let Message::RouteMonitoring(rm) =
+        Message::from_octets(out.as_ref()).unwrap()
+    else {
+        panic!("expected Route Monitoring");
+    };
+    let update = rm.bgp_update(SessionConfig::modern());
+    assert!(update
+        .map(|update| update.unicast_announcements_vec().is_err())
+        .unwrap_or(true));
+}
+
+#[test]
+fn errors() {
+    let msg = route_monitoring();
+    assert_eq!(
+        mutate(
+            &msg,
+            &[Mutation::DuplicateAttribute(
+                PathAttributeType::MultiExitDisc
+            )]
+        ),
+        Err(MutateError::AttributeNotFound(
+            PathAttributeType::MultiExitDisc
+        ))
+    );
+
+    let initiation = Initiation::new().build().unwrap();
+    assert!(matches!(
+        mutate(&initiation, &[Mutation::CorruptMarker]),
+        Err(MutateError::NoBgpMessage(_))
+    ));
+    // Mutations that do not need a BGP message still apply.
+    assert!(mutate(&initiation, &[Mutation::Version(2)]).is_ok());
+
+    let pph =
+        PerPeerHeader::new("10.0.0.1".parse().unwrap(), Asn::from_u32(65001));
+    let peer_down = PeerDown::new(pph).build().unwrap().0;
+    assert!(matches!(
+        mutate(&peer_down, &[Mutation::BgpLength(0)]),
+        Err(MutateError::NoBgpMessage(_))
+    ));
+}
+
+#[test]
+fn parse_and_display() {
+    for s in [
+        "bmp_length=1",
+        "bgp_length=5",
+        "truncate=20",
+        "corrupt_marker",
+        "version=2",
+        "duplicate_attribute=2",
+        "attribute_flags=1:0x80",
+        "prefix_length=33",
+        "trailing_garbage=deadbeef",
+    ] {
+        let mutation: Mutation = s.parse().unwrap();
+        assert_eq!(mutation.to_string(), s);
+    }
+    assert!("bogus".parse::<Mutation>().is_err());
+    assert!("trailing_garbage=abc".parse::<Mutation>().is_err());
+}
+
+#[test]
+fn fix_lengths_route_monitoring() {
+    let msg = route_monitoring();
+    let broken =
+        mutate(&msg, &[Mutation::BmpLength(0), Mutation::BgpLength(0)])
+            .unwrap();
+    assert_eq!(fix_lengths(&broken).unwrap(), msg);
+}
+
+#[test]
+fn fix_lengths_peer_up() {
+    let pph =
+        PerPeerHeader::new("10.0.0.1".parse().unwrap(), Asn::from_u32(65001))
+            .timestamp(Timestamp::try_new(1_700_000_000, 0).unwrap());
+    let msg = PeerUp::new(pph)
+        .sent_open(Open::new(65002, 1).eor_capable(true))
+        .information(InformationTlvType::String, "hi")
+        .build()
+        .unwrap()
+        .0;
+
+    // Zero the BMP length and the lengths of both OPENs.
+    let mut broken = msg.to_vec();
+    broken[1..5].fill(0);
+    let sent = 6 + 42 + 20;
+    let sent_len = usize::from(u16::from_be_bytes([
+        broken[sent + 16],
+        broken[sent + 17],
+    ]));
+    broken[sent + 16..sent + 18].fill(0);
+    broken[sent + sent_len + 16..sent + sent_len + 18].fill(0);
+
+    assert_eq!(fix_lengths(&broken).unwrap(), msg);
+}
+
+#[test]
+fn fix_lengths_without_bgp_message() {
+    let msg = Initiation::new().build().unwrap();
+    let broken = mutate(&msg, &[Mutation::BmpLength(1)]).unwrap();
+    assert_eq!(fix_lengths(&broken).unwrap(), msg);
+}
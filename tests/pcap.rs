@@ -0,0 +1,134 @@
+//! Parses the output of `routes::pcap` and checks that the synthetic TCP
+//! session carries the messages with consistent sequence numbers and valid
+//! checksums.
+
+use routes::bmp::clock::Timestamp;
+use routes::pcap::PcapWriter;
+
+const TIMESTAMP: Timestamp = Timestamp {
+    secs: 1_700_000_000,
+    micros: 123_456,
+};
+
+struct Packet {
+    ts: Timestamp,
+    ip: Vec<u8>,
+}
+
+impl Packet {
+    fn tcp(&self) -> &[u8] {
+        &self.ip[20..]
+    }
+
+    fn flags(&self) -> u8 {
+        self.tcp()[13]
+    }
+
+    fn seq(&self) -> u32 {
+        u32::from_be_bytes(self.tcp()[4..8].try_into().unwrap())
+    }
+
+    fn ack(&self) -> u32 {
+        u32::from_be_bytes(self.tcp()[8..12].try_into().unwrap())
+    }
+
+    fn dst_port(&self) -> u16 {
+        u16::from_be_bytes(self.tcp()[2..4].try_into().unwrap())
+    }
+
+    fn payload(&self) -> &[u8] {
+        &self.tcp()[20..]
+    }
+}
+
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum = 0u32;
+    for chunk in data.chunks(2) {
+        let hi = chunk[0];
+        let lo = chunk.get(1).copied().unwrap_or(0);
+        sum += u32::from(u16::from_be_bytes([hi, lo]));
+    }
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+fn parse(file: &[u8]) -> Vec<Packet> {
+    assert_eq!(&file[..4], &0xa1b2_c3d4u32.to_le_bytes());
+    assert_eq!(u32::from_le_bytes(file[20..24].try_into().unwrap()), 1);
+    let mut rest = &file[24..];
+    let mut packets = vec![];
+    while !rest.is_empty() {
+        let field = |pos: usize| {
+            u32::from_le_bytes(rest[pos..pos + 4].try_into().unwrap())
+        };
+        let ts = Timestamp::try_new(field(0), field(4)).unwrap();
+        let len = field(8) as usize;
+        assert_eq!(field(12) as usize, len);
+        let frame = &rest[16..16 + len];
+        assert_eq!(&frame[12..14], &[0x08, 0x00]);
+        let ip = frame[14..].to_vec();
+        assert_eq!(usize::from(u16::from_be_bytes([ip[2], ip[3]])), ip.len());
+        assert_eq!(checksum(&ip[..20]), 0);
+
+        let mut pseudo = ip[12..20].to_vec();
+        pseudo.extend_from_slice(&[0, 6]);
+        pseudo.extend_from_slice(&((ip.len() - 20) as u16).to_be_bytes());
+        pseudo.extend_from_slice(&ip[20..]);
+        assert_eq!(checksum(&pseudo), 0);
+
+        packets.push(Packet { ts, ip });
+        rest = &rest[16 + len..];
+    }
+    packets
+}
+
+#[test]
+fn session() {
+    let mut writer = PcapWriter::new(vec![]).unwrap();
+    writer.write_message(b"first", TIMESTAMP).unwrap();
+    writer.write_message(b"second!", TIMESTAMP).unwrap();
+    writer.close(TIMESTAMP).unwrap();
+    let packets = parse(&writer.into_inner());
+
+    let flags: Vec<_> = packets.iter().map(Packet::flags).collect();
+    assert_eq!(flags, [0x02, 0x12, 0x10, 0x18, 0x18, 0x11]);
+    assert!(packets.iter().all(|p| p.ts == TIMESTAMP));
+
+    let [syn, syn_ack, ack, first, second, fin] = &packets[..] else {
+        unreachable!()
+    };
+    assert_eq!(syn.dst_port(), 11019);
+    assert_eq!(syn_ack.ack(), syn.seq() + 1);
+    assert_eq!(ack.seq(), syn.seq() + 1);
+    assert_eq!(ack.ack(), syn_ack.seq() + 1);
+    assert_eq!(first.seq(), ack.seq());
+    assert_eq!(first.payload(), b"first");
+    assert_eq!(second.seq(), first.seq() + 5);
+    assert_eq!(second.payload(), b"second!");
+    assert_eq!(fin.seq(), second.seq() + 7);
+}
+
+#[test]
+fn large_message_is_segmented() {
+    let msg: Vec<u8> = (0..100_000u32).map(|i| i as u8).collect();
+    let mut writer = PcapWriter::new(vec![]).unwrap();
+    writer.write_message(&msg, TIMESTAMP).unwrap();
+    let packets = parse(&writer.into_inner());
+
+    let data = &packets[3..];
+    assert_eq!(data.len(), 2);
+    assert_eq!(
+        data[1].seq(),
+        data[0].seq() + data[0].payload().len() as u32
+    );
+    assert_eq!([data[0].payload(), data[1].payload()].concat(), msg);
+}
+
+#[test]
+fn empty_session() {
+    let mut writer = PcapWriter::new(vec![]).unwrap();
+    writer.close(TIMESTAMP).unwrap();
+    assert_eq!(writer.into_inner().len(), 24);
+}
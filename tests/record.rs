@@ -0,0 +1,68 @@
+//! Checks that `routes::bmp::record` captures messages so that they can be
+//! read back unchanged.
+
+use routes::bmp::builder::{Initiation, Termination};
+use routes::bmp::clock::Timestamp;
+use routes::bmp::record::{RecordFormat, Recorder};
+use routes::bmp::replay::MrtToBmp;
+use routes::mrt::{MrtBody, MrtReader};
+
+const SENT: Timestamp = Timestamp {
+    secs: 1_700_000_000,
+    micros: 123_456,
+};
+
+fn messages() -> Vec<Vec<u8>> {
+    vec![
+        Initiation::new().build().unwrap().to_vec(),
+        Termination::new().build().unwrap().to_vec(),
+    ]
+}
+
+fn record(format: RecordFormat) -> Vec<u8> {
+    let mut recorder = Recorder::new(vec![], format).unwrap();
+    for msg in messages() {
+        recorder.record(&msg, SENT).unwrap();
+    }
+    recorder.into_inner()
+}
+
+#[test]
+fn raw() {
+    assert_eq!(record(RecordFormat::Raw), messages().concat());
+}
+
+#[test]
+fn mrt() {
+    let capture = record(RecordFormat::Mrt);
+    let records: Vec<_> = MrtReader::new(capture.as_slice())
+        .collect::<Result<_, _>>()
+        .unwrap();
+    assert_eq!(records.len(), 2);
+    for (record, msg) in records.iter().zip(messages()) {
+        assert_eq!(record.timestamp, SENT);
+        assert_eq!(
+            record.body,
+            MrtBody::Bmp {
+                message: msg.into()
+            }
+        );
+    }
+
+    // Replaying the capture sends the messages unchanged.
+    let mut converter = MrtToBmp::new();
+    let replayed: Vec<_> = records
+        .iter()
+        .flat_map(|record| converter.convert(record).unwrap())
+        .map(|(bytes, _)| bytes.to_vec())
+        .collect();
+    assert_eq!(replayed, messages());
+}
+
+#[test]
+fn parse_format() {
+    for s in ["raw", "mrt", "pcap"] {
+        assert_eq!(s.parse::<RecordFormat>().unwrap().to_string(), s);
+    }
+    assert!("pcapng".parse::<RecordFormat>().is_err());
+}
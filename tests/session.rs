@@ -0,0 +1,367 @@
+//! Feeds messages built with `routes::bmp::builder` to
+//! `routes::bmp::session` and checks the tracked peers and routes, the
+//! sequence checks, and that the replayed state parses back into what was
+//! announced.
+
+use std::collections::BTreeSet;
+use std::net::IpAddr;
+
+use bytes::Bytes;
+use routecore::addr::Prefix;
+use routecore::asn::Asn;
+use routecore::bgp::message::update::SessionConfig;
+use routecore::bmp::message::{Message, MessageType};
+
+use routes::bmp::builder::{
+    Initiation, PeerDown, PeerUp, RouteMonitoring, Termination,
+};
+use routes::bmp::clock::Timestamp;
+use routes::bmp::encode::{Open, PerPeerHeader};
+use routes::bmp::session::{OpenInfo, SequenceWarning, Session};
+
+const SENT: Timestamp = Timestamp {
+    secs: 1_700_000_000,
+    micros: 0,
+};
+const REPLAYED: Timestamp = Timestamp {
+    secs: 1_700_000_100,
+    micros: 0,
+};
+
+fn pph(addr: &str, asn: u32) -> PerPeerHeader {
+    PerPeerHeader::new(addr.parse().unwrap(), Asn::from_u32(asn))
+        .timestamp(SENT)
+}
+
+fn peer_up(addr: &str, asn: u32) -> Bytes {
+    PeerUp::new(pph(addr, asn))
+        .sent_open(Open::new(65000, 0x0a00_00fe))
+        .received_open(Open::new(asn as u16, 0x0a00_0001))
+        .build()
+        .unwrap()
+        .0
+}
+
+fn route_monitoring(
+    addr: &str,
+    asn: u32,
+    withdraw: Option<&str>,
+    announce: Option<&str>,
+) -> Bytes {
+    let mut rm = RouteMonitoring::new(pph(addr, asn));
+    if let Some(withdraw) = withdraw {
+        rm = rm.withdraw(withdraw.parse().unwrap());
+    }
+    if let Some(announce) = announce {
+        rm = rm.announce(announce.parse().unwrap());
+    }
+    rm.build().unwrap().0
+}
+
+fn session(msgs: &[Bytes]) -> Session {
+    let mut session = Session::new();
+    for msg in msgs {
+        session.observe(msg);
+    }
+    session
+}
+
+fn types(msgs: &[Bytes]) -> Vec<MessageType> {
+    msgs.iter()
+        .map(|msg| Message::from_octets(msg.as_ref()).unwrap().msg_type())
+        .collect()
+}
+
+/// Returns the prefixes announced in the Route Monitoring messages and the
+/// number of End-of-RIB markers.
+fn announced(msgs: &[Bytes]) -> (BTreeSet<Prefix>, usize) {
+    let mut prefixes = BTreeSet::new();
+    let mut eors = 0;
+    for msg in msgs {
+        let Message::RouteMonitoring(rm) =
+            Message::from_octets(msg.as_ref()).unwrap()
+        else {
+            continue;
+        };
+        assert_eq!(
+            rm.per_peer_header().timestamp().timestamp(),
+            1_700_000_100
+        );
+        let update = rm.bgp_update(SessionConfig::modern()).unwrap();
+        if update.is_eor().unwrap().is_some() {
+            eors += 1;
+        }
+        for nlri in update.unicast_announcements_vec().unwrap() {
+            prefixes.insert(nlri.prefix);
+        }
+    }
+    (prefixes, eors)
+}
+
+fn prefixes(s: &str) -> BTreeSet<Prefix> {
+    s.split(',').map(|p| p.parse().unwrap()).collect()
+}
+
+#[test]
+fn replay_state() {
+    let initiation = Initiation::new().sys_name("test").build().unwrap();
+    let session = session(&[
+        initiation.clone(),
+        peer_up("10.0.0.1", 65001),
+        route_monitoring(
+            "10.0.0.1",
+            65001,
+            None,
+            Some("i [65001] 10.0.0.1 none 10.1.0.0/16,10.2.0.0/16"),
+        ),
+        route_monitoring(
+            "10.0.0.1",
+            65001,
+            None,
+            Some("i [65001,65010] 10.0.0.1 none 10.3.0.0/16"),
+        ),
+        route_monitoring("10.0.0.1", 65001, Some("10.2.0.0/16"), None),
+        peer_up("2001:db8::2", 65002),
+        route_monitoring(
+            "2001:db8::2",
+            65002,
+            None,
+            Some("i [65002] 2001:db8::2 none 2001:db8:1::/48"),
+        ),
+    ]);
+    assert_eq!(session.route_count(), 3);
+
+    let msgs = session.replay(REPLAYED).unwrap();
+    assert_eq!(msgs[0], initiation);
+    use MessageType::*;
+    assert_eq!(
+        types(&msgs),
+        [
+            InitiationMessage,
+            PeerUpNotification,
+            // One UPDATE per set of attributes and an End-of-RIB.
+            RouteMonitoring,
+            RouteMonitoring,
+            RouteMonitoring,
+            PeerUpNotification,
+            RouteMonitoring,
+            RouteMonitoring,
+        ]
+    );
+    let (announced, eors) = announced(&msgs);
+    assert_eq!(
+        announced,
+        prefixes("10.1.0.0/16,10.3.0.0/16,2001:db8:1::/48")
+    );
+    assert_eq!(eors, 2);
+}
+
+#[test]
+fn peer_down_and_termination() {
+    let mut session = session(&[
+        Initiation::new().build().unwrap(),
+        peer_up("10.0.0.1", 65001),
+        route_monitoring(
+            "10.0.0.1",
+            65001,
+            None,
+            Some("i [65001] 10.0.0.1 none 10.1.0.0/16"),
+        ),
+        peer_up("10.0.0.2", 65002),
+        PeerDown::new(pph("10.0.0.1", 65001)).build().unwrap().0,
+    ]);
+    assert_eq!(session.route_count(), 0);
+    assert_eq!(
+        types(&session.replay(REPLAYED).unwrap()),
+        [
+            MessageType::InitiationMessage,
+            MessageType::PeerUpNotification
+        ]
+    );
+
+    session.observe(&Termination::new().build().unwrap());
+    assert!(session.is_empty());
+    assert!(session.replay(REPLAYED).unwrap().is_empty());
+}
+
+#[test]
+fn packing() {
+    // Enough /24s with the same attributes to need several UPDATEs, sent
+    // in messages of 500 prefixes.
+    let mut msgs = vec![peer_up("10.0.0.1", 65001)];
+    for chunk in 0..4 {
+        let nlri: Vec<String> = (chunk * 500..(chunk + 1) * 500)
+            .map(|i| format!("10.{}.{}.0/24", i / 256, i % 256))
+            .collect();
+        msgs.push(route_monitoring(
+            "10.0.0.1",
+            65001,
+            None,
+            Some(&format!("i [65001] 10.0.0.1 none {}", nlri.join(","))),
+        ));
+    }
+    let session = session(&msgs);
+    let msgs = session.replay(REPLAYED).unwrap();
+    // The Peer Up, 2000 prefixes of 4 bytes in UPDATEs of at most 4096
+    // bytes, and an End-of-RIB.
+    assert_eq!(msgs.len(), 1 + 2 + 1);
+    for msg in &msgs[1..] {
+        assert!(msg.len() <= 6 + 42 + 4096);
+    }
+    let (announced, eors) = announced(&msgs);
+    assert_eq!(announced.len(), 2000);
+    assert_eq!(eors, 1);
+}
+
+#[test]
+fn unparseable_messages_are_ignored() {
+    let mut session = Session::new();
+    session.observe(&[]);
+    session.observe(&[3, 0, 0, 0, 6, 0]);
+    let mut rm = route_monitoring(
+        "10.0.0.1",
+        65001,
+        None,
+        Some("i [65001] 10.0.0.1 none 10.1.0.0/16"),
+    )
+    .to_vec();
+    rm.truncate(rm.len() - 1);
+    session.observe(&rm);
+    assert_eq!(session.route_count(), 0);
+}
+
+#[test]
+fn peers_and_routes() {
+    let post_policy =
+        RouteMonitoring::new(pph("10.0.0.1", 65001).post_policy(true))
+            .announce(
+                "e [65001,65010] 10.0.0.1 65001:1 10.1.0.0/16"
+                    .parse()
+                    .unwrap(),
+            )
+            .build()
+            .unwrap()
+            .0;
+    let session = session(&[
+        Initiation::new().build().unwrap(),
+        PeerUp::new(pph("10.0.0.1", 65001))
+            .local("10.0.0.254".parse().unwrap(), 179)
+            .remote_port(50000)
+            .sent_open(Open::new(65000, 0x0a00_00fe).hold_time(90))
+            .received_open(Open::new(65001, 0x0a00_0001).hold_time(180))
+            .build()
+            .unwrap()
+            .0,
+        route_monitoring(
+            "10.0.0.1",
+            65001,
+            None,
+            Some("i [65001] 10.0.0.1 none 10.1.0.0/16,10.2.0.0/16"),
+        ),
+        post_policy,
+        // Routes for a peer that is not up are tracked all the same.
+        route_monitoring(
+            "10.0.0.2",
+            65002,
+            None,
+            Some("? [65002] 10.0.0.2 none 10.3.0.0/16"),
+        ),
+    ]);
+    assert!(session.is_initiated());
+
+    let [up, not_up] = session.peers() else {
+        panic!("expected two peers");
+    };
+    assert_eq!(up.address(), "10.0.0.1".parse::<IpAddr>().unwrap());
+    assert_eq!(up.asn(), Asn::from_u32(65001));
+    assert!(up.is_up());
+    assert_eq!(up.up_since(), Some(SENT));
+    assert_eq!(
+        up.local(),
+        Some(("10.0.0.254".parse().unwrap(), 179, 50000))
+    );
+    assert_eq!(
+        up.sent_open(),
+        Some(OpenInfo {
+            asn: Asn::from_u32(65000),
+            hold_time: 90,
+            bgp_id: "10.0.0.254".parse().unwrap(),
+        })
+    );
+    assert_eq!(up.received_open().unwrap().hold_time, 180);
+    assert!(!not_up.is_up());
+    assert_eq!(not_up.up_since(), None);
+
+    let [pre, post] = up.ribs() else {
+        panic!("expected a pre- and a post-policy RIB");
+    };
+    assert!(!pre.is_post_policy());
+    assert!(post.is_post_policy());
+    let routes: Vec<String> = pre
+        .routes()
+        .chain(post.routes())
+        .map(|(prefix, route)| format!("{prefix} {route}"))
+        .collect();
+    assert_eq!(
+        routes,
+        [
+            "10.1.0.0/16 i [65001] 10.0.0.1 none",
+            "10.2.0.0/16 i [65001] 10.0.0.1 none",
+            "10.1.0.0/16 e [65001,65010] 10.0.0.1 65001:1",
+        ]
+    );
+    assert_eq!(
+        not_up.ribs()[0].routes().next().unwrap().1.to_string(),
+        "? [65002] 10.0.0.2 none"
+    );
+}
+
+#[test]
+fn sequence_checks() {
+    use MessageType::*;
+    let rm = route_monitoring(
+        "10.0.0.1",
+        65001,
+        None,
+        Some("i [65001] 10.0.0.1 none 10.1.0.0/16"),
+    );
+    let peer_down = PeerDown::new(pph("10.0.0.1", 65001)).build().unwrap().0;
+    let addr: IpAddr = "10.0.0.1".parse().unwrap();
+
+    let mut session = Session::new();
+    assert_eq!(
+        session.check(&rm),
+        [
+            SequenceWarning::NotInitiated(RouteMonitoring),
+            SequenceWarning::PeerNotUp(RouteMonitoring, addr),
+        ]
+    );
+
+    let initiation = Initiation::new().build().unwrap();
+    assert!(session.check(&initiation).is_empty());
+    session.observe(&initiation);
+    assert_eq!(
+        session.check(&initiation),
+        [SequenceWarning::AlreadyInitiated]
+    );
+    assert_eq!(
+        session.check(&peer_down),
+        [SequenceWarning::PeerNotUp(PeerDownNotification, addr)]
+    );
+
+    let peer_up = peer_up("10.0.0.1", 65001);
+    assert!(session.check(&peer_up).is_empty());
+    session.observe(&peer_up);
+    assert_eq!(
+        session.check(&peer_up),
+        [SequenceWarning::PeerAlreadyUp(addr)]
+    );
+    assert!(session.check(&rm).is_empty());
+    assert!(session.check(&peer_down).is_empty());
+
+    // The checks are only advice, the message is still tracked.
+    session.observe(&peer_down);
+    session.observe(&rm);
+    assert_eq!(session.route_count(), 1);
+    assert!(!session.peers()[0].is_up());
+}
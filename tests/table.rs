@@ -0,0 +1,149 @@
+//! Generates synthetic tables with `routes::bmp::table` and checks that they
+//! are reproducible, look like the Internet table and encode into Route
+//! Monitoring messages.
+
+use std::collections::HashSet;
+use std::net::IpAddr;
+
+use routecore::asn::Asn;
+use routecore::bmp::message::{Message, MessageType};
+
+use routes::bmp::encode::{mk_route_monitoring_msg, PerPeerHeader, Prefixes};
+use routes::bmp::table::{Table, TableConfig};
+
+fn generate(ipv4_prefixes: usize, ipv6_prefixes: usize, seed: u64) -> Table {
+    Table::generate(&TableConfig {
+        ipv4_prefixes,
+        ipv6_prefixes,
+        seed,
+    })
+}
+
+/// Returns the share of the routes matching `f` in percent.
+fn share(
+    table: &Table,
+    f: impl Fn(&routes::bmp::table::Route) -> bool,
+) -> f64 {
+    let matching = table.routes().iter().filter(|route| f(route)).count();
+    100.0 * matching as f64 / table.len() as f64
+}
+
+#[test]
+fn reproducible() {
+    let a = generate(2000, 500, 1);
+    let b = generate(2000, 500, 1);
+    let c = generate(2000, 500, 2);
+    let prefixes = |table: &Table| {
+        table.routes().iter().map(|r| r.prefix).collect::<Vec<_>>()
+    };
+    assert_eq!(prefixes(&a), prefixes(&b));
+    assert!(a
+        .routes()
+        .iter()
+        .zip(b.routes())
+        .all(|(a, b)| a.attributes == b.attributes));
+    assert_ne!(prefixes(&a), prefixes(&c));
+}
+
+#[test]
+fn looks_like_the_internet() {
+    let table = generate(20_000, 5000, 0);
+    assert_eq!(table.len(), 25_000);
+    let unique: HashSet<_> =
+        table.routes().iter().map(|r| r.prefix).collect();
+    assert_eq!(unique.len(), 25_000);
+    assert!(table.routes().windows(2).all(|w| w[0].prefix < w[1].prefix));
+    assert_eq!(
+        table.routes().iter().filter(|r| r.prefix.is_v4()).count(),
+        20_000
+    );
+
+    // Mostly /24s and /48s.
+    let v4_24 = share(&table, |r| r.prefix.is_v4() && r.prefix.len() == 24);
+    assert!((45.0..60.0).contains(&v4_24), "{v4_24}% IPv4 /24s");
+    let v6_48 = share(&table, |r| r.prefix.is_v6() && r.prefix.len() == 48);
+    assert!((7.0..13.0).contains(&v6_48), "{v6_48}% IPv6 /48s");
+
+    // No private, shared, loopback or documentation space.
+    for route in table.routes() {
+        let bogon = match route.prefix.addr() {
+            IpAddr::V4(addr) => {
+                addr.is_private()
+                    || addr.is_loopback()
+                    || addr.is_documentation()
+                    || addr.octets()[0] >= 224
+            }
+            IpAddr::V6(addr) => {
+                addr.segments()[0] & 0xe000 != 0x2000
+                    || addr.segments()[..2] == [0x2001, 0x0db8]
+            }
+        };
+        assert!(!bogon, "{}", route.prefix);
+    }
+
+    // Paths are mostly 3 to 5 hops including the peer.
+    let short =
+        share(&table, |r| (2..=4).contains(&r.attributes.as_path.len()));
+    assert!(short > 60.0, "{short}% of paths with 2 to 4 hops");
+
+    // A few origins announce many prefixes, most origins a few.
+    let origins: HashSet<Asn> = table
+        .routes()
+        .iter()
+        .map(|r| r.attributes.origin_asn())
+        .collect();
+    assert!(origins.len() > 500 && origins.len() < 2100);
+    assert!(table.attribute_set_count() > origins.len());
+    assert!(table.attribute_set_count() <= 2 * origins.len());
+
+    // About half of the paths carry communities.
+    let communities = share(&table, |r| !r.attributes.communities.is_empty());
+    assert!((35.0..75.0).contains(&communities), "{communities}%");
+}
+
+#[test]
+fn grouped_by_attributes() {
+    let table = generate(3000, 1000, 3);
+    let groups = table.by_attributes();
+    assert_eq!(groups.iter().map(|(_, p)| p.len()).sum::<usize>(), 4000);
+    for (attributes, prefixes) in &groups {
+        assert!(prefixes.iter().all(|p| p.is_v4() == prefixes[0].is_v4()));
+        assert!(table
+            .routes()
+            .iter()
+            .filter(|r| prefixes.contains(&r.prefix))
+            .all(|r| &r.attributes == attributes));
+    }
+}
+
+#[test]
+fn encodes_for_each_peer() {
+    let table = generate(500, 500, 4);
+    for idx in 1..=3u8 {
+        let peer_as = Asn::from_u32(64500 + u32::from(idx));
+        let v4_next_hop = IpAddr::from([10, 0, 0, idx]);
+        let v6_next_hop =
+            IpAddr::from([0xfd00, 0, 0, 0, 0, 0, 0, idx.into()]);
+        let pph = PerPeerHeader::new(v4_next_hop, peer_as);
+        for route in table.routes() {
+            let next_hop = if route.prefix.is_v4() {
+                v4_next_hop
+            } else {
+                v6_next_hop
+            };
+            let (bytes, warnings) = mk_route_monitoring_msg(
+                &pph,
+                &Prefixes::default(),
+                &route.announcements(peer_as, next_hop),
+                &[],
+            )
+            .unwrap();
+            assert!(warnings.is_empty(), "{warnings:?}");
+            let msg = Message::from_octets(bytes).unwrap();
+            assert_eq!(
+                msg.common_header().msg_type(),
+                MessageType::RouteMonitoring
+            );
+        }
+    }
+}